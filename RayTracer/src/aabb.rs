@@ -77,8 +77,14 @@ impl Aabb {
     }
 
     pub fn hit(&self, r: &Ray, ray_t: Interval) -> bool {
+        self.clip(r, ray_t).is_some()
+    }
+
+    // the part of ray_t inside the box, None if the ray misses it
+    pub fn clip(&self, r: &Ray, ray_t: Interval) -> Option<Interval> {
         let ray_origin = r.origin();
         let ray_direction = r.direction();
+        let mut ray_t = ray_t;
         
         for i in 0..3 {
             let ax = self.axis_interval(i);
@@ -87,14 +93,39 @@ impl Aabb {
             let t0 = (ax.tmin - ray_origin.lp(i as u8)) * adinv;
             let t1 = (ax.tmax - ray_origin.lp(i as u8)) * adinv;
 
-            let lef = fmax(ray_t.tmin, fmin(t0, t1));
-            let rig = fmin(ray_t.tmax, fmax(t0, t1));
+            // the slabs have to overlap, so the interval shrinks axis by axis
+            ray_t.tmin = fmax(ray_t.tmin, fmin(t0, t1));
+            ray_t.tmax = fmin(ray_t.tmax, fmax(t0, t1));
 
-            if rig <= lef {
-                return false
+            if ray_t.tmax <= ray_t.tmin {
+                return None
             }
         }
-        true 
+        Some(ray_t)
+    }
+
+    // infinite extents are clamped so that SAH costs stay finite
+    pub fn surface_area(&self) -> f64 {
+        let dx = self.x.size().clamp(0.0, 1e30);
+        let dy = self.y.size().clamp(0.0, 1e30);
+        let dz = self.z.size().clamp(0.0, 1e30);
+        2.0 * (dx * dy + dy * dz + dz * dx)
+    }
+
+    // center of the box, an unbounded side falls back to its finite end (or 0)
+    pub fn centroid(&self) -> Vec3 {
+        let center = |interval: &Interval| {
+            if interval.tmin.is_finite() && interval.tmax.is_finite() {
+                (interval.tmin + interval.tmax) / 2.0
+            } else if interval.tmin.is_finite() {
+                interval.tmin
+            } else if interval.tmax.is_finite() {
+                interval.tmax
+            } else {
+                0.0
+            }
+        };
+        Vec3::new(center(&self.x), center(&self.y), center(&self.z))
     }
 
     pub fn pad_to_minimums(&mut self) -> Self {
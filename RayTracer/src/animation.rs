@@ -0,0 +1,401 @@
+use crate::aabb::*;
+use crate::camera::*;
+use crate::hitable::*;
+use crate::instance::*;
+use crate::interval::*;
+use crate::material::*;
+use crate::ray::*;
+use crate::texture::*;
+use crate::transform::*;
+use crate::utils::*;
+use crate::vec3::*;
+
+use std::fs::File;
+use std::path::Path;
+use std::sync::Arc;
+
+// values that can be keyframed
+pub trait Animatable: Copy {
+    fn lerp(a: Self, b: Self, t: f64) -> Self;
+}
+
+impl Animatable for f64 {
+    fn lerp(a: f64, b: f64, t: f64) -> f64 {
+        a + (b - a) * t
+    }
+}
+
+impl Animatable for Vec3 {
+    fn lerp(a: Vec3, b: Vec3, t: f64) -> Vec3 {
+        a * (1.0 - t) + b * t
+    }
+}
+
+// rotation of angle degrees around axis; the angle is interpolated on its own, so keys
+// 0 and 720 spin twice, and the axis turns from one key to the next
+#[derive(Copy, Clone, Debug)]
+pub struct AxisAngle {
+    pub axis: Vec3,
+    pub angle: f64,
+}
+
+impl AxisAngle {
+    pub fn new(axis: Vec3, angle: f64) -> Self {
+        Self {
+            axis: unit_vec(axis),
+            angle,
+        }
+    }
+
+    pub fn around_y(angle: f64) -> Self {
+        Self::new(Vec3::new(0.0, 1.0, 0.0), angle)
+    }
+
+    pub fn transform(&self) -> Transform {
+        Transform::rotate(self.axis, self.angle)
+    }
+}
+
+impl Animatable for AxisAngle {
+    fn lerp(a: AxisAngle, b: AxisAngle, t: f64) -> AxisAngle {
+        let axis = Vec3::lerp(a.axis, b.axis, t);
+        AxisAngle {
+            // opposite axes have no way between them, keep the nearest one
+            axis: if axis.near_zero() { if t < 0.5 { a.axis } else { b.axis } } else { unit_vec(axis) },
+            angle: f64::lerp(a.angle, b.angle, t),
+        }
+    }
+}
+
+#[derive(Copy, Clone)]
+pub enum Interpolation {
+    Linear,
+    // the segment leaving a Bezier key is a cubic Bezier curve through
+    // (value, out_handle, next.in_handle, next.value)
+    Bezier,
+}
+
+#[derive(Copy, Clone)]
+pub struct Keyframe<T: Animatable> {
+    pub time: f64,
+    pub value: T,
+    pub interpolation: Interpolation,
+    pub in_handle: T,
+    pub out_handle: T,
+}
+
+impl<T: Animatable> Keyframe<T> {
+    pub fn new_linear(time: f64, value: T) -> Self {
+        Self {
+            time,
+            value,
+            interpolation: Interpolation::Linear,
+            in_handle: value,
+            out_handle: value,
+        }
+    }
+
+    pub fn new_bezier(time: f64, value: T, in_handle: T, out_handle: T) -> Self {
+        Self {
+            time,
+            value,
+            interpolation: Interpolation::Bezier,
+            in_handle,
+            out_handle,
+        }
+    }
+}
+
+// piecewise curve through keyframes, constant before the first and after the last key
+#[derive(Clone)]
+pub struct AnimationCurve<T: Animatable> {
+    pub keys: Vec<Keyframe<T>>,
+}
+
+impl<T: Animatable> AnimationCurve<T> {
+    pub fn new(mut keys: Vec<Keyframe<T>>) -> Self {
+        assert!(!keys.is_empty(), "an animation curve needs at least one keyframe");
+        keys.sort_by(|a, b| a.time.total_cmp(&b.time));
+        Self { keys }
+    }
+
+    pub fn new_constant(value: T) -> Self {
+        Self::new(vec![Keyframe::new_linear(0.0, value)])
+    }
+
+    pub fn new_linear(t0: f64, v0: T, t1: f64, v1: T) -> Self {
+        Self::new(vec![Keyframe::new_linear(t0, v0), Keyframe::new_linear(t1, v1)])
+    }
+
+    pub fn evaluate(&self, time: f64) -> T {
+        let first = &self.keys[0];
+        let last = &self.keys[self.keys.len() - 1];
+        if time <= first.time {
+            return first.value;
+        }
+        if time >= last.time {
+            return last.value;
+        }
+
+        let next = self.keys.partition_point(|k| k.time <= time);
+        let (k0, k1) = (&self.keys[next - 1], &self.keys[next]);
+        let t = (time - k0.time) / (k1.time - k0.time);
+        match k0.interpolation {
+            Interpolation::Linear => T::lerp(k0.value, k1.value, t),
+            Interpolation::Bezier => {
+                // de Casteljau
+                let a = T::lerp(k0.value, k0.out_handle, t);
+                let b = T::lerp(k0.out_handle, k1.in_handle, t);
+                let c = T::lerp(k1.in_handle, k1.value, t);
+                let ab = T::lerp(a, b, t);
+                let bc = T::lerp(b, c, t);
+                T::lerp(ab, bc, t)
+            }
+        }
+    }
+}
+
+// keyframed camera parameters, everything else (resolution, filter, projection...) comes from a template
+#[derive(Clone)]
+pub struct CameraRig {
+    pub look_from: AnimationCurve<Vec3>,
+    pub look_at: AnimationCurve<Vec3>,
+    pub vfov: AnimationCurve<f64>,
+    pub defocus_angle: AnimationCurve<f64>,
+    pub focus_dist: AnimationCurve<f64>,
+}
+
+impl CameraRig {
+    // a rig that keeps every parameter of the template camera
+    pub fn new(template: &Camera) -> Self {
+        Self {
+            look_from: AnimationCurve::new_constant(template.look_from),
+            look_at: AnimationCurve::new_constant(template.look_at),
+            vfov: AnimationCurve::new_constant(template.vfov),
+            defocus_angle: AnimationCurve::new_constant(template.defocus_angle),
+            focus_dist: AnimationCurve::new_constant(template.focus_dist),
+        }
+    }
+
+    pub fn camera_at(&self, template: &Camera, time: f64) -> Camera {
+        let mut camera = Camera::new(
+            template.width,
+            template.height,
+            template.samples_per_pixel,
+            template.max_depth,
+            self.vfov.evaluate(time),
+            self.look_from.evaluate(time),
+            self.look_at.evaluate(time),
+            template.vup,
+            self.defocus_angle.evaluate(time),
+            self.focus_dist.evaluate(time),
+            template.background,
+        );
+        camera.set_filter(template.filter.clone());
+        camera.set_projection(template.projection.clone());
+        camera.set_shutter(template.shutter_open, template.shutter_close);
+        camera.set_exposure(template.exposure);
+        camera.set_aperture(template.aperture.clone());
+        camera.set_cat_eye_vignetting(template.cat_eye);
+        camera
+    }
+}
+
+// object scaled, rotated and then translated, all keyframed in seconds
+// the ray time (0..1 over one frame) is mapped to frame_start + time * frame_duration,
+// so moving objects get motion blur from the camera shutter
+pub struct AnimatedObject {
+    pub object: Arc<dyn Hittable + Send + Sync>,
+    pub translation: AnimationCurve<Vec3>,
+    pub rotation: AnimationCurve<AxisAngle>,
+    pub scale: AnimationCurve<Vec3>,
+    pub frame_start: f64,
+    pub frame_duration: f64,
+    pub bbox: Aabb,
+}
+
+// number of poses sampled over a frame to bound the moving object
+const BBOX_TIME_SAMPLES: usize = 16;
+
+impl AnimatedObject {
+    pub fn new(
+        object: Arc<dyn Hittable + Send + Sync>,
+        translation: AnimationCurve<Vec3>,
+        rotation: AnimationCurve<AxisAngle>,
+        scale: AnimationCurve<Vec3>,
+        frame_start: f64,
+        frame_duration: f64,
+    ) -> AnimatedObject {
+        let mut animated = AnimatedObject {
+            object,
+            translation,
+            rotation,
+            scale,
+            frame_start,
+            frame_duration,
+            bbox: Aabb::default(),
+        };
+        let object_bbox = animated.object.bounding_box();
+        let mut bbox = Aabb::default();
+        for i in 0..=BBOX_TIME_SAMPLES {
+            let time = frame_start + frame_duration * i as f64 / BBOX_TIME_SAMPLES as f64;
+            bbox = Aabb::new_from_bbox(bbox, animated.transform_at(time).apply_bbox(&object_bbox));
+        }
+        // the pose may still move a little between two samples
+        let margin = bbox.x.size().max(bbox.y.size()).max(bbox.z.size()) / BBOX_TIME_SAMPLES as f64;
+        animated.bbox = Aabb::new(bbox.x.expand(margin), bbox.y.expand(margin), bbox.z.expand(margin));
+        animated
+    }
+
+    fn transform_at(&self, time: f64) -> Transform {
+        Transform::translate(self.translation.evaluate(time))
+            * self.rotation.evaluate(time).transform()
+            * Transform::scale(self.scale.evaluate(time))
+    }
+}
+
+impl Hittable for AnimatedObject {
+    fn hit(&self, ray: &Ray, ray_t: Interval) -> Option<HitRecord<'_>> {
+        let time = self.frame_start + ray.time() * self.frame_duration;
+        hit_transformed(self.object.as_ref(), &self.transform_at(time), ray, ray_t)
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.bbox
+    }
+
+    fn instancing(self) -> Arc<dyn Hittable + Send + Sync> {
+        Arc::new(self)
+    }
+}
+
+// a texture tinted by a keyframed color, for material colors that change over time
+// textures are looked up without the ray, so the tint is the one at the middle of the frame
+pub struct AnimatedTexture {
+    pub tex: Arc<dyn TextureTrait + Send + Sync>,
+    pub tint: AnimationCurve<Vec3>,
+    time: f64,
+}
+
+impl AnimatedTexture {
+    pub fn new(tex: Arc<dyn TextureTrait + Send + Sync>, tint: AnimationCurve<Vec3>, frame_start: f64, frame_duration: f64) -> Self {
+        Self {
+            tex,
+            tint,
+            time: frame_start + 0.5 * frame_duration,
+        }
+    }
+}
+
+impl TextureTrait for AnimatedTexture {
+    fn value(&self, u: f64, v: f64, p: Vec3) -> Vec3 {
+        self.sample(&TexCoord::new(u, v, p))
+    }
+
+    fn sample(&self, tc: &TexCoord) -> Vec3 {
+        dot(self.tex.sample(tc), self.tint.evaluate(self.time))
+    }
+
+    fn instancing(self) -> Arc<dyn TextureTrait + Send + Sync> {
+        Arc::new(self)
+    }
+}
+
+// a light whose emission is scaled by a keyframed intensity, scattering is left to the light;
+// emitted has no ray, so the intensity is the one at the middle of the frame
+pub struct AnimatedLight {
+    pub light: Arc<dyn MaterialTrait + Send + Sync>,
+    pub intensity: AnimationCurve<f64>,
+    time: f64,
+}
+
+impl AnimatedLight {
+    pub fn new(light: Arc<dyn MaterialTrait + Send + Sync>, intensity: AnimationCurve<f64>, frame_start: f64, frame_duration: f64) -> Self {
+        Self {
+            light,
+            intensity,
+            time: frame_start + 0.5 * frame_duration,
+        }
+    }
+}
+
+impl MaterialTrait for AnimatedLight {
+    fn scatter(&self, r: &Ray, hit_record: &HitRecord, attenuation: &mut Vec3, scattered: &mut Ray) -> bool {
+        self.light.scatter(r, hit_record, attenuation, scattered)
+    }
+
+    fn emitted(&self, hit_record: &HitRecord) -> Vec3 {
+        self.light.emitted(hit_record) * self.intensity.evaluate(self.time)
+    }
+
+    fn instancing(self) -> Arc<dyn MaterialTrait + Send + Sync> {
+        Arc::new(self)
+    }
+}
+
+// renders frame_count frames at fps into output_dir/frame_0000.jpg, frame_0001.jpg, ...
+// scene(time, frame_duration) builds the camera and world of the frame starting at time (seconds)
+pub fn render_sequence<F>(output_dir: &Path, frame_count: usize, fps: f64, quality: u8, scene: F)
+where
+    F: Fn(f64, f64) -> (Camera, Arc<dyn Hittable + Send + Sync>),
+{
+    std::fs::create_dir_all(output_dir).expect("cannot create the output directory");
+    let frame_duration = 1.0 / fps;
+    for frame in 0..frame_count {
+        let time = frame as f64 * frame_duration;
+        let (camera, world) = scene(time, frame_duration);
+        let img = camera.render(&world);
+
+        let path = output_dir.join(format!("frame_{:04}.jpg", frame));
+        println!("Ouput frame {}/{} as \"{}\"", frame + 1, frame_count, path.display());
+        let output_image: image::DynamicImage = image::DynamicImage::ImageRgb8(img);
+        let mut output_file: File = File::create(&path).unwrap();
+        if output_image
+            .write_to(&mut output_file, image::ImageOutputFormat::Jpeg(quality))
+            .is_err()
+        {
+            println!("Outputting image fails.");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sphere::*;
+
+    #[test]
+    fn axis_angle_spins_past_a_full_turn() {
+        let curve = AnimationCurve::new_linear(0.0, AxisAngle::around_y(0.0), 2.0, AxisAngle::around_y(720.0));
+        let half = curve.evaluate(1.0);
+        assert_eq!(half.angle, 360.0);
+        assert!((half.axis - Vec3::new(0.0, 1.0, 0.0)).length() < 1e-12);
+
+        // the axis turns on its way from x to z
+        let tilt = AxisAngle::lerp(AxisAngle::new(Vec3::new(3.0, 0.0, 0.0), 10.0), AxisAngle::new(Vec3::new(0.0, 0.0, 1.0), 30.0), 0.5);
+        assert!((tilt.axis - unit_vec(Vec3::new(1.0, 0.0, 1.0))).length() < 1e-12);
+        assert_eq!(tilt.angle, 20.0);
+    }
+
+    #[test]
+    fn animated_object_is_scaled_rotated_and_moved() {
+        let material = Lambertian::new_from_color(Vec3::ones()).instancing();
+        let sphere = Sphere::new(Vec3::new(1.0, 0.0, 0.0), 0.5, material).instancing();
+        // at time 1 the sphere is scaled by 2, turned a quarter around z and moved up by 10
+        let object = AnimatedObject::new(
+            sphere,
+            AnimationCurve::new_linear(0.0, Vec3::zero(), 1.0, Vec3::new(0.0, 10.0, 0.0)),
+            AnimationCurve::new_linear(0.0, AxisAngle::new(Vec3::new(0.0, 0.0, 1.0), 0.0), 1.0, AxisAngle::new(Vec3::new(0.0, 0.0, 1.0), 90.0)),
+            AnimationCurve::new_linear(0.0, Vec3::ones(), 1.0, Vec3::ones() * 2.0),
+            1.0,
+            1.0,
+        );
+        // the center moved to (0, 12, 0) and the radius to 1
+        let ray = Ray::new(Vec3::new(0.0, 12.0, 10.0), Vec3::new(0.0, 0.0, -1.0), 0.0);
+        let rec = object.hit(&ray, Interval::new(0.001, f64::INFINITY)).expect("the ray should hit the sphere");
+        assert!((rec.t - 9.0).abs() < 1e-9, "{}", rec.t);
+        assert!((rec.normal - Vec3::new(0.0, 0.0, 1.0)).length() < 1e-9, "{:?}", rec.normal);
+        let bbox = object.bounding_box();
+        assert!(bbox.y.tmin <= 11.0 && bbox.y.tmax >= 13.0);
+    }
+}
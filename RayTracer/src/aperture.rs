@@ -0,0 +1,167 @@
+use crate::utils::*;
+use crate::vec3::*;
+
+use image::{DynamicImage, GenericImageView};
+use std::f64::consts::PI;
+use std::path::Path;
+use std::sync::Arc;
+
+// shape of the lens opening, which is also the shape of out of focus highlights (bokeh)
+// sample() returns a point in [-1, 1]^2 (z = 0), scaled by the defocus radius of the camera
+pub trait ApertureTrait {
+    fn sample(&self) -> Vec3;
+    fn instancing(self) -> Arc<dyn ApertureTrait + Send + Sync>;
+}
+
+pub struct CircularAperture {}
+
+impl CircularAperture {
+    pub fn new() -> Self {
+        Self {}
+    }
+}
+
+impl Default for CircularAperture {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ApertureTrait for CircularAperture {
+    fn sample(&self) -> Vec3 {
+        random_in_unit_disk()
+    }
+
+    fn instancing(self) -> Arc<dyn ApertureTrait + Send + Sync> {
+        Arc::new(self)
+    }
+}
+
+// regular polygon inscribed in the unit circle, one corner per diaphragm blade
+pub struct PolygonAperture {
+    pub blades: u32,
+    pub rotation: f64,
+}
+
+impl PolygonAperture {
+    // rotation in degrees
+    pub fn new(blades: u32, rotation: f64) -> Self {
+        Self {
+            blades: blades.max(3),
+            rotation,
+        }
+    }
+}
+
+impl ApertureTrait for PolygonAperture {
+    fn sample(&self) -> Vec3 {
+        // all triangles of the fan have the same area, pick one and sample it uniformly
+        let step = 2.0 * PI / self.blades as f64;
+        let k = random_int_range(0, self.blades as i32 - 1) as f64;
+        let phi0 = self.rotation.to_radians() + k * step;
+        let a = Vec3::new(phi0.cos(), phi0.sin(), 0.0);
+        let b = Vec3::new((phi0 + step).cos(), (phi0 + step).sin(), 0.0);
+
+        let (mut r1, mut r2) = (random_f64_0_1(), random_f64_0_1());
+        if r1 + r2 > 1.0 {
+            r1 = 1.0 - r1;
+            r2 = 1.0 - r2;
+        }
+        a * r1 + b * r2
+    }
+
+    fn instancing(self) -> Arc<dyn ApertureTrait + Send + Sync> {
+        Arc::new(self)
+    }
+}
+
+// arbitrary aperture mask, brighter (and more opaque) pixels let more light through
+pub struct ImageAperture {
+    width: u32,
+    height: u32,
+    cdf: Vec<f64>,
+}
+
+impl ImageAperture {
+    pub fn new(path: &Path) -> Result<Self, String> {
+        let img = image::open(path).map_err(|err| format!("aperture mask {}: {}", path.display(), err))?;
+        Self::new_from_image(&img).ok_or_else(|| format!("aperture mask {} is completely black", path.display()))
+    }
+
+    // None when no pixel lets light through
+    pub fn new_from_image(img: &DynamicImage) -> Option<Self> {
+        let (width, height) = (img.width(), img.height());
+        let mut cdf = Vec::with_capacity((width * height) as usize);
+        let mut total = 0.0;
+        for j in 0..height {
+            for i in 0..width {
+                let pixel = img.get_pixel(i, j);
+                let luminance = (0.2126 * pixel[0] as f64 + 0.7152 * pixel[1] as f64 + 0.0722 * pixel[2] as f64) / 255.0;
+                total += luminance * pixel[3] as f64 / 255.0;
+                cdf.push(total);
+            }
+        }
+        if total <= 0.0 {
+            return None;
+        }
+        Some(Self { width, height, cdf })
+    }
+
+    // like TextureManager, a mask that cannot be used is reported and replaced, here by a circle
+    pub fn load(path: &Path) -> Arc<dyn ApertureTrait + Send + Sync> {
+        match Self::new(path) {
+            Ok(aperture) => aperture.instancing(),
+            Err(err) => {
+                eprintln!("{}, using a circular aperture", err);
+                CircularAperture::new().instancing()
+            }
+        }
+    }
+}
+
+impl ApertureTrait for ImageAperture {
+    fn sample(&self) -> Vec3 {
+        let total = self.cdf[self.cdf.len() - 1];
+        let target = random_f64_0_1() * total;
+        let index = self.cdf.partition_point(|&c| c <= target).min(self.cdf.len() - 1);
+        let i = index as u32 % self.width;
+        let j = index as u32 / self.width;
+
+        // fit the longer image side into [-1, 1], image rows grow downward
+        let scale = 2.0 / self.width.max(self.height) as f64;
+        let x = (i as f64 + random_f64_0_1() - self.width as f64 / 2.0) * scale;
+        let y = (self.height as f64 / 2.0 - j as f64 - random_f64_0_1()) * scale;
+        Vec3::new(x, y, 0.0)
+    }
+
+    fn instancing(self) -> Arc<dyn ApertureTrait + Send + Sync> {
+        Arc::new(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{Rgb, RgbImage};
+
+    #[test]
+    fn missing_or_black_mask_is_an_error() {
+        let err = ImageAperture::new(Path::new("no_such_aperture.png")).err().unwrap();
+        assert!(err.contains("no_such_aperture.png"), "{}", err);
+
+        let black = DynamicImage::ImageRgb8(RgbImage::new(4, 4));
+        assert!(ImageAperture::new_from_image(&black).is_none());
+    }
+
+    #[test]
+    fn mask_samples_stay_on_the_lit_pixels() {
+        // only the top right pixel of a 2 x 2 mask is lit, that is x and y in [0, 1]
+        let mut mask = RgbImage::new(2, 2);
+        mask.put_pixel(1, 0, Rgb([255, 255, 255]));
+        let aperture = ImageAperture::new_from_image(&DynamicImage::ImageRgb8(mask)).unwrap();
+        for _ in 0..1000 {
+            let p = aperture.sample();
+            assert!((0.0..=1.0).contains(&p.x) && (0.0..=1.0).contains(&p.y), "{:?}", p);
+        }
+    }
+}
@@ -1,5 +1,4 @@
 use std::sync::Arc;
-use std::cmp::Ordering;
 
 use crate::utils::*;
 use crate::vec3::*;
@@ -7,85 +6,313 @@ use crate::hitable::*;
 use crate::interval::*;
 use crate::aabb::*;
 
-pub struct BVHnode {
-    pub left: Arc<dyn Hittable + Send + Sync>,
-    pub right: Arc<dyn Hittable + Send + Sync>,
+// binned SAH builder with a flattened (depth first) node array
+// interior node: the first child is the next node in the array, offset is the second child
+// leaf node: primitives[offset..offset + primitive_count]
+#[derive(Copy, Clone)]
+pub struct LinearBVHNode {
     pub bbox: Aabb,
+    pub offset: usize,
+    pub primitive_count: usize,
+    pub axis: usize,
 }
 
-impl BVHnode {
-    pub fn new(list :&mut Hittable_list) -> Self {
-        let end = list.objects.len();
-        Self::new_from_list(&mut list.objects, 0, end)
+#[derive(Copy, Clone, Debug, Default)]
+pub struct BVHStats {
+    pub node_count: usize,
+    pub leaf_count: usize,
+    pub primitive_count: usize,
+    pub max_leaf_size: usize,
+    pub max_depth: usize,
+    pub sah_cost: f64,
+}
+
+impl BVHStats {
+    pub fn info(&self) {
+        println!(
+            "bvh: {} nodes, {} leaves, {} primitives, max leaf size {}, max depth {}, SAH cost {:.3}",
+            self.node_count, self.leaf_count, self.primitive_count, self.max_leaf_size, self.max_depth, self.sah_cost
+        );
     }
+}
+
+const SAH_BINS: usize = 12;
+const SAH_TRAVERSAL_COST: f64 = 0.125;
+const SAH_INTERSECTION_COST: f64 = 1.0;
+pub const DEFAULT_MAX_LEAF_SIZE: usize = 4;
+// also the size of the traversal stack
+const BVH_MAX_DEPTH: usize = 64;
+
+struct BuildPrimitive {
+    index: usize,
+    bbox: Aabb,
+    centroid: Vec3,
+}
+
+// returns the nodes, the primitive indices in leaf order and the build statistics
+pub fn build_sah(bboxes: &[Aabb], max_leaf_size: usize) -> (Vec<LinearBVHNode>, Vec<usize>, BVHStats) {
+    let mut primitives: Vec<BuildPrimitive> = bboxes
+        .iter()
+        .enumerate()
+        .map(|(index, bbox)| BuildPrimitive {
+            index,
+            bbox: *bbox,
+            centroid: bbox.centroid(),
+        })
+        .collect();
+    let mut nodes = Vec::with_capacity(2 * bboxes.len());
+    let mut ordered = Vec::with_capacity(bboxes.len());
+    let mut stats = BVHStats {
+        primitive_count: bboxes.len(),
+        ..BVHStats::default()
+    };
 
-    pub fn new_from_list(objects : &mut Vec<Arc<dyn Hittable + Send + Sync>>, start: usize, end: usize) -> Self {
-        let mut bbox = Aabb::default();
-        for i in start..end {
-            bbox = Aabb::new_from_bbox(bbox, objects[i].bounding_box());
+    if !primitives.is_empty() {
+        build_recursive(&mut primitives, max_leaf_size.max(1), 1, &mut nodes, &mut ordered, &mut stats);
+        let root_area = nodes[0].bbox.surface_area();
+        if root_area > 0.0 {
+            stats.sah_cost = nodes
+                .iter()
+                .map(|node| {
+                    let area = node.bbox.surface_area() / root_area;
+                    if node.primitive_count > 0 {
+                        area * node.primitive_count as f64 * SAH_INTERSECTION_COST
+                    } else {
+                        area * SAH_TRAVERSAL_COST
+                    }
+                })
+                .sum();
         }
+    }
+    stats.node_count = nodes.len();
+    (nodes, ordered, stats)
+}
 
-        let axis = bbox.longest_axis();
-
-        let object_span = end - start;
-        let left: Arc<dyn Hittable + Send + Sync>;
-        let right: Arc<dyn Hittable + Send + Sync>;
-
-        match object_span {
-            1 => {
-                left = objects[start].clone();
-                right = objects[start].clone();
-            },
-            2 => {
-                left = objects[start].clone();
-                right = objects[start+1].clone();
-            },
-            _ => {
-                objects[start..end].sort_by(|a, b| Self::bbox_compare(a, b, axis));
-                let mid = (start + end) / 2;
-                left = BVHnode::new_from_list(objects, start, mid).instancing();
-                right = BVHnode::new_from_list(objects, mid, end).instancing();
-            }
+fn build_recursive(
+    primitives: &mut [BuildPrimitive],
+    max_leaf_size: usize,
+    depth: usize,
+    nodes: &mut Vec<LinearBVHNode>,
+    ordered: &mut Vec<usize>,
+    stats: &mut BVHStats,
+) -> usize {
+    let mut bbox = Aabb::default();
+    let mut centroid_min = Vec3::new(f64::INFINITY, f64::INFINITY, f64::INFINITY);
+    let mut centroid_max = Vec3::new(f64::NEG_INFINITY, f64::NEG_INFINITY, f64::NEG_INFINITY);
+    for primitive in primitives.iter() {
+        bbox = Aabb::new_from_bbox(bbox, primitive.bbox);
+        centroid_min = Vec3::merge_min(&centroid_min, &primitive.centroid);
+        centroid_max = Vec3::merge_max(&centroid_max, &primitive.centroid);
+    }
+
+    let node_index = nodes.len();
+    nodes.push(LinearBVHNode {
+        bbox,
+        offset: 0,
+        primitive_count: 0,
+        axis: 0,
+    });
+    stats.max_depth = stats.max_depth.max(depth);
+
+    let n = primitives.len();
+    let split = if n == 1 || depth >= BVH_MAX_DEPTH {
+        None
+    } else {
+        find_sah_split(primitives, &bbox, &centroid_min, &centroid_max)
+    };
+
+    let mid = match split {
+        Some((axis, bin, cost)) if n > max_leaf_size || cost < n as f64 * SAH_INTERSECTION_COST => {
+            nodes[node_index].axis = axis;
+            let min = centroid_min.lp(axis as u8);
+            let extent = centroid_max.lp(axis as u8) - min;
+            Some(partition(primitives, |p| bin_index(p.centroid.lp(axis as u8), min, extent) < bin))
         }
-        Self {
-            left,
-            right,
-            bbox,
+        // all centroids coincide, only an arbitrary split keeps the leaves small
+        None if n > max_leaf_size && depth < BVH_MAX_DEPTH => {
+            nodes[node_index].axis = bbox.longest_axis();
+            Some(n / 2)
+        }
+        _ => None,
+    };
+
+    match mid {
+        Some(mid) => {
+            let (left, right) = primitives.split_at_mut(mid);
+            build_recursive(left, max_leaf_size, depth + 1, nodes, ordered, stats);
+            let second = build_recursive(right, max_leaf_size, depth + 1, nodes, ordered, stats);
+            nodes[node_index].offset = second;
+        }
+        None => {
+            nodes[node_index].offset = ordered.len();
+            nodes[node_index].primitive_count = n;
+            ordered.extend(primitives.iter().map(|p| p.index));
+            stats.leaf_count += 1;
+            stats.max_leaf_size = stats.max_leaf_size.max(n);
+        }
+    }
+    node_index
+}
+
+fn bin_index(centroid: f64, min: f64, extent: f64) -> usize {
+    (((centroid - min) / extent * SAH_BINS as f64) as usize).min(SAH_BINS - 1)
+}
+
+// (axis, first bin of the right side, SAH cost) of the cheapest binned split
+fn find_sah_split(primitives: &[BuildPrimitive], bbox: &Aabb, centroid_min: &Vec3, centroid_max: &Vec3) -> Option<(usize, usize, f64)> {
+    let parent_area = bbox.surface_area();
+    let mut best: Option<(usize, usize, f64)> = None;
+
+    for axis in 0..3 {
+        let min = centroid_min.lp(axis as u8);
+        let extent = centroid_max.lp(axis as u8) - min;
+        if extent.is_nan() || extent <= 0.0 {
+            continue;
+        }
+
+        let mut counts = [0usize; SAH_BINS];
+        let mut bounds = [Aabb::default(); SAH_BINS];
+        for primitive in primitives {
+            let b = bin_index(primitive.centroid.lp(axis as u8), min, extent);
+            counts[b] += 1;
+            bounds[b] = Aabb::new_from_bbox(bounds[b], primitive.bbox);
+        }
+
+        // sweep from the right to get the suffix areas, then from the left
+        let mut right_area = [0.0; SAH_BINS];
+        let mut right_count = [0usize; SAH_BINS];
+        let mut acc = Aabb::default();
+        let mut count = 0;
+        for b in (1..SAH_BINS).rev() {
+            acc = Aabb::new_from_bbox(acc, bounds[b]);
+            count += counts[b];
+            right_area[b] = acc.surface_area();
+            right_count[b] = count;
+        }
+
+        let mut acc = Aabb::default();
+        let mut count = 0;
+        for b in 1..SAH_BINS {
+            acc = Aabb::new_from_bbox(acc, bounds[b - 1]);
+            count += counts[b - 1];
+            if count == 0 || right_count[b] == 0 {
+                continue;
+            }
+            let cost = SAH_TRAVERSAL_COST
+                + SAH_INTERSECTION_COST * (count as f64 * acc.surface_area() + right_count[b] as f64 * right_area[b]) / parent_area;
+            if best.is_none_or(|(_, _, best_cost)| cost < best_cost) {
+                best = Some((axis, b, cost));
+            }
         }
     }
+    best
+}
 
-    fn bbox_compare(a: &Arc<dyn Hittable + Send + Sync>, b: &Arc<dyn Hittable + Send + Sync>, axis_index: usize) -> Ordering {
-        let a_binding = a.bounding_box();
-        let a_axis_interval = a_binding.axis_interval(axis_index);
-        let b_binding = b.bounding_box();
-        let b_axis_interval = b_binding.axis_interval(axis_index);
-        if a_axis_interval.tmin < b_axis_interval.tmin {
-            Ordering::Less
+// moves the primitives satisfying pred to the front, returns how many there are
+fn partition(primitives: &mut [BuildPrimitive], pred: impl Fn(&BuildPrimitive) -> bool) -> usize {
+    let mut first = 0;
+    for i in 0..primitives.len() {
+        if pred(&primitives[i]) {
+            primitives.swap(first, i);
+            first += 1;
         }
-        else if a_axis_interval.tmin == b_axis_interval.tmin {
-            Ordering::Equal
+    }
+    first
+}
+
+// walks the flattened nodes front to back
+// leaf_hit(i, ray_t) tests the i-th primitive in leaf order and returns the t of the hit, if any
+pub fn traverse_flat_bvh(
+    nodes: &[LinearBVHNode],
+    r: &Ray,
+    ray_t: Interval,
+    mut leaf_hit: impl FnMut(usize, Interval) -> Option<f64>,
+) -> bool {
+    if nodes.is_empty() {
+        return false;
+    }
+    let direction = r.direction();
+    let mut closest_so_far = ray_t.tmax;
+    let mut hit_anything = false;
+    let mut stack = [0usize; BVH_MAX_DEPTH];
+    let mut stack_size = 0;
+    let mut current = 0;
+
+    loop {
+        let node = &nodes[current];
+        if node.bbox.hit(r, Interval::new(ray_t.tmin, closest_so_far)) {
+            if node.primitive_count > 0 {
+                for i in node.offset..node.offset + node.primitive_count {
+                    if let Some(t) = leaf_hit(i, Interval::new(ray_t.tmin, closest_so_far)) {
+                        hit_anything = true;
+                        closest_so_far = t;
+                    }
+                }
+            } else {
+                // visit the child on the side the ray comes from first
+                if direction.lp(node.axis as u8) < 0.0 {
+                    stack[stack_size] = current + 1;
+                    current = node.offset;
+                } else {
+                    stack[stack_size] = node.offset;
+                    current += 1;
+                }
+                stack_size += 1;
+                continue;
+            }
         }
-        else {
-            Ordering::Greater
+        if stack_size == 0 {
+            break;
         }
+        stack_size -= 1;
+        current = stack[stack_size];
     }
+    hit_anything
+}
+
+pub struct FlatBVH {
+    pub nodes: Vec<LinearBVHNode>,
+    pub primitives: Vec<Arc<dyn Hittable + Send + Sync>>,
+    pub stats: BVHStats,
 }
 
-impl Hittable for BVHnode {
-    fn hit(&self, r: &Ray, ray_t: Interval, rec: &mut HitRecord) -> bool {
-        if !self.bbox.hit(r, ray_t) {
-            return false;
+impl FlatBVH {
+    pub fn new(list: &Hittable_list) -> Self {
+        Self::new_from_objects(list.objects.clone(), DEFAULT_MAX_LEAF_SIZE)
+    }
+
+    pub fn new_from_objects(objects: Vec<Arc<dyn Hittable + Send + Sync>>, max_leaf_size: usize) -> Self {
+        let bboxes: Vec<Aabb> = objects.iter().map(|object| object.bounding_box()).collect();
+        let (nodes, ordered, stats) = build_sah(&bboxes, max_leaf_size);
+        let primitives = ordered.iter().map(|&i| objects[i].clone()).collect();
+        Self {
+            nodes,
+            primitives,
+            stats,
         }
-        let hit_left = self.left.hit(r, ray_t, rec);
-        let hit_right = self.right.hit(r, Interval::new(ray_t.tmin, if hit_left {rec.t} else {ray_t.tmax}), rec);
-        hit_left || hit_right
+    }
+}
+
+impl Hittable for FlatBVH {
+    fn hit(&self, r: &Ray, ray_t: Interval) -> Option<HitRecord<'_>> {
+        let mut closest = None;
+        traverse_flat_bvh(&self.nodes, r, ray_t, |i, interval| {
+            let rec = self.primitives[i].hit(r, interval)?;
+            closest = Some(rec);
+            Some(rec.t)
+        });
+        closest
     }
 
     fn bounding_box(&self) -> Aabb {
-        self.bbox
+        if self.nodes.is_empty() {
+            Aabb::default()
+        } else {
+            self.nodes[0].bbox
+        }
     }
 
     fn instancing(self) -> Arc<dyn Hittable + Send + Sync> {
         Arc::new(self)
     }
-}
\ No newline at end of file
+}
@@ -1,15 +1,17 @@
-use crate::color::*;
 use crate::vec3::*;
 use crate::utils::*;
 use crate::hitable::*;
 use crate::ray::*;
 use crate::interval::*;
 use crate::material::*;
+use crate::filter::*;
+use crate::film::*;
+use crate::projection::*;
+use crate::aperture::*;
 
-use rand::prelude::*;
 use indicatif::{ProgressBar, ProgressStyle};
 use std::fs::File;
-use image::{ImageBuffer, RgbImage}; 
+use image::RgbImage;
 use std::f64::consts::PI;
 use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::{Arc, Mutex, Condvar};
@@ -20,7 +22,7 @@ const HEIGHT_PARTITION: usize = 20; // multithreading parameters
 const WIDTH_PARTITION: usize = 20;
 const THREAD_LIMIT: usize = 20;
 
-#[derive(Copy, Clone)]
+#[derive(Clone)]
 pub struct Camera {
     pub camera_center: Vec3,
     pub aspect_ratio: f64,
@@ -43,6 +45,17 @@ pub struct Camera {
     pub background: Vec3,
     pub sqrt_spp: u32,
     pub recip_sqrt_spp: f64,
+    pub filter: Arc<dyn FilterTrait + Send + Sync>,
+    pub u: Vec3,
+    pub v: Vec3,
+    pub w: Vec3,
+    pub viewport_upper_left: Vec3,
+    pub projection: Arc<dyn ProjectionTrait + Send + Sync>,
+    pub shutter_open: f64,
+    pub shutter_close: f64,
+    pub exposure: f64,
+    pub aperture: Arc<dyn ApertureTrait + Send + Sync>,
+    pub cat_eye: f64,
 }
 
 impl Camera {
@@ -108,27 +121,86 @@ impl Camera {
            background,
            sqrt_spp,
            recip_sqrt_spp,
+           filter: BoxFilter::new(0.5).instancing(),
+           u,
+           v,
+           w,
+           viewport_upper_left,
+           projection: Perspective::new().instancing(),
+           shutter_open: 0.0,
+           shutter_close: 1.0,
+           exposure: 1.0,
+           aperture: CircularAperture::new().instancing(),
+           cat_eye: 0.0,
         }
     }
 
+    // the default box filter of radius 0.5 is the plain per-pixel average
+    pub fn set_filter(&mut self, filter: Arc<dyn FilterTrait + Send + Sync>) {
+        self.filter = filter;
+    }
+
+    // the perspective projection set up by new() is the default
+    pub fn set_projection(&mut self, projection: Arc<dyn ProjectionTrait + Send + Sync>) {
+        self.projection = projection;
+    }
+
+    // ray times are drawn from [shutter_open, shutter_close), 0..1 spans one frame
+    pub fn set_shutter(&mut self, shutter_open: f64, shutter_close: f64) {
+        self.shutter_open = shutter_open;
+        self.shutter_close = shutter_close;
+    }
+
+    // every radiance sample is scaled by the exposure before it reaches the film
+    pub fn set_exposure(&mut self, exposure: f64) {
+        self.exposure = exposure;
+    }
+
+    pub fn set_aperture(&mut self, aperture: Arc<dyn ApertureTrait + Send + Sync>) {
+        self.aperture = aperture;
+    }
+
+    // 0 disables it; at the frame corners the circle clipping the lens is shifted by 2 * cat_eye
+    // lens radii, so 0.5 leaves about 39% of the opening there and 1 closes it
+    pub fn set_cat_eye_vignetting(&mut self, cat_eye: f64) {
+        self.cat_eye = cat_eye;
+    }
+
 
     pub fn defocus_disk_sample(&self) -> Vec3 {
-        let p = random_in_unit_disk();
+        let p = self.aperture.sample();
         self.camera_center + (self.defocus_disk_u * p.x) + (self.defocus_disk_v * p.y)
     }
 
+    // lens sample for film position (s, t), None if the barrel of the lens blocks it
+    // with cat's eye vignetting the exit pupil seen from the film shifts toward the frame edges
+    pub fn lens_sample(&self, s: f64, t: f64) -> Option<Vec3> {
+        let p = self.aperture.sample();
+        if self.cat_eye > 0.0 {
+            let half_diagonal = ((self.width * self.width + self.height * self.height) as f64).sqrt() / 2.0;
+            let shift_x = (s - 0.5) * self.width as f64 / half_diagonal * 2.0 * self.cat_eye;
+            let shift_y = (0.5 - t) * self.height as f64 / half_diagonal * 2.0 * self.cat_eye;
+            let dx = p.x + shift_x;
+            let dy = p.y + shift_y;
+            if dx * dx + dy * dy > 1.0 {
+                return None;
+            }
+        }
+        Some(self.camera_center + (self.defocus_disk_u * p.x) + (self.defocus_disk_v * p.y))
+    }
+
     pub fn ray_color(&self, r: &Ray, world: &Arc<dyn Hittable + Send + Sync>, depth: u32) -> Vec3 {
         if depth <= 0 {
             return Vec3::new(0.0, 0.0, 0.0);
         }
 
-        let mut rec = HitRecord::default();
-        if !world.hit(r, Interval::new(0.001,core::f64::INFINITY), &mut rec) {
+        let Some(mut rec) = world.hit(r, Interval::new(0.001,core::f64::INFINITY)) else {
             return self.background;
-        }
+        };
+        rec.compute_differentials(r);
         let mut scattered = Ray::default();
         let mut attenuation = Vec3::zero();
-        let color_from_emission = rec.material.emitted(rec.u, rec.v, rec.point);
+        let color_from_emission = rec.material.emitted(&rec);
         if !rec.material.scatter(r, &rec, &mut attenuation, &mut scattered) {
             return color_from_emission;
         }
@@ -144,17 +216,29 @@ impl Camera {
         color_from_emission + color_from_scattered
     }
     
-    pub fn get_ray(&self, i: u32, j: u32, s_i: u32, s_j: u32) -> Ray {
+    pub fn get_ray(&self, i: u32, j: u32, s_i: u32, s_j: u32) -> Option<Ray> {
         let offset = self.sample_square_stratified(s_i, s_j);
-        let pixel_center = self.pixel_zero_loc + (self.pixel_delta_u * (i as f64 + offset.x)) + (self.pixel_delta_v * (j as f64 + offset.y));
-        let ray_origin = if self.defocus_angle <= 0.0 {
-            self.camera_center
+        self.get_ray_with_offset(i, j, offset)
+    }
+
+    // offset is the sample position inside pixel (i, j), in [-0.5, 0.5)^2
+    pub fn get_ray_with_offset(&self, i: u32, j: u32, offset: Vec3) -> Option<Ray> {
+        let s = (i as f64 + 0.5 + offset.x) / self.width as f64;
+        let t = (j as f64 + 0.5 + offset.y) / self.height as f64;
+        let mut ray = self.projection.generate_ray(self, s, t, self.sample_time())?;
+        ray.differential = self.projection.generate_differential(self, &ray, s, t);
+        // the samples of a pixel already average part of its footprint
+        ray.scale_differential(self.recip_sqrt_spp.max(0.125));
+        Some(ray)
+    }
+
+    pub fn sample_time(&self) -> f64 {
+        if self.shutter_close > self.shutter_open {
+            random_f64_range(self.shutter_open, self.shutter_close)
         }
         else {
-            self.defocus_disk_sample()
-        };
-        let ray_direction = pixel_center - ray_origin;
-        Ray::new(ray_origin, ray_direction, random_f64_0_1())
+            self.shutter_open
+        }
     }
 
     pub fn sample_square_stratified(&self, s_i: u32, s_j: u32) -> Vec3 {
@@ -168,8 +252,8 @@ impl Camera {
     }
 
     pub fn render(&self, world: &Arc<dyn Hittable + Send + Sync>) -> RgbImage{
-        let mut img: RgbImage = ImageBuffer::new(self.width, self.height);
-        let img_mtx = Arc::new(Mutex::new(&mut img));
+        let mut film = Film::new(self.width as usize, self.height as usize);
+        let film_mtx = Arc::new(Mutex::new(&mut film));
 
         let bar: ProgressBar = if Self::is_ci() {
             ProgressBar::hidden()
@@ -197,7 +281,7 @@ impl Camera {
                 }
                 
                 let camera = Arc::clone(&camera);
-                let img_mtx = Arc::clone(&img_mtx);
+                let film_mtx = Arc::clone(&film_mtx);
                 let bar = Arc::clone(&bar_wrapper);
                 let world = Arc::clone(&world);
                 let thread_count = Arc::clone(&thread_count);
@@ -208,7 +292,7 @@ impl Camera {
                 bar.set_message(format!("|{} threads outstanding|", thread_count.load(Ordering::SeqCst))); 
       
                 let _ = thd_spawner.spawn(move |_| {
-                  camera.render_sub(&world, &img_mtx, &bar, 
+                  camera.render_sub(&world, &film_mtx, &bar, 
                     i * chunk_width, (i + 1) * chunk_width, 
                     j * chunk_height, (j + 1) * chunk_height);
       
@@ -222,10 +306,10 @@ impl Camera {
           }).unwrap();
         
           bar.finish();
-          img
+          film.to_image()
     }
 
-    pub fn render_sub(&self, world: &Arc<dyn Hittable + Send + Sync>, img_mtx: &Mutex<&mut RgbImage>, bar: &ProgressBar, x_min: usize, x_max: usize, y_min: usize, y_max: usize) {
+    pub fn render_sub(&self, world: &Arc<dyn Hittable + Send + Sync>, film_mtx: &Mutex<&mut Film>, bar: &ProgressBar, x_min: usize, x_max: usize, y_min: usize, y_max: usize) {
         let x_max = x_max.min(self.width as usize);
         let y_max = y_max.min(self.height as usize);
         let x_min = x_min.max(0);
@@ -233,27 +317,26 @@ impl Camera {
 
         // avoid situation x_min == x_max 
         if x_max > x_min && y_max > y_min {
-            let mut buff: Vec<Vec<Vec3>> = vec![vec![Vec3::zero(); y_max - y_min]; x_max - x_min];
+            // samples near the tile border are splatted into the neighbouring tiles as well
+            let mut tile = Film::new_tile_for(self.filter.as_ref(), self.width as usize, self.height as usize, x_min, x_max, y_min, y_max);
 
             for j in y_min..y_max {
                 for i in x_min..x_max {
-                    let mut pixel_color = Vec3::new(0.0,0.0,0.0);
                     for s_i in 0..self.sqrt_spp {
                         for s_j in 0..self.sqrt_spp {
-                            let mut r = self.get_ray(i as u32, j as u32, s_i as u32, s_j as u32);
-                        pixel_color += self.ray_color(&r,&world, self.max_depth);
+                            let offset = self.sample_square_stratified(s_i, s_j);
+                            let sample_color = match self.get_ray_with_offset(i as u32, j as u32, offset) {
+                                Some(r) => self.ray_color(&r, world, self.max_depth) * self.exposure,
+                                None => Vec3::zero(),
+                            };
+                            tile.add_sample(i as f64 + 0.5 + offset.x, j as f64 + 0.5 + offset.y, sample_color, self.filter.as_ref());
                         } 
                     }
-                    buff[i - x_min][j - y_min] = pixel_color *self.pixel_samples_scale;
                 }
                 bar.inc((x_max - x_min) as u64);
             }
-            let mut img = img_mtx.lock().unwrap();
-            for j in y_min..y_max {
-                for i in x_min..x_max {
-                    write_color(buff[i - x_min][j - y_min], &mut img, i as usize, j as usize);
-                }
-            }
+            let mut film = film_mtx.lock().unwrap();
+            film.merge(&tile);
         }
     }
     
@@ -11,6 +11,110 @@ pub fn linear_to_gamma(linear_component:f64) -> f64 {
     }
 }
 
+// how the values stored in an image relate to the linear values the renderer works with
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub enum ColorSpace {
+    // 8 and 16-bit color images, their sRGB transfer curve is undone
+    Srgb,
+    // linear color stored as is
+    Linear,
+    // not a color (roughness, heights, normal maps), the values are used as stored
+    Raw,
+}
+
+impl ColorSpace {
+    // stored value in [0, 1] to linear
+    pub fn decode(&self, value: f32) -> f32 {
+        match self {
+            ColorSpace::Srgb => {
+                if value <= 0.04045 {
+                    value / 12.92
+                } else {
+                    ((value + 0.055) / 1.055).powf(2.4)
+                }
+            }
+            ColorSpace::Linear | ColorSpace::Raw => value,
+        }
+    }
+}
+
+// colors at positions in [0, 1], linearly blended in between; before the first and after the last
+// stop the end colors continue
+// usage: ColorRamp::new(vec![(0.0, dark), (0.6, mid), (1.0, light)]).eval(t)
+#[derive(Clone, Debug)]
+pub struct ColorRamp {
+    stops: Vec<(f64, Vec3)>,
+}
+
+impl ColorRamp {
+    // the stops can come in any order, there must be at least one
+    pub fn new(mut stops: Vec<(f64, Vec3)>) -> ColorRamp {
+        assert!(!stops.is_empty(), "a color ramp needs a stop");
+        stops.sort_by(|a, b| a.0.total_cmp(&b.0));
+        ColorRamp { stops }
+    }
+
+    pub fn new_two_colors(start: Vec3, end: Vec3) -> ColorRamp {
+        Self::new(vec![(0.0, start), (1.0, end)])
+    }
+
+    // black to white, eval(t) is t in every channel
+    pub fn grayscale() -> ColorRamp {
+        Self::new_two_colors(Vec3::zero(), Vec3::ones())
+    }
+
+    pub fn eval(&self, t: f64) -> Vec3 {
+        let next = self.stops.partition_point(|stop| stop.0 <= t);
+        if next == 0 {
+            return self.stops[0].1;
+        }
+        if next == self.stops.len() {
+            return self.stops[next - 1].1;
+        }
+        let ((t0, c0), (t1, c1)) = (self.stops[next - 1], self.stops[next]);
+        let f = (t - t0) / (t1 - t0);
+        c0 * (1.0 - f) + c1 * f
+    }
+}
+
+// Rec. 709 weights, a gray color gives back its value
+pub fn luminance(c: Vec3) -> f64 {
+    0.2126 * c.x + 0.7152 * c.y + 0.0722 * c.z
+}
+
+// (hue, saturation, value), the hue in turns in [0, 1)
+pub fn rgb_to_hsv(c: Vec3) -> Vec3 {
+    let max = c.x.max(c.y).max(c.z);
+    let min = c.x.min(c.y).min(c.z);
+    let delta = max - min;
+    let saturation = if max > 0.0 { delta / max } else { 0.0 };
+    if delta <= 0.0 {
+        return Vec3::new(0.0, saturation, max);
+    }
+    let sector = if max == c.x {
+        (c.y - c.z) / delta
+    } else if max == c.y {
+        (c.z - c.x) / delta + 2.0
+    } else {
+        (c.x - c.y) / delta + 4.0
+    };
+    Vec3::new((sector / 6.0).rem_euclid(1.0), saturation, max)
+}
+
+pub fn hsv_to_rgb(hsv: Vec3) -> Vec3 {
+    let (hue, saturation, value) = (hsv.x.rem_euclid(1.0) * 6.0, hsv.y, hsv.z);
+    let f = hue - hue.floor();
+    let (p, q, t) = (value * (1.0 - saturation), value * (1.0 - saturation * f), value * (1.0 - saturation * (1.0 - f)));
+    match hue as u32 {
+        0 => Vec3::new(value, t, p),
+        1 => Vec3::new(q, value, p),
+        2 => Vec3::new(p, value, t),
+        3 => Vec3::new(p, q, value),
+        4 => Vec3::new(t, p, value),
+        _ => Vec3::new(value, p, q),
+    }
+}
+
 /// the multi-sample write_color() function
 pub fn write_color(pixel_color: Vec3, img: &mut RgbImage, i: usize, j: usize) {
     let pixel = img.get_pixel_mut(i.try_into().unwrap(), j.try_into().unwrap());
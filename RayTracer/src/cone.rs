@@ -0,0 +1,115 @@
+use crate::aabb::*;
+use crate::disk::*;
+use crate::hitable::*;
+use crate::interval::*;
+use crate::material::*;
+use crate::onb::*;
+use crate::ray::*;
+use crate::utils::*;
+use crate::vec3::*;
+
+use std::f64::consts::PI;
+use std::sync::Arc;
+
+// cone with its base disk at base and its tip at apex, the base may be left open
+// side: u is the angle around the axis, v the height in [0, 1]; base: v is the distance to the axis over the radius
+pub struct Cone {
+    pub base: Vec3,
+    pub apex: Vec3,
+    pub radius: f64,
+    pub capped: bool,
+    pub material: Arc<dyn MaterialTrait + Send + Sync>,
+    height: f64,
+    onb: Onb,
+    bbox: Aabb,
+}
+
+impl Cone {
+    pub fn new(base: Vec3, apex: Vec3, radius: f64, material: Arc<dyn MaterialTrait + Send + Sync>) -> Cone {
+        let axis = apex - base;
+        let onb = Onb::new(axis);
+        Cone {
+            base,
+            apex,
+            radius,
+            capped: true,
+            material,
+            height: axis.length(),
+            onb,
+            bbox: Aabb::new_from_bbox(disk_bbox(base, axis, radius), Aabb::new_from_point(apex, apex)),
+        }
+    }
+
+    pub fn new_uncapped(base: Vec3, apex: Vec3, radius: f64, material: Arc<dyn MaterialTrait + Send + Sync>) -> Cone {
+        Cone {
+            capped: false,
+            ..Self::new(base, apex, radius, material)
+        }
+    }
+}
+
+impl Hittable for Cone {
+    fn hit(&self, r: &Ray, ray_t: Interval) -> Option<HitRecord<'_>> {
+        // z goes from 0 (base) to height (apex), the radius at z is k * (height - z)
+        let o = self.onb.to_local(r.origin() - self.base);
+        let d = self.onb.to_local(r.direction());
+        let k = self.radius / self.height;
+        let k2 = k * k;
+        // (t, normal, u, v, dpdu, dpdv) in the frame of the axis
+        let mut closest: Option<(f64, Vec3, f64, f64, Vec3, Vec3)> = None;
+        let mut consider = |t: f64, local_normal: Vec3, u: f64, v: f64, dpdu: Vec3, dpdv: Vec3| {
+            if ray_t.surrounds(t) && closest.is_none_or(|c| t < c.0) {
+                closest = Some((t, local_normal, u, v, dpdu, dpdv));
+            }
+        };
+
+        // x^2 + y^2 = k^2 (height - z)^2
+        let h = self.height - o.z;
+        let a = d.x * d.x + d.y * d.y - k2 * d.z * d.z;
+        let b = 2.0 * (o.x * d.x + o.y * d.y + k2 * h * d.z);
+        let c = o.x * o.x + o.y * o.y - k2 * h * h;
+        let (roots, count) = solve_quadratic(a, b, c);
+        for &t in &roots[..count] {
+            let p = o + d * t;
+            if (0.0..=self.height).contains(&p.z) {
+                // gradient of the implicit surface
+                let normal = unit_vec(Vec3::new(p.x, p.y, k2 * (self.height - p.z)));
+                let rho = (p.x * p.x + p.y * p.y).sqrt();
+                let dpdu = Vec3::new(-p.y, p.x, 0.0) * (2.0 * PI);
+                // along the slant, the radius shrinks by k per unit of z
+                let dpdv = if rho > 0.0 {
+                    Vec3::new(-k * p.x / rho, -k * p.y / rho, 1.0) * self.height
+                } else {
+                    Vec3::new(0.0, 0.0, self.height)
+                };
+                consider(t, normal, azimuth_uv(p.x, p.y), p.z / self.height, dpdu, dpdv);
+            }
+        }
+
+        if self.capped && fabs(d.z) > 1e-12 {
+            let t = -o.z / d.z;
+            let p = o + d * t;
+            let rho2 = p.x * p.x + p.y * p.y;
+            if rho2 <= self.radius * self.radius {
+                let (dpdu, dpdv) = cap_dpduv(p, self.radius);
+                consider(t, Vec3::new(0.0, 0.0, -1.0), azimuth_uv(p.x, p.y), rho2.sqrt() / self.radius, dpdu, dpdv);
+            }
+        }
+
+        let (t, local_normal, u, v, dpdu, dpdv) = closest?;
+        let normal = self.onb.local_vec(local_normal);
+        let mut rec = HitRecord::new(t, r.at(t), normal, self.material.as_ref(), true, u, v);
+        rec.dpdu = self.onb.local_vec(dpdu);
+        rec.dpdv = self.onb.local_vec(dpdv);
+        rec.set_face_normal(*r, normal);
+        Some(rec)
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.bbox
+    }
+
+    fn instancing(self) -> Arc<dyn Hittable + Send + Sync> {
+        Arc::new(self)
+    }
+}
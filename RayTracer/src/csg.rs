@@ -0,0 +1,127 @@
+use crate::aabb::*;
+use crate::hitable::*;
+use crate::interval::*;
+use crate::ray::*;
+
+use std::sync::Arc;
+
+#[derive(Copy, Clone, PartialEq)]
+pub enum CsgOperation {
+    Union,
+    Intersection,
+    // a minus b
+    Difference,
+}
+
+impl CsgOperation {
+    pub fn inside(&self, in_a: bool, in_b: bool) -> bool {
+        match self {
+            CsgOperation::Union => in_a || in_b,
+            CsgOperation::Intersection => in_a && in_b,
+            CsgOperation::Difference => in_a && !in_b,
+        }
+    }
+}
+
+// boolean combination of two closed objects whose normals point outwards
+// the surfaces keep the material of the object they come from, so the walls carved by b in a
+// difference show b's material
+pub struct Csg {
+    pub a: Arc<dyn Hittable + Send + Sync>,
+    pub b: Arc<dyn Hittable + Send + Sync>,
+    pub operation: CsgOperation,
+    bbox: Aabb,
+}
+
+// how far past a surface the next hit is searched for, same as ConstantMedium
+const CSG_EPSILON: f64 = 0.0001;
+
+impl Csg {
+    pub fn new(operation: CsgOperation, a: Arc<dyn Hittable + Send + Sync>, b: Arc<dyn Hittable + Send + Sync>) -> Csg {
+        let (box_a, box_b) = (a.bounding_box(), b.bounding_box());
+        let bbox = match operation {
+            CsgOperation::Union => Aabb::new_from_bbox(box_a, box_b),
+            CsgOperation::Intersection => Aabb::new(
+                Interval::new_overlap(box_a.x, box_b.x),
+                Interval::new_overlap(box_a.y, box_b.y),
+                Interval::new_overlap(box_a.z, box_b.z),
+            ),
+            CsgOperation::Difference => box_a,
+        };
+        Csg {
+            a,
+            b,
+            operation,
+            bbox,
+        }
+    }
+
+    pub fn new_union(a: Arc<dyn Hittable + Send + Sync>, b: Arc<dyn Hittable + Send + Sync>) -> Csg {
+        Self::new(CsgOperation::Union, a, b)
+    }
+
+    pub fn new_intersection(a: Arc<dyn Hittable + Send + Sync>, b: Arc<dyn Hittable + Send + Sync>) -> Csg {
+        Self::new(CsgOperation::Intersection, a, b)
+    }
+
+    pub fn new_difference(a: Arc<dyn Hittable + Send + Sync>, b: Arc<dyn Hittable + Send + Sync>) -> Csg {
+        Self::new(CsgOperation::Difference, a, b)
+    }
+
+    // next surface of the object after tmin, up to infinity so that the last exit is always found
+    fn next_hit<'a>(object: &'a Arc<dyn Hittable + Send + Sync>, r: &Ray, tmin: f64) -> Option<HitRecord<'a>> {
+        object.hit(r, Interval::new(tmin, f64::INFINITY))
+    }
+}
+
+impl Hittable for Csg {
+    // walks the surfaces of both objects in order along the ray; a front face enters the object,
+    // a back face leaves it, and the first surface that changes the combined state is the hit
+    fn hit(&self, r: &Ray, ray_t: Interval) -> Option<HitRecord<'_>> {
+        let mut hit_a = Self::next_hit(&self.a, r, ray_t.tmin);
+        let mut hit_b = Self::next_hit(&self.b, r, ray_t.tmin);
+        // the first surface ahead tells whether the ray starts inside
+        let mut in_a = hit_a.is_some_and(|rec| !rec.front_face);
+        let mut in_b = hit_b.is_some_and(|rec| !rec.front_face);
+
+        loop {
+            let (use_a, mut current) = match (hit_a, hit_b) {
+                (Some(rec_a), Some(rec_b)) => if rec_a.t <= rec_b.t { (true, rec_a) } else { (false, rec_b) },
+                (Some(rec_a), None) => (true, rec_a),
+                (None, Some(rec_b)) => (false, rec_b),
+                (None, None) => return None,
+            };
+            if current.t > ray_t.tmax {
+                return None;
+            }
+
+            let was_inside = self.operation.inside(in_a, in_b);
+            if use_a {
+                in_a = current.front_face;
+            } else {
+                in_b = current.front_face;
+            }
+            let is_inside = self.operation.inside(in_a, in_b);
+
+            if was_inside != is_inside {
+                // the normal already faces the ray, only the side of the result has to be told
+                current.front_face = is_inside;
+                return Some(current);
+            }
+
+            if use_a {
+                hit_a = Self::next_hit(&self.a, r, current.t + CSG_EPSILON);
+            } else {
+                hit_b = Self::next_hit(&self.b, r, current.t + CSG_EPSILON);
+            }
+        }
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.bbox
+    }
+
+    fn instancing(self) -> Arc<dyn Hittable + Send + Sync> {
+        Arc::new(self)
+    }
+}
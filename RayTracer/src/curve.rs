@@ -0,0 +1,364 @@
+use crate::aabb::*;
+use crate::bvh::*;
+use crate::hitable::*;
+use crate::interval::*;
+use crate::material::*;
+use crate::onb::*;
+use crate::ray::*;
+use crate::utils::*;
+use crate::vec3::*;
+
+use std::sync::Arc;
+
+#[derive(Copy, Clone, PartialEq)]
+pub enum CurveType {
+    // ribbon facing the ray, or following the curve normals when it has some (grass blades)
+    Flat,
+    // ribbon facing the ray but shaded as a round tube (hair, cables)
+    Cylinder,
+}
+
+// one cubic Bezier segment whose width goes linearly from width[0] to width[1]
+#[derive(Copy, Clone)]
+pub struct Curve {
+    pub cp: [Vec3; 4],
+    pub width: [f64; 2],
+    // orientation of a flat ribbon at both ends
+    pub normal: Option<[Vec3; 2]>,
+    pub curve_type: CurveType,
+}
+
+impl Curve {
+    pub fn new(cp: [Vec3; 4], width0: f64, width1: f64, curve_type: CurveType) -> Curve {
+        Curve {
+            cp,
+            width: [width0, width1],
+            normal: None,
+            curve_type,
+        }
+    }
+
+    pub fn new_ribbon(cp: [Vec3; 4], width0: f64, width1: f64, normal0: Vec3, normal1: Vec3) -> Curve {
+        Curve {
+            cp,
+            width: [width0, width1],
+            normal: Some([unit_vec(normal0), unit_vec(normal1)]),
+            curve_type: CurveType::Flat,
+        }
+    }
+
+    pub fn width_at(&self, u: f64) -> f64 {
+        self.width[0] * (1.0 - u) + self.width[1] * u
+    }
+}
+
+// point and derivative of a cubic Bezier
+pub fn eval_bezier(cp: &[Vec3; 4], u: f64) -> (Vec3, Vec3) {
+    let lerp = |a: Vec3, b: Vec3| a * (1.0 - u) + b * u;
+    let cp1 = [lerp(cp[0], cp[1]), lerp(cp[1], cp[2]), lerp(cp[2], cp[3])];
+    let cp2 = [lerp(cp1[0], cp1[1]), lerp(cp1[1], cp1[2])];
+    let derivative = if (cp2[1] - cp2[0]).squared_length() > 0.0 {
+        (cp2[1] - cp2[0]) * 3.0
+    } else {
+        // degenerate ends, the chord still gives the direction
+        cp[3] - cp[0]
+    };
+    (lerp(cp2[0], cp2[1]), derivative)
+}
+
+// de Casteljau at the middle, the halves share the middle point
+fn subdivide_bezier(cp: &[Vec3; 4]) -> [Vec3; 7] {
+    let mid = |a: Vec3, b: Vec3| (a + b) * 0.5;
+    let c01 = mid(cp[0], cp[1]);
+    let c12 = mid(cp[1], cp[2]);
+    let c23 = mid(cp[2], cp[3]);
+    let c012 = mid(c01, c12);
+    let c123 = mid(c12, c23);
+    [cp[0], c01, c012, mid(c012, c123), c123, c23, cp[3]]
+}
+
+fn blossom_bezier(cp: &[Vec3; 4], u0: f64, u1: f64, u2: f64) -> Vec3 {
+    let lerp = |t: f64, a: Vec3, b: Vec3| a * (1.0 - t) + b * t;
+    let a = [lerp(u0, cp[0], cp[1]), lerp(u0, cp[1], cp[2]), lerp(u0, cp[2], cp[3])];
+    let b = [lerp(u1, a[0], a[1]), lerp(u1, a[1], a[2])];
+    lerp(u2, b[0], b[1])
+}
+
+// control points of the part of the curve between u0 and u1
+fn sub_bezier(cp: &[Vec3; 4], u0: f64, u1: f64) -> [Vec3; 4] {
+    [
+        blossom_bezier(cp, u0, u0, u0),
+        blossom_bezier(cp, u0, u0, u1),
+        blossom_bezier(cp, u0, u1, u1),
+        blossom_bezier(cp, u1, u1, u1),
+    ]
+}
+
+// long curves are cut in 2^split_depth pieces so that the BVH boxes stay tight around them
+pub const CURVE_SPLIT_DEPTH: u32 = 3;
+
+// collects curves sharing one material, then builds a CurveBVH
+pub struct CurveList {
+    pub curves: Vec<Curve>,
+    pub material: Arc<dyn MaterialTrait + Send + Sync>,
+    pub split_depth: u32,
+}
+
+impl CurveList {
+    pub fn new(material: Arc<dyn MaterialTrait + Send + Sync>) -> CurveList {
+        CurveList {
+            curves: Vec::new(),
+            material,
+            split_depth: CURVE_SPLIT_DEPTH,
+        }
+    }
+
+    pub fn add(&mut self, curve: Curve) {
+        self.curves.push(curve);
+    }
+
+    // uniform cubic B-spline through the control points with a width per point,
+    // added as one Bezier segment per span
+    pub fn add_bspline(&mut self, points: &[Vec3], widths: &[f64], curve_type: CurveType) {
+        assert!(points.len() >= 4 && widths.len() == points.len(), "a B-spline needs at least 4 points and one width per point");
+        for i in 0..points.len() - 3 {
+            let p = &points[i..i + 4];
+            let w = &widths[i..i + 4];
+            let cp = [
+                (p[0] + p[1] * 4.0 + p[2]) / 6.0,
+                (p[1] * 4.0 + p[2] * 2.0) / 6.0,
+                (p[1] * 2.0 + p[2] * 4.0) / 6.0,
+                (p[1] + p[2] * 4.0 + p[3]) / 6.0,
+            ];
+            let width0 = (w[0] + 4.0 * w[1] + w[2]) / 6.0;
+            let width1 = (w[1] + 4.0 * w[2] + w[3]) / 6.0;
+            self.curves.push(Curve::new(cp, width0, width1, curve_type));
+        }
+    }
+
+    pub fn build(self) -> CurveBVH {
+        let pieces = 1 << self.split_depth;
+        let mut segments = Vec::with_capacity(self.curves.len() * pieces);
+        let mut bboxes = Vec::with_capacity(self.curves.len() * pieces);
+        for (index, curve) in self.curves.iter().enumerate() {
+            for piece in 0..pieces {
+                let u0 = piece as f64 / pieces as f64;
+                let u1 = (piece + 1) as f64 / pieces as f64;
+                let segment = CurveSegment::new(curve, index, u0, u1);
+                bboxes.push(segment.bbox());
+                segments.push(segment);
+            }
+        }
+
+        let (nodes, ordered, stats) = build_sah(&bboxes, DEFAULT_MAX_LEAF_SIZE);
+        let segments = ordered.iter().map(|&i| segments[i]).collect();
+        CurveBVH {
+            curves: self.curves,
+            segments,
+            material: self.material,
+            nodes,
+            stats,
+        }
+    }
+}
+
+#[derive(Copy, Clone)]
+pub struct CurveSegment {
+    pub curve: usize,
+    pub u0: f64,
+    pub u1: f64,
+    pub cp: [Vec3; 4],
+    // widest point of the piece
+    pub max_width: f64,
+    // how many times the piece is halved before it is treated as a straight line
+    pub max_depth: u32,
+}
+
+impl CurveSegment {
+    fn new(curve: &Curve, index: usize, u0: f64, u1: f64) -> CurveSegment {
+        let cp = sub_bezier(&curve.cp, u0, u1);
+        let max_width = curve.width_at(u0).max(curve.width_at(u1));
+
+        // enough halvings for the pieces to be flat to 5% of the width
+        let curvature = (0..2)
+            .map(|i| (cp[i] - cp[i + 1] * 2.0 + cp[i + 2]).length())
+            .fold(0.0, f64::max);
+        let epsilon = max_width * 0.05;
+        let max_depth = if curvature > 0.0 && epsilon > 0.0 {
+            ((std::f64::consts::SQRT_2 * 6.0 * curvature / (8.0 * epsilon)).log2() / 2.0).clamp(0.0, 10.0) as u32
+        } else {
+            0
+        };
+
+        CurveSegment {
+            curve: index,
+            u0,
+            u1,
+            cp,
+            max_width,
+            max_depth,
+        }
+    }
+
+    fn bbox(&self) -> Aabb {
+        let mut bbox = Aabb::new_from_point(self.cp[0], self.cp[1]);
+        bbox = Aabb::new_from_bbox(bbox, Aabb::new_from_point(self.cp[2], self.cp[3]));
+        Aabb::new(
+            bbox.x.expand(self.max_width),
+            bbox.y.expand(self.max_width),
+            bbox.z.expand(self.max_width),
+        )
+    }
+}
+
+// curves in a SAH BVH over their pieces, intersected in the frame of the ray
+pub struct CurveBVH {
+    pub curves: Vec<Curve>,
+    pub segments: Vec<CurveSegment>,
+    pub material: Arc<dyn MaterialTrait + Send + Sync>,
+    pub nodes: Vec<LinearBVHNode>,
+    pub stats: BVHStats,
+}
+
+// closest hit so far, as (distance along the unit ray direction, u, width at the hit)
+type CurveHit = (f64, f64, f64);
+
+// state of the search through one segment
+struct CurveQuery {
+    ray_dir: Vec3,
+    max_width: f64,
+    z_min: f64,
+    z_max: f64,
+    closest: Option<CurveHit>,
+}
+
+impl CurveBVH {
+    // cp are in the ray frame: the ray starts at the origin and goes along +z
+    fn recursive_intersect(&self, curve: &Curve, cp: &[Vec3; 4], u0: f64, u1: f64, depth: u32, query: &mut CurveQuery) {
+        // the hull of the control points, grown by half the width, has to cover the ray
+        let half_width = 0.5 * query.max_width;
+        let (mut lo, mut hi) = (cp[0], cp[0]);
+        for p in &cp[1..] {
+            lo = Vec3::merge_min(&lo, p);
+            hi = Vec3::merge_max(&hi, p);
+        }
+        if lo.x - half_width > 0.0 || hi.x + half_width < 0.0 || lo.y - half_width > 0.0 || hi.y + half_width < 0.0 {
+            return;
+        }
+        if lo.z - half_width > query.z_max || hi.z + half_width < query.z_min {
+            return;
+        }
+
+        if depth > 0 {
+            let split = subdivide_bezier(cp);
+            let u_mid = 0.5 * (u0 + u1);
+            let first = [split[0], split[1], split[2], split[3]];
+            let second = [split[3], split[4], split[5], split[6]];
+            self.recursive_intersect(curve, &first, u0, u_mid, depth - 1, query);
+            self.recursive_intersect(curve, &second, u_mid, u1, depth - 1, query);
+            return;
+        }
+
+        // the piece is now a line, the ray must pass between the planes perpendicular to its ends
+        let start_edge = (cp[1].y - cp[0].y) * -cp[0].y + cp[0].x * (cp[0].x - cp[1].x);
+        let end_edge = (cp[2].y - cp[3].y) * -cp[3].y + cp[3].x * (cp[3].x - cp[2].x);
+        if start_edge < 0.0 || end_edge < 0.0 {
+            return;
+        }
+        let (sx, sy) = (cp[3].x - cp[0].x, cp[3].y - cp[0].y);
+        let denom = sx * sx + sy * sy;
+        if denom == 0.0 {
+            return;
+        }
+        let w = (-cp[0].x * sx - cp[0].y * sy) / denom;
+        let u = (u0 + (u1 - u0) * w).clamp(u0, u1);
+
+        let mut hit_width = curve.width_at(u);
+        if let Some([n0, n1]) = curve.normal {
+            // an oriented ribbon looks thinner from the side
+            let n = unit_vec(n0 * (1.0 - u) + n1 * u);
+            hit_width *= fabs(n * query.ray_dir);
+        }
+
+        let (pc, _) = eval_bezier(cp, w.clamp(0.0, 1.0));
+        if pc.x * pc.x + pc.y * pc.y > hit_width * hit_width * 0.25 {
+            return;
+        }
+        if pc.z < query.z_min || pc.z > query.z_max {
+            return;
+        }
+        query.z_max = pc.z;
+        query.closest = Some((pc.z, u, hit_width));
+    }
+
+    fn hit_record(&self, curve: &Curve, r: &Ray, hit: CurveHit) -> HitRecord<'_> {
+        let (z, u, hit_width) = hit;
+        let t = z / r.direction().length();
+        let d = unit_vec(r.direction());
+        let point = r.at(t);
+        let (center, dpdu) = eval_bezier(&curve.cp, u);
+        let tangent = unit_vec(dpdu);
+
+        // across the ribbon, and the side of the ribbon that faces the ray
+        let mut across = tangent.cross(d);
+        if across.near_zero() {
+            across = Onb::new(tangent).u;
+        }
+        let across = unit_vec(across);
+        let facing = unit_vec(tangent * (tangent * d) - d);
+        let offset = ((point - center) * across / (0.5 * hit_width).max(1e-12)).clamp(-1.0, 1.0);
+
+        let outward_normal = match (curve.curve_type, curve.normal) {
+            (CurveType::Flat, Some([n0, n1])) => unit_vec(n0 * (1.0 - u) + n1 * u),
+            (CurveType::Flat, None) => facing,
+            // the normal turns from one edge of the ribbon to the other like on a tube
+            (CurveType::Cylinder, _) => facing * (1.0 - offset * offset).sqrt() + across * offset,
+        };
+
+        let mut rec = HitRecord::new(t, point, outward_normal, self.material.as_ref(), true, u, 0.5 * (offset + 1.0));
+        rec.set_face_normal(*r, outward_normal);
+        rec.dpdu = dpdu;
+        rec.dpdv = across * hit_width;
+        rec
+    }
+}
+
+impl Hittable for CurveBVH {
+    fn hit(&self, r: &Ray, ray_t: Interval) -> Option<HitRecord<'_>> {
+        let direction_length = r.direction().length();
+        let frame = Onb::new(r.direction());
+
+        let mut closest: Option<(usize, CurveHit)> = None;
+        traverse_flat_bvh(&self.nodes, r, ray_t, |i, interval| {
+            let segment = &self.segments[i];
+            let curve = &self.curves[segment.curve];
+            let cp = segment.cp.map(|p| frame.to_local(p - r.origin()));
+            let mut query = CurveQuery {
+                ray_dir: frame.w,
+                max_width: segment.max_width,
+                z_min: interval.tmin * direction_length,
+                z_max: interval.tmax * direction_length,
+                closest: None,
+            };
+            self.recursive_intersect(curve, &cp, segment.u0, segment.u1, segment.max_depth, &mut query);
+            let hit = query.closest?;
+            closest = Some((segment.curve, hit));
+            Some(hit.0 / direction_length)
+        });
+
+        let (curve, hit) = closest?;
+        Some(self.hit_record(&self.curves[curve], r, hit))
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        if self.nodes.is_empty() {
+            Aabb::default()
+        } else {
+            self.nodes[0].bbox
+        }
+    }
+
+    fn instancing(self) -> Arc<dyn Hittable + Send + Sync> {
+        Arc::new(self)
+    }
+}
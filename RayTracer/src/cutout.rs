@@ -0,0 +1,75 @@
+use crate::aabb::*;
+use crate::color::*;
+use crate::hitable::*;
+use crate::interval::*;
+use crate::ray::*;
+use crate::texture::*;
+use crate::vec3::*;
+
+use std::sync::Arc;
+
+// gives up on a ray after this many transparent hits in a row, like dense layers of leaves
+const MAX_SKIPPED_HITS: usize = 64;
+
+// makes parts of an object transparent, for leaves, fences or decals
+// opacity is the luminance of the texture at the hit: 0 lets the ray through, 1 stops it,
+// values in between stop it with that probability
+// usage: Cutout::new(leaf_quad, ImageTexture::new_alpha(path).instancing())
+pub struct Cutout {
+    pub object: Arc<dyn Hittable + Send + Sync>,
+    pub opacity: Arc<dyn TextureTrait + Send + Sync>,
+}
+
+impl Cutout {
+    pub fn new(object: Arc<dyn Hittable + Send + Sync>, opacity: Arc<dyn TextureTrait + Send + Sync>) -> Cutout {
+        Cutout { object, opacity }
+    }
+
+    fn is_opaque(&self, r: &Ray, rec: &HitRecord) -> bool {
+        let alpha = luminance(self.opacity.sample(&TexCoord::from_hit(rec)));
+        if alpha >= 1.0 {
+            return true;
+        }
+        if alpha <= 0.0 {
+            return false;
+        }
+        hash_hit(rec.point, r.direction()) < alpha
+    }
+}
+
+// a random number in [0, 1) that is the same every time the same ray hits the same point,
+// so ConstantMedium finds the same boundary on both of its queries
+fn hash_hit(p: Vec3, d: Vec3) -> f64 {
+    let mut h: u64 = 0x9e3779b97f4a7c15;
+    for x in [p.x, p.y, p.z, d.x, d.y, d.z] {
+        h ^= x.to_bits();
+        h = (h ^ (h >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        h = (h ^ (h >> 27)).wrapping_mul(0x94d049bb133111eb);
+        h ^= h >> 31;
+    }
+    (h >> 11) as f64 / (1u64 << 53) as f64
+}
+
+impl Hittable for Cutout {
+    // every ray goes through here, shadows and medium boundaries included,
+    // so a transparent hit is skipped by looking again past it
+    fn hit(&self, r: &Ray, ray_t: Interval) -> Option<HitRecord<'_>> {
+        let mut tmin = ray_t.tmin;
+        for _ in 0..MAX_SKIPPED_HITS {
+            let rec = self.object.hit(r, Interval::new(tmin, ray_t.tmax))?;
+            if self.is_opaque(r, &rec) {
+                return Some(rec);
+            }
+            tmin = rec.t;
+        }
+        None
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.object.bounding_box()
+    }
+
+    fn instancing(self) -> Arc<dyn Hittable + Send + Sync> {
+        Arc::new(self)
+    }
+}
@@ -0,0 +1,106 @@
+use crate::aabb::*;
+use crate::disk::*;
+use crate::hitable::*;
+use crate::interval::*;
+use crate::material::*;
+use crate::onb::*;
+use crate::ray::*;
+use crate::utils::*;
+use crate::vec3::*;
+
+use std::f64::consts::PI;
+use std::sync::Arc;
+
+// cylinder from base to top, with or without its two end caps
+// side: u is the angle around the axis, v the height in [0, 1]
+// caps: u is the angle, v the distance to the axis over the radius
+pub struct Cylinder {
+    pub base: Vec3,
+    pub top: Vec3,
+    pub radius: f64,
+    pub capped: bool,
+    pub material: Arc<dyn MaterialTrait + Send + Sync>,
+    height: f64,
+    onb: Onb,
+    bbox: Aabb,
+}
+
+impl Cylinder {
+    pub fn new(base: Vec3, top: Vec3, radius: f64, material: Arc<dyn MaterialTrait + Send + Sync>) -> Cylinder {
+        let axis = top - base;
+        let onb = Onb::new(axis);
+        Cylinder {
+            base,
+            top,
+            radius,
+            capped: true,
+            material,
+            height: axis.length(),
+            onb,
+            bbox: Aabb::new_from_bbox(disk_bbox(base, axis, radius), disk_bbox(top, axis, radius)),
+        }
+    }
+
+    // an open tube, its inside is seen through the ends
+    pub fn new_uncapped(base: Vec3, top: Vec3, radius: f64, material: Arc<dyn MaterialTrait + Send + Sync>) -> Cylinder {
+        Cylinder {
+            capped: false,
+            ..Self::new(base, top, radius, material)
+        }
+    }
+}
+
+impl Hittable for Cylinder {
+    fn hit(&self, r: &Ray, ray_t: Interval) -> Option<HitRecord<'_>> {
+        // everything in the frame of the axis, z goes from 0 (base) to height (top)
+        let o = self.onb.to_local(r.origin() - self.base);
+        let d = self.onb.to_local(r.direction());
+        // (t, normal, u, v, dpdu, dpdv) in the frame of the axis
+        let mut closest: Option<(f64, Vec3, f64, f64, Vec3, Vec3)> = None;
+        let mut consider = |t: f64, local_normal: Vec3, u: f64, v: f64, dpdu: Vec3, dpdv: Vec3| {
+            if ray_t.surrounds(t) && closest.is_none_or(|c| t < c.0) {
+                closest = Some((t, local_normal, u, v, dpdu, dpdv));
+            }
+        };
+
+        let a = d.x * d.x + d.y * d.y;
+        let b = 2.0 * (o.x * d.x + o.y * d.y);
+        let c = o.x * o.x + o.y * o.y - self.radius * self.radius;
+        let (roots, count) = if a > 1e-12 { solve_quadratic(a, b, c) } else { ([0.0; 2], 0) };
+        for &t in &roots[..count] {
+            let p = o + d * t;
+            if (0.0..=self.height).contains(&p.z) {
+                let dpdu = Vec3::new(-p.y, p.x, 0.0) * (2.0 * PI);
+                consider(t, Vec3::new(p.x, p.y, 0.0) / self.radius, azimuth_uv(p.x, p.y), p.z / self.height, dpdu, Vec3::new(0.0, 0.0, self.height));
+            }
+        }
+
+        if self.capped && fabs(d.z) > 1e-12 {
+            for (z, normal_z) in [(0.0, -1.0), (self.height, 1.0)] {
+                let t = (z - o.z) / d.z;
+                let p = o + d * t;
+                let rho2 = p.x * p.x + p.y * p.y;
+                if rho2 <= self.radius * self.radius {
+                    let (dpdu, dpdv) = cap_dpduv(p, self.radius);
+                    consider(t, Vec3::new(0.0, 0.0, normal_z), azimuth_uv(p.x, p.y), rho2.sqrt() / self.radius, dpdu, dpdv);
+                }
+            }
+        }
+
+        let (t, local_normal, u, v, dpdu, dpdv) = closest?;
+        let normal = self.onb.local_vec(local_normal);
+        let mut rec = HitRecord::new(t, r.at(t), normal, self.material.as_ref(), true, u, v);
+        rec.dpdu = self.onb.local_vec(dpdu);
+        rec.dpdv = self.onb.local_vec(dpdv);
+        rec.set_face_normal(*r, normal);
+        Some(rec)
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.bbox
+    }
+
+    fn instancing(self) -> Arc<dyn Hittable + Send + Sync> {
+        Arc::new(self)
+    }
+}
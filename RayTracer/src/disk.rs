@@ -0,0 +1,123 @@
+use crate::aabb::*;
+use crate::hitable::*;
+use crate::interval::*;
+use crate::material::*;
+use crate::onb::*;
+use crate::ray::*;
+use crate::utils::*;
+use crate::vec3::*;
+
+use std::f64::consts::PI;
+use std::sync::Arc;
+
+// disk, or annulus when inner_radius > 0
+// u is the angle around the normal, v goes from the inner to the outer edge
+pub struct Disk {
+    pub center: Vec3,
+    pub normal: Vec3,
+    pub radius: f64,
+    pub inner_radius: f64,
+    pub material: Arc<dyn MaterialTrait + Send + Sync>,
+    onb: Onb,
+    bbox: Aabb,
+}
+
+impl Disk {
+    pub fn new(center: Vec3, normal: Vec3, radius: f64, material: Arc<dyn MaterialTrait + Send + Sync>) -> Disk {
+        Self::new_annulus(center, normal, 0.0, radius, material)
+    }
+
+    pub fn new_annulus(
+        center: Vec3,
+        normal: Vec3,
+        inner_radius: f64,
+        radius: f64,
+        material: Arc<dyn MaterialTrait + Send + Sync>,
+    ) -> Disk {
+        let onb = Onb::new(normal);
+        Disk {
+            center,
+            normal: onb.w,
+            radius,
+            inner_radius,
+            material,
+            onb,
+            bbox: disk_bbox(center, onb.w, radius),
+        }
+    }
+}
+
+// bounds of a circle of the given radius, on each axis the extent is radius * sin(angle to the normal)
+pub fn disk_bbox(center: Vec3, normal: Vec3, radius: f64) -> Aabb {
+    let n = unit_vec(normal);
+    let extent = Vec3::new(
+        radius * fmax(0.0, 1.0 - n.x * n.x).sqrt(),
+        radius * fmax(0.0, 1.0 - n.y * n.y).sqrt(),
+        radius * fmax(0.0, 1.0 - n.z * n.z).sqrt(),
+    );
+    Aabb::new_from_point(center - extent, center + extent)
+}
+
+// angle around w in [0, 1)
+pub fn azimuth_uv(x: f64, y: f64) -> f64 {
+    let phi = y.atan2(x);
+    if phi < 0.0 {
+        (phi + 2.0 * PI) / (2.0 * PI)
+    } else {
+        phi / (2.0 * PI)
+    }
+}
+
+// tangents of a cap at local point p, with u = azimuth_uv and v = distance from the center / radius
+pub fn cap_dpduv(p: Vec3, radius: f64) -> (Vec3, Vec3) {
+    let rho = (p.x * p.x + p.y * p.y).sqrt();
+    let dpdu = Vec3::new(-p.y, p.x, 0.0) * (2.0 * PI);
+    let dpdv = if rho > 0.0 { Vec3::new(p.x, p.y, 0.0) * (radius / rho) } else { Vec3::zero() };
+    (dpdu, dpdv)
+}
+
+impl Hittable for Disk {
+    fn hit(&self, r: &Ray, ray_t: Interval) -> Option<HitRecord<'_>> {
+        let denom = self.normal * r.direction();
+        if fabs(denom) < 1e-8 {
+            return None;
+        }
+        let t = (self.normal * (self.center - r.origin())) / denom;
+        if !ray_t.surrounds(t) {
+            return None;
+        }
+        let point = r.at(t);
+        let local = self.onb.to_local(point - self.center);
+        let rho2 = local.x * local.x + local.y * local.y;
+        if rho2 > self.radius * self.radius || rho2 < self.inner_radius * self.inner_radius {
+            return None;
+        }
+
+        let rho = rho2.sqrt();
+        let mut rec = HitRecord::new(
+            t,
+            point,
+            self.normal,
+            self.material.as_ref(),
+            true,
+            azimuth_uv(local.x, local.y),
+            (rho - self.inner_radius) / (self.radius - self.inner_radius),
+        );
+        rec.dpdu = self.onb.local(-local.y, local.x, 0.0) * (2.0 * PI);
+        rec.dpdv = if rho > 0.0 {
+            self.onb.local(local.x, local.y, 0.0) * ((self.radius - self.inner_radius) / rho)
+        } else {
+            Vec3::zero()
+        };
+        rec.set_face_normal(*r, self.normal);
+        Some(rec)
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.bbox
+    }
+
+    fn instancing(self) -> Arc<dyn Hittable + Send + Sync> {
+        Arc::new(self)
+    }
+}
@@ -0,0 +1,199 @@
+use crate::camera::*;
+use crate::color::*;
+use crate::mesh::*;
+use crate::texture::*;
+use crate::utils::*;
+use crate::vec3::*;
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+// when Displacement splits an edge, measured in the space of the mesh positions
+#[derive(Copy, Clone, Debug)]
+pub enum EdgeTolerance {
+    // edges longer than this are split
+    Length(f64),
+    // edges that would cover more than this many pixels seen from eye are split;
+    // pixel_angle is the size of a pixel at distance 1
+    Screen { eye: Vec3, pixel_angle: f64, pixels: f64 },
+}
+
+impl EdgeTolerance {
+    // for a mesh placed by an Instance, eye has to be moved into object space instead
+    pub fn screen(camera: &Camera, pixels: f64) -> Self {
+        let pixel_angle = 2.0 * (camera.vfov.to_radians() / 2.0).tan() / camera.height as f64;
+        EdgeTolerance::Screen {
+            eye: camera.look_from,
+            pixel_angle,
+            pixels,
+        }
+    }
+
+    fn too_long(&self, a: Vec3, b: Vec3) -> bool {
+        let length = (b - a).length();
+        match *self {
+            EdgeTolerance::Length(max_length) => length > max_length,
+            EdgeTolerance::Screen { eye, pixel_angle, pixels } => {
+                let distance = ((a + b) * 0.5 - eye).length().max(1e-8);
+                length > pixels * pixel_angle * distance
+            }
+        }
+    }
+}
+
+// true displacement: the mesh is split until its edges meet the tolerance, then every vertex
+// is moved along its normal by (height - midlevel) * scale, height being the luminance of the texture
+// usage: Displacement::new(height, 0.1, EdgeTolerance::screen(&camera, 1.0)).midlevel(0.5)
+pub struct Displacement {
+    height: Arc<dyn TextureTrait + Send + Sync>,
+    scale: f64,
+    midlevel: f64,
+    tolerance: EdgeTolerance,
+    // every level splits each triangle in up to 4
+    max_level: usize,
+}
+
+impl Displacement {
+    pub fn new(height: Arc<dyn TextureTrait + Send + Sync>, scale: f64, tolerance: EdgeTolerance) -> Self {
+        Self {
+            height,
+            scale,
+            midlevel: 0.0,
+            tolerance,
+            max_level: 8,
+        }
+    }
+
+    pub fn midlevel(mut self, midlevel: f64) -> Self {
+        self.midlevel = midlevel;
+        self
+    }
+
+    pub fn max_level(mut self, max_level: usize) -> Self {
+        self.max_level = max_level;
+        self
+    }
+
+    // takes and returns the arguments of TriangleMesh::new; the result always has normals,
+    // recomputed from the displaced surface
+    pub fn apply(&self, mesh: MeshData) -> MeshData {
+        let (positions, indices, normals, uvs) = mesh;
+        let normals = if normals.is_empty() { smooth_normals(&positions, &indices) } else { normals };
+        let (positions, indices, normals, uvs) = self.subdivide((positions, indices, normals, uvs));
+
+        // vertices that share a position (uv seams, hard edges) move together, or the surface would tear
+        let mut groups: HashMap<[u64; 3], (Vec3, f64, usize)> = HashMap::new();
+        let offsets: Vec<f64> = positions
+            .iter()
+            .enumerate()
+            .map(|(i, &p)| {
+                let (u, v) = if uvs.is_empty() { (0.0, 0.0) } else { uvs[i] };
+                let offset = (luminance(self.height.value(u, v, p)) - self.midlevel) * self.scale;
+                let group = groups.entry(position_key(p)).or_insert((Vec3::zero(), 0.0, 0));
+                group.0 += normals[i];
+                group.1 += offset;
+                group.2 += 1;
+                offset
+            })
+            .collect();
+        let displaced: Vec<Vec3> = positions
+            .iter()
+            .enumerate()
+            .map(|(i, &p)| match groups.get(&position_key(p)) {
+                Some(&(direction, offset, count)) if direction.length() > 1e-12 => p + unit_vec(direction) * (offset / count as f64),
+                _ => p + normals[i] * offsets[i],
+            })
+            .collect();
+
+        let normals = smooth_normals(&displaced, &indices);
+        (displaced, indices, normals, uvs)
+    }
+
+    // an edge is split or not depending only on its end positions, so the triangles on both
+    // sides of it agree and no T-junctions are left
+    fn subdivide(&self, mesh: MeshData) -> MeshData {
+        let (mut positions, mut indices, mut normals, mut uvs) = mesh;
+        for _ in 0..self.max_level {
+            let mut midpoints: HashMap<(usize, usize), usize> = HashMap::new();
+            let mut split = Vec::with_capacity(indices.len() * 2);
+            let mut midpoint = |a: usize, b: usize| -> Option<usize> {
+                if !self.tolerance.too_long(positions[a], positions[b]) {
+                    return None;
+                }
+                let key = (a.min(b), a.max(b));
+                let index = *midpoints.entry(key).or_insert_with(|| {
+                    positions.push((positions[a] + positions[b]) * 0.5);
+                    let n = normals[a] + normals[b];
+                    normals.push(if n.length() > 1e-12 { unit_vec(n) } else { normals[a] });
+                    if !uvs.is_empty() {
+                        uvs.push(((uvs[a].0 + uvs[b].0) * 0.5, (uvs[a].1 + uvs[b].1) * 0.5));
+                    }
+                    positions.len() - 1
+                });
+                Some(index)
+            };
+
+            for &tri in &indices {
+                // mids[k] splits the edge from tri[k] to tri[k + 1]
+                let mids = [0, 1, 2].map(|k| midpoint(tri[k], tri[(k + 1) % 3]));
+                match mids.iter().filter(|m| m.is_some()).count() {
+                    0 => split.push(tri),
+                    1 => {
+                        let k = mids.iter().position(|m| m.is_some()).unwrap();
+                        let (a, b, c) = (tri[k], tri[(k + 1) % 3], tri[(k + 2) % 3]);
+                        let m = mids[k].unwrap();
+                        split.push([a, m, c]);
+                        split.push([m, b, c]);
+                    }
+                    2 => {
+                        // a to b is the edge that stays whole
+                        let k = mids.iter().position(|m| m.is_none()).unwrap();
+                        let (a, b, c) = (tri[k], tri[(k + 1) % 3], tri[(k + 2) % 3]);
+                        let (m_bc, m_ca) = (mids[(k + 1) % 3].unwrap(), mids[(k + 2) % 3].unwrap());
+                        split.push([a, b, m_bc]);
+                        split.push([a, m_bc, m_ca]);
+                        split.push([m_ca, m_bc, c]);
+                    }
+                    _ => {
+                        let (m_ab, m_bc, m_ca) = (mids[0].unwrap(), mids[1].unwrap(), mids[2].unwrap());
+                        split.push([tri[0], m_ab, m_ca]);
+                        split.push([m_ab, tri[1], m_bc]);
+                        split.push([m_ca, m_bc, tri[2]]);
+                        split.push([m_ab, m_bc, m_ca]);
+                    }
+                }
+            }
+
+            let done = split.len() == indices.len();
+            indices = split;
+            if done {
+                break;
+            }
+        }
+        (positions, indices, normals, uvs)
+    }
+}
+
+// + 0.0 turns -0.0 into 0.0
+fn position_key(p: Vec3) -> [u64; 3] {
+    [(p.x + 0.0).to_bits(), (p.y + 0.0).to_bits(), (p.z + 0.0).to_bits()]
+}
+
+// area weighted, shared by all the vertices at the same position
+fn smooth_normals(positions: &[Vec3], indices: &[[usize; 3]]) -> Vec<Vec3> {
+    let mut sums: HashMap<[u64; 3], Vec3> = HashMap::new();
+    for tri in indices {
+        let [p0, p1, p2] = tri.map(|i| positions[i]);
+        let face = (p1 - p0).cross(p2 - p0);
+        for p in [p0, p1, p2] {
+            *sums.entry(position_key(p)).or_insert(Vec3::zero()) += face;
+        }
+    }
+    positions
+        .iter()
+        .map(|&p| {
+            let n = sums.get(&position_key(p)).copied().unwrap_or(Vec3::zero());
+            if n.length() > 1e-12 { unit_vec(n) } else { Vec3::new(0.0, 1.0, 0.0) }
+        })
+        .collect()
+}
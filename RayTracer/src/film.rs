@@ -0,0 +1,108 @@
+use crate::color::*;
+use crate::filter::*;
+use crate::vec3::*;
+
+use image::{ImageBuffer, RgbImage};
+
+// weighted film buffer, every pixel keeps sum(w * L) and sum(w)
+pub struct Film {
+    pub width: usize,
+    pub height: usize,
+    pub x_min: usize,
+    pub y_min: usize,
+    pub color_sum: Vec<Vec3>,
+    pub weight_sum: Vec<f64>,
+}
+
+impl Film {
+    pub fn new(width: usize, height: usize) -> Film {
+        Self::new_tile(0, 0, width, height)
+    }
+
+    // a film covering [x_min, x_min + width) x [y_min, y_min + height) of the image
+    pub fn new_tile(x_min: usize, y_min: usize, width: usize, height: usize) -> Film {
+        Film {
+            width,
+            height,
+            x_min,
+            y_min,
+            color_sum: vec![Vec3::zero(); width * height],
+            weight_sum: vec![0.0; width * height],
+        }
+    }
+
+    // tile around [x_min, x_max) x [y_min, y_max) grown by the filter radius and clipped to the image
+    pub fn new_tile_for(
+        filter: &(dyn FilterTrait + Send + Sync),
+        image_width: usize,
+        image_height: usize,
+        x_min: usize,
+        x_max: usize,
+        y_min: usize,
+        y_max: usize,
+    ) -> Film {
+        let margin = filter.radius().ceil() as usize;
+        let x0 = x_min.saturating_sub(margin);
+        let y0 = y_min.saturating_sub(margin);
+        let x1 = (x_max + margin).min(image_width);
+        let y1 = (y_max + margin).min(image_height);
+        Self::new_tile(x0, y0, x1 - x0, y1 - y0)
+    }
+
+    // (x, y) is the continuous image position of the sample, pixel centers sit at +0.5
+    pub fn add_sample(&mut self, x: f64, y: f64, color: Vec3, filter: &(dyn FilterTrait + Send + Sync)) {
+        let radius = filter.radius();
+        let x0 = ((x - 0.5 - radius).ceil() as i64).max(self.x_min as i64);
+        let x1 = ((x - 0.5 + radius).floor() as i64).min((self.x_min + self.width) as i64 - 1);
+        let y0 = ((y - 0.5 - radius).ceil() as i64).max(self.y_min as i64);
+        let y1 = ((y - 0.5 + radius).floor() as i64).min((self.y_min + self.height) as i64 - 1);
+
+        for j in y0..=y1 {
+            for i in x0..=x1 {
+                let weight = filter.evaluate(i as f64 + 0.5 - x, j as f64 + 0.5 - y);
+                if weight == 0.0 {
+                    continue;
+                }
+                let index = (j as usize - self.y_min) * self.width + (i as usize - self.x_min);
+                self.color_sum[index] += color * weight;
+                self.weight_sum[index] += weight;
+            }
+        }
+    }
+
+    // accumulate a tile rendered by another thread
+    pub fn merge(&mut self, tile: &Film) {
+        for j in 0..tile.height {
+            for i in 0..tile.width {
+                let (x, y) = (tile.x_min + i, tile.y_min + j);
+                if x < self.x_min || y < self.y_min || x >= self.x_min + self.width || y >= self.y_min + self.height {
+                    continue;
+                }
+                let index = (y - self.y_min) * self.width + (x - self.x_min);
+                self.color_sum[index] += tile.color_sum[j * tile.width + i];
+                self.weight_sum[index] += tile.weight_sum[j * tile.width + i];
+            }
+        }
+    }
+
+    pub fn pixel_color(&self, i: usize, j: usize) -> Vec3 {
+        let index = (j - self.y_min) * self.width + (i - self.x_min);
+        let weight = self.weight_sum[index];
+        // negative lobes (Mitchell, Lanczos) may cancel out the weights
+        if weight.abs() < 1e-12 {
+            Vec3::zero()
+        } else {
+            self.color_sum[index] / weight
+        }
+    }
+
+    pub fn to_image(&self) -> RgbImage {
+        let mut img: RgbImage = ImageBuffer::new(self.width as u32, self.height as u32);
+        for j in 0..self.height {
+            for i in 0..self.width {
+                write_color(self.pixel_color(self.x_min + i, self.y_min + j), &mut img, i, j);
+            }
+        }
+        img
+    }
+}
@@ -0,0 +1,189 @@
+use std::f64::consts::PI;
+use std::sync::Arc;
+
+// pixel reconstruction filters, x and y are offsets (in pixels) from the pixel center
+pub trait FilterTrait {
+    fn radius(&self) -> f64;
+    fn evaluate(&self, x: f64, y: f64) -> f64;
+    fn instancing(self) -> Arc<dyn FilterTrait + Send + Sync>;
+}
+
+pub struct BoxFilter {
+    pub radius: f64,
+}
+
+impl BoxFilter {
+    pub fn new(radius: f64) -> Self {
+        Self { radius }
+    }
+}
+
+impl FilterTrait for BoxFilter {
+    fn radius(&self) -> f64 {
+        self.radius
+    }
+
+    fn evaluate(&self, x: f64, y: f64) -> f64 {
+        if x.abs() <= self.radius && y.abs() <= self.radius {
+            1.0
+        } else {
+            0.0
+        }
+    }
+
+    fn instancing(self) -> Arc<dyn FilterTrait + Send + Sync> {
+        Arc::new(self)
+    }
+}
+
+pub struct TentFilter {
+    pub radius: f64,
+}
+
+impl TentFilter {
+    pub fn new(radius: f64) -> Self {
+        Self { radius }
+    }
+}
+
+impl FilterTrait for TentFilter {
+    fn radius(&self) -> f64 {
+        self.radius
+    }
+
+    fn evaluate(&self, x: f64, y: f64) -> f64 {
+        (self.radius - x.abs()).max(0.0) * (self.radius - y.abs()).max(0.0)
+    }
+
+    fn instancing(self) -> Arc<dyn FilterTrait + Send + Sync> {
+        Arc::new(self)
+    }
+}
+
+pub struct GaussianFilter {
+    pub radius: f64,
+    pub alpha: f64,
+    exp_r: f64,
+}
+
+impl GaussianFilter {
+    pub fn new(radius: f64, alpha: f64) -> Self {
+        Self {
+            radius,
+            alpha,
+            exp_r: (-alpha * radius * radius).exp(),
+        }
+    }
+
+    // subtract the value at the radius so that the filter falls to 0 at its edge
+    fn gaussian(&self, d: f64) -> f64 {
+        ((-self.alpha * d * d).exp() - self.exp_r).max(0.0)
+    }
+}
+
+impl FilterTrait for GaussianFilter {
+    fn radius(&self) -> f64 {
+        self.radius
+    }
+
+    fn evaluate(&self, x: f64, y: f64) -> f64 {
+        self.gaussian(x) * self.gaussian(y)
+    }
+
+    fn instancing(self) -> Arc<dyn FilterTrait + Send + Sync> {
+        Arc::new(self)
+    }
+}
+
+pub struct MitchellFilter {
+    pub radius: f64,
+    pub b: f64,
+    pub c: f64,
+}
+
+impl MitchellFilter {
+    pub fn new(radius: f64, b: f64, c: f64) -> Self {
+        Self { radius, b, c }
+    }
+
+    // the recommended B = C = 1/3
+    pub fn new_default(radius: f64) -> Self {
+        Self::new(radius, 1.0 / 3.0, 1.0 / 3.0)
+    }
+
+    // x is remapped to [-2, 2] over the filter radius
+    fn mitchell(&self, x: f64) -> f64 {
+        let x = (2.0 * x / self.radius).abs();
+        let (b, c) = (self.b, self.c);
+        if x > 2.0 {
+            0.0
+        } else if x > 1.0 {
+            ((-b - 6.0 * c) * x * x * x
+                + (6.0 * b + 30.0 * c) * x * x
+                + (-12.0 * b - 48.0 * c) * x
+                + (8.0 * b + 24.0 * c))
+                / 6.0
+        } else {
+            ((12.0 - 9.0 * b - 6.0 * c) * x * x * x
+                + (-18.0 + 12.0 * b + 6.0 * c) * x * x
+                + (6.0 - 2.0 * b))
+                / 6.0
+        }
+    }
+}
+
+impl FilterTrait for MitchellFilter {
+    fn radius(&self) -> f64 {
+        self.radius
+    }
+
+    fn evaluate(&self, x: f64, y: f64) -> f64 {
+        self.mitchell(x) * self.mitchell(y)
+    }
+
+    fn instancing(self) -> Arc<dyn FilterTrait + Send + Sync> {
+        Arc::new(self)
+    }
+}
+
+pub struct LanczosFilter {
+    pub radius: f64,
+    pub tau: f64,
+}
+
+impl LanczosFilter {
+    pub fn new(radius: f64, tau: f64) -> Self {
+        Self { radius, tau }
+    }
+
+    fn sinc(x: f64) -> f64 {
+        if x.abs() < 1e-5 {
+            1.0
+        } else {
+            (PI * x).sin() / (PI * x)
+        }
+    }
+
+    // sinc windowed by a wider sinc
+    fn windowed_sinc(&self, x: f64) -> f64 {
+        if x.abs() > self.radius {
+            0.0
+        } else {
+            Self::sinc(x) * Self::sinc(x / self.tau)
+        }
+    }
+}
+
+impl FilterTrait for LanczosFilter {
+    fn radius(&self) -> f64 {
+        self.radius
+    }
+
+    fn evaluate(&self, x: f64, y: f64) -> f64 {
+        self.windowed_sinc(x) * self.windowed_sinc(y)
+    }
+
+    fn instancing(self) -> Arc<dyn FilterTrait + Send + Sync> {
+        Arc::new(self)
+    }
+}
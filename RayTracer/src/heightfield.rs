@@ -0,0 +1,256 @@
+use crate::aabb::*;
+use crate::hitable::*;
+use crate::interval::*;
+use crate::material::*;
+use crate::mesh::*;
+use crate::ray::*;
+use crate::texture::*;
+use crate::utils::*;
+use crate::vec3::*;
+
+use image::error::{ImageError, ImageResult, ParameterError, ParameterErrorKind};
+use std::path::Path;
+use std::sync::Arc;
+
+// terrain over the rectangle [corner.x, corner.x + size.x] x [corner.z, corner.z + size.z],
+// sample (i, j) of the grid is at height corner.y + size.y * height
+// every cell is two triangles with normals interpolated from the grid, and it is only
+// tested when the ray passes within its height range
+pub struct Heightfield {
+    heights: Vec<f64>,
+    normals: Vec<Vec3>,
+    // (lowest, highest) world height of every cell
+    cell_range: Vec<(f64, f64)>,
+    nx: usize,
+    nz: usize,
+    corner: Vec3,
+    cell_x: f64,
+    cell_z: f64,
+    material: Arc<dyn MaterialTrait + Send + Sync>,
+    bbox: Aabb,
+}
+
+impl Heightfield {
+    // heights in rows of nx samples along x, nz rows along z
+    pub fn new(heights: Vec<f64>, nx: usize, nz: usize, corner: Vec3, size: Vec3, material: Arc<dyn MaterialTrait + Send + Sync>) -> Heightfield {
+        assert!(nx >= 2 && nz >= 2 && heights.len() == nx * nz, "a heightfield needs at least 2 x 2 samples");
+        let heights: Vec<f64> = heights.iter().map(|h| corner.y + h * size.y).collect();
+        let cell_x = size.x / (nx - 1) as f64;
+        let cell_z = size.z / (nz - 1) as f64;
+
+        // central differences, one sided on the borders
+        let mut normals = Vec::with_capacity(nx * nz);
+        for j in 0..nz {
+            for i in 0..nx {
+                let (i0, i1) = (i.saturating_sub(1), (i + 1).min(nx - 1));
+                let (j0, j1) = (j.saturating_sub(1), (j + 1).min(nz - 1));
+                let dhdx = (heights[j * nx + i1] - heights[j * nx + i0]) / ((i1 - i0) as f64 * cell_x);
+                let dhdz = (heights[j1 * nx + i] - heights[j0 * nx + i]) / ((j1 - j0) as f64 * cell_z);
+                normals.push(unit_vec(Vec3::new(-dhdx, 1.0, -dhdz)));
+            }
+        }
+
+        let mut cell_range = Vec::with_capacity((nx - 1) * (nz - 1));
+        let (mut lowest, mut highest) = (f64::INFINITY, f64::NEG_INFINITY);
+        for j in 0..nz - 1 {
+            for i in 0..nx - 1 {
+                let corners = [
+                    heights[j * nx + i],
+                    heights[j * nx + i + 1],
+                    heights[(j + 1) * nx + i],
+                    heights[(j + 1) * nx + i + 1],
+                ];
+                let low = corners.iter().cloned().fold(f64::INFINITY, f64::min);
+                let high = corners.iter().cloned().fold(f64::NEG_INFINITY, f64::max);
+                lowest = lowest.min(low);
+                highest = highest.max(high);
+                cell_range.push((low, high));
+            }
+        }
+
+        let bbox = Aabb::new_from_point(
+            Vec3::new(corner.x, lowest, corner.z),
+            Vec3::new(corner.x + size.x, highest, corner.z + size.z),
+        );
+        Heightfield {
+            heights,
+            normals,
+            cell_range,
+            nx,
+            nz,
+            corner,
+            cell_x,
+            cell_z,
+            material,
+            bbox,
+        }
+    }
+
+    // a grayscale (or converted to grayscale) image, black is 0 and white is 1 before scaling
+    // 16-bit and float images keep their precision; fails if the image cannot be read or is
+    // narrower than 2 x 2
+    pub fn new_from_image(path: &Path, corner: Vec3, size: Vec3, material: Arc<dyn MaterialTrait + Send + Sync>) -> ImageResult<Heightfield> {
+        let img = open_image(path)?.to_luma32f();
+        let (nx, nz) = (img.width() as usize, img.height() as usize);
+        if nx < 2 || nz < 2 {
+            return Err(ImageError::Parameter(ParameterError::from_kind(ParameterErrorKind::DimensionMismatch)));
+        }
+        let heights = img.pixels().map(|p| p[0] as f64).collect();
+        Ok(Self::new(heights, nx, nz, corner, size, material))
+    }
+
+    fn vertex(&self, i: usize, j: usize) -> Vec3 {
+        Vec3::new(
+            self.corner.x + i as f64 * self.cell_x,
+            self.heights[j * self.nx + i],
+            self.corner.z + j as f64 * self.cell_z,
+        )
+    }
+
+    // the two triangles of cell (i, j), nearest hit as (t, geometric normal, shading normal)
+    fn hit_cell(&self, r: &Ray, ray_t: Interval, i: usize, j: usize) -> Option<(f64, Vec3, Vec3)> {
+        let ids = [(i, j), (i + 1, j), (i, j + 1), (i + 1, j + 1)];
+        let mut closest: Option<(f64, Vec3, Vec3)> = None;
+        // both wound so that the geometric normal points up
+        for triangle in [[0, 2, 1], [1, 2, 3]] {
+            let [a, b, c] = triangle.map(|k| ids[k]);
+            let (pa, pb, pc) = (self.vertex(a.0, a.1), self.vertex(b.0, b.1), self.vertex(c.0, c.1));
+            let tmax = closest.map_or(ray_t.tmax, |(t, _, _)| t);
+            let Some((t, b1, b2)) = intersect_triangle(r, Interval::new(ray_t.tmin, tmax), pa, pb, pc) else {
+                continue;
+            };
+            let shading = self.normals[a.1 * self.nx + a.0] * (1.0 - b1 - b2)
+                + self.normals[b.1 * self.nx + b.0] * b1
+                + self.normals[c.1 * self.nx + c.0] * b2;
+            closest = Some((t, unit_vec((pb - pa).cross(pc - pa)), unit_vec(shading)));
+        }
+        closest
+    }
+}
+
+impl Hittable for Heightfield {
+    fn hit(&self, r: &Ray, ray_t: Interval) -> Option<HitRecord<'_>> {
+        let clipped = self.bbox.clip(r, ray_t)?;
+
+        // the ray in cell units on the ground
+        let (cells_x, cells_z) = (self.nx - 1, self.nz - 1);
+        let ox = (r.origin().x - self.corner.x) / self.cell_x;
+        let oz = (r.origin().z - self.corner.z) / self.cell_z;
+        let dx = r.direction().x / self.cell_x;
+        let dz = r.direction().z / self.cell_z;
+
+        let cell_of = |p: f64, cells: usize| (p.floor().max(0.0) as usize).min(cells - 1);
+        let mut i = cell_of(ox + dx * clipped.tmin, cells_x);
+        let mut j = cell_of(oz + dz * clipped.tmin, cells_z);
+
+        // ray parameter of the next cell border on each axis, and between two borders
+        let next_border = |o: f64, d: f64, cell: usize| {
+            if d > 0.0 {
+                ((cell + 1) as f64 - o) / d
+            } else if d < 0.0 {
+                (cell as f64 - o) / d
+            } else {
+                f64::INFINITY
+            }
+        };
+        let mut t_next_x = next_border(ox, dx, i);
+        let mut t_next_z = next_border(oz, dz, j);
+        let t_delta_x = if dx != 0.0 { fabs(1.0 / dx) } else { f64::INFINITY };
+        let t_delta_z = if dz != 0.0 { fabs(1.0 / dz) } else { f64::INFINITY };
+
+        let mut t_enter = clipped.tmin;
+        loop {
+            let t_exit = t_next_x.min(t_next_z).min(clipped.tmax);
+            let (low, high) = self.cell_range[j * cells_x + i];
+            let y0 = r.origin().y + r.direction().y * t_enter;
+            let y1 = r.origin().y + r.direction().y * t_exit;
+            if y0.min(y1) <= high && y0.max(y1) >= low {
+                if let Some((t, geometric_normal, shading)) = self.hit_cell(r, ray_t, i, j) {
+                    let point = r.at(t);
+                    // u runs along x and v against z, the slopes come from the shading normal
+                    let (width, depth) = (self.cell_x * cells_x as f64, self.cell_z * cells_z as f64);
+                    let u = (point.x - self.corner.x) / width;
+                    let v = 1.0 - (point.z - self.corner.z) / depth;
+                    let mut rec = HitRecord::new(t, point, geometric_normal, self.material.as_ref(), true, u, v);
+                    if fabs(shading.y) > 1e-8 {
+                        rec.dpdu = Vec3::new(1.0, -shading.x / shading.y, 0.0) * width;
+                        rec.dpdv = Vec3::new(0.0, -shading.z / shading.y, 1.0) * -depth;
+                    }
+                    rec.set_face_normal(*r, geometric_normal);
+                    // shading normal, flipped to the side of the geometric one like in TriangleMesh
+                    rec.normal = if shading * rec.normal < 0.0 { shading * -1.0 } else { shading };
+                    return Some(rec);
+                }
+            }
+
+            if t_exit >= clipped.tmax {
+                return None;
+            }
+            if t_next_x < t_next_z {
+                if (dx > 0.0 && i + 1 >= cells_x) || (dx < 0.0 && i == 0) {
+                    return None;
+                }
+                i = if dx > 0.0 { i + 1 } else { i - 1 };
+                t_next_x += t_delta_x;
+            } else {
+                if (dz > 0.0 && j + 1 >= cells_z) || (dz < 0.0 && j == 0) {
+                    return None;
+                }
+                j = if dz > 0.0 { j + 1 } else { j - 1 };
+                t_next_z += t_delta_z;
+            }
+            t_enter = t_exit;
+        }
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.bbox
+    }
+
+    fn instancing(self) -> Arc<dyn Hittable + Send + Sync> {
+        Arc::new(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{GrayImage, Luma};
+
+    fn white() -> Arc<dyn MaterialTrait + Send + Sync> {
+        Lambertian::new_from_color(Vec3::ones()).instancing()
+    }
+
+    fn save_gray(name: &str, img: &GrayImage) -> std::path::PathBuf {
+        let path = std::env::temp_dir().join(format!("ray_tracer_{}_{}", std::process::id(), name));
+        img.save(&path).unwrap();
+        path
+    }
+
+    #[test]
+    fn unreadable_images_are_errors() {
+        let missing = Heightfield::new_from_image(Path::new("no_such_heightfield.png"), Vec3::zero(), Vec3::ones(), white());
+        assert!(missing.is_err());
+
+        let path = save_gray("heightfield_1x3.png", &GrayImage::new(1, 3));
+        let narrow = Heightfield::new_from_image(&path, Vec3::zero(), Vec3::ones(), white());
+        let _ = std::fs::remove_file(&path);
+        assert!(narrow.is_err());
+    }
+
+    #[test]
+    fn image_brightness_is_height() {
+        // a ramp from black at x = 0 to white at x = 1
+        let mut img = GrayImage::new(2, 2);
+        img.put_pixel(1, 0, Luma([255]));
+        img.put_pixel(1, 1, Luma([255]));
+        let path = save_gray("heightfield_ramp.png", &img);
+        let terrain = Heightfield::new_from_image(&path, Vec3::zero(), Vec3::new(1.0, 2.0, 1.0), white());
+        let _ = std::fs::remove_file(&path);
+        let terrain = terrain.unwrap();
+
+        let ray = Ray::new(Vec3::new(0.25, 10.0, 0.5), Vec3::new(0.0, -1.0, 0.0), 0.0);
+        let rec = terrain.hit(&ray, Interval::new(0.001, f64::INFINITY)).unwrap();
+        assert!((rec.point.y - 0.5).abs() < 1e-9, "{:?}", rec.point);
+    }
+}
@@ -2,31 +2,63 @@ use crate::{ray::Ray, vec3::Vec3};
 use crate::interval::*;
 use crate::material::*;
 use crate::aabb::*;
+#[cfg(not(feature = "simd"))]
 use crate::bvh::*;
+#[cfg(feature = "simd")]
+use crate::simd::*;
 use crate::utils::*;
 use crate::texture::*;
 
 use std::sync::Arc;
 use std::f64::consts::{PI, E};
 
-pub struct HitRecord {
+// the material is borrowed from the object that was hit, so a record is copied without touching
+// any refcount
+#[derive(Copy, Clone)]
+pub struct HitRecord<'a> {
     pub t: f64,
     pub point: Vec3,
     pub normal: Vec3,
-    pub material: Arc<dyn MaterialTrait + Send + Sync>,
+    pub material: &'a (dyn MaterialTrait + Send + Sync),
     pub front_face: bool,
     pub u: f64,
     pub v: f64,
+    // derivatives of the point along u and v, zero when the primitive does not provide them;
+    // they follow the uv the primitive reports and are never flipped for back faces, materials
+    // that need a side (like BumpMapMaterial) take it from front_face
+    pub dpdu: Vec3,
+    pub dpdv: Vec3,
+    // derivatives of the normal along u and v, zero for flat primitives and for the curved ones
+    // that do not provide them (only spheres do)
+    pub dndu: Vec3,
+    pub dndv: Vec3,
+    // per point color of a point cloud, it tints the albedo of the diffuse materials
+    pub color: Option<Vec3>,
+    // pixel footprint at the point, set by compute_differentials for rays that carry differentials
+    pub differentials: Option<SurfaceDifferentials>,
+}
+
+// how the point and the texture coordinates change from one pixel to the next
+#[derive(Copy, Clone, Debug)]
+pub struct SurfaceDifferentials {
+    pub dpdx: Vec3,
+    pub dpdy: Vec3,
+    pub dudx: f64,
+    pub dvdx: f64,
+    pub dudy: f64,
+    pub dvdy: f64,
+    pub dndx: Vec3,
+    pub dndy: Vec3,
 }
 
 pub trait Hittable {
-    fn hit(&self, r: &Ray, ray_t: Interval, rec: &mut HitRecord) -> bool;
+    fn hit(&self, r: &Ray, ray_t: Interval) -> Option<HitRecord<'_>>;
     fn bounding_box(&self) -> Aabb;
     fn instancing(self) -> Arc<dyn Hittable + Send + Sync>; 
 }
 
-impl HitRecord {
-    pub fn new(t: f64, point: Vec3, normal: Vec3, material: Arc<dyn MaterialTrait + Send + Sync>, front_face: bool, u: f64, v: f64) -> HitRecord {
+impl<'a> HitRecord<'a> {
+    pub fn new(t: f64, point: Vec3, normal: Vec3, material: &'a (dyn MaterialTrait + Send + Sync), front_face: bool, u: f64, v: f64) -> HitRecord<'a> {
         HitRecord {
             t,
             point,
@@ -35,19 +67,62 @@ impl HitRecord {
             front_face,
             u,
             v,
+            dpdu: Vec3::zero(),
+            dpdv: Vec3::zero(),
+            dndu: Vec3::zero(),
+            dndv: Vec3::zero(),
+            color: None,
+            differentials: None,
         }
     }
 
-    pub fn default() -> Self {
-        HitRecord {
-            t: 0.0,
-            point: Vec3::zero(),
-            normal: Vec3::zero(),
-            material: Lambertian::new_from_color(Vec3::zero()).instancing(),
-            front_face: false,
-            u: 0.0,
-            v: 0.0,
+    pub fn tint(&self, albedo: Vec3) -> Vec3 {
+        match self.color {
+            Some(color) => dot(albedo, color),
+            None => albedo,
+        }
+    }
+
+    // intersects the offset rays of r with the tangent plane of the hit, and projects the offsets
+    // on dpdu and dpdv to get the uv footprint (zero when the primitive has no dpdu / dpdv)
+    pub fn compute_differentials(&mut self, r: &Ray) {
+        self.differentials = None;
+        let Some(d) = r.differential else {
+            return;
+        };
+        let plane_d = self.normal * self.point;
+        let tx = (plane_d - self.normal * d.rx_origin) / (self.normal * d.rx_direction);
+        let ty = (plane_d - self.normal * d.ry_origin) / (self.normal * d.ry_direction);
+        if !tx.is_finite() || !ty.is_finite() {
+            return;
         }
+        let dpdx = d.rx_origin + d.rx_direction * tx - self.point;
+        let dpdy = d.ry_origin + d.ry_direction * ty - self.point;
+
+        // least squares solution of dpdx = dpdu * dudx + dpdv * dvdx
+        let (a00, a01, a11) = (self.dpdu * self.dpdu, self.dpdu * self.dpdv, self.dpdv * self.dpdv);
+        let det = a00 * a11 - a01 * a01;
+        let solve = |dp: Vec3| {
+            if fabs(det) < 1e-20 {
+                return (0.0, 0.0);
+            }
+            let (b0, b1) = (self.dpdu * dp, self.dpdv * dp);
+            let du = (a11 * b0 - a01 * b1) / det;
+            let dv = (a00 * b1 - a01 * b0) / det;
+            if du.is_finite() && dv.is_finite() { (du.clamp(-1e8, 1e8), dv.clamp(-1e8, 1e8)) } else { (0.0, 0.0) }
+        };
+        let (dudx, dvdx) = solve(dpdx);
+        let (dudy, dvdy) = solve(dpdy);
+        self.differentials = Some(SurfaceDifferentials {
+            dpdx,
+            dpdy,
+            dudx,
+            dvdx,
+            dudy,
+            dvdy,
+            dndx: self.dndu * dudx + self.dndv * dvdx,
+            dndy: self.dndu * dudy + self.dndv * dvdy,
+        });
     }
 
     pub fn set_face_normal(&mut self, r: Ray, outward_normal: Vec3) {
@@ -61,15 +136,6 @@ impl HitRecord {
     }
 }
 
-impl Clone for HitRecord {
-    fn clone(&self) -> Self {
-      HitRecord {
-        material: self.material.clone(),
-        ..*self
-      }
-    }
-}
-
 pub struct Hittable_list {
     pub objects: Vec<Arc<dyn Hittable + Send + Sync>>,
     pub bbox: Aabb,
@@ -99,25 +165,34 @@ impl Hittable_list {
         self.objects.push(object);
     }
 
+    #[cfg(not(feature = "simd"))]
     pub fn to_bvh(&mut self) -> Arc<dyn Hittable + Send + Sync> {
-        (BVHnode::new(self)).instancing()
+        let bvh = FlatBVH::new(self);
+        bvh.stats.info();
+        bvh.instancing()
+    }
+
+    // 4-wide f32 boxes
+    #[cfg(feature = "simd")]
+    pub fn to_bvh(&mut self) -> Arc<dyn Hittable + Send + Sync> {
+        let bvh = BVH4::new(self);
+        bvh.stats.info();
+        bvh.instancing()
     }
 }
 
 impl Hittable for Hittable_list {
-    fn hit(&self, ray: &Ray, ray_t: Interval, rec: &mut HitRecord) -> bool {
-        let mut rec_tmp = HitRecord::default();
+    fn hit(&self, ray: &Ray, ray_t: Interval) -> Option<HitRecord<'_>> {
         let mut closest_so_far = ray_t.tmax;
-        let mut hit_anything = false;
+        let mut closest = None;
 
         for object in &self.objects {
-            if object.hit(&ray, Interval::new(ray_t.tmin, closest_so_far), &mut rec_tmp) {
-                hit_anything =  true;
-                closest_so_far = rec_tmp.t;
-                *rec = rec_tmp.clone();
+            if let Some(rec) = object.hit(ray, Interval::new(ray_t.tmin, closest_so_far)) {
+                closest_so_far = rec.t;
+                closest = Some(rec);
             }
         }
-        hit_anything
+        closest
     }
 
     fn bounding_box(&self) -> Aabb {
@@ -148,13 +223,11 @@ impl Translate {
 }
 
 impl Hittable for Translate {
-    fn hit(&self, ray: &Ray, ray_t: Interval, rec: &mut HitRecord) -> bool {
+    fn hit(&self, ray: &Ray, ray_t: Interval) -> Option<HitRecord<'_>> {
        let offset_ray = Ray::new(ray.origin() - self.offset, ray.direction(), ray.time());
-       if !self.objects.hit(&offset_ray, ray_t, rec) {
-            return false;
-        }
+       let mut rec = self.objects.hit(&offset_ray, ray_t)?;
         rec.point += self.offset;
-        true
+        Some(rec)
     }
 
     fn bounding_box(&self) -> Aabb {
@@ -216,7 +289,7 @@ impl RotateY {
 }
 
 impl Hittable for RotateY {
-    fn hit(&self, ray: &Ray, ray_t: Interval, rec: &mut HitRecord) -> bool {
+    fn hit(&self, ray: &Ray, ray_t: Interval) -> Option<HitRecord<'_>> {
         let mut origin = ray.origin();
         origin = Vec3::new(
             self.cos_theta * origin.x - self.sin_theta * origin.z,
@@ -233,9 +306,7 @@ impl Hittable for RotateY {
     
         let rotated_ray = Ray::new(origin, direction, ray.time());
     
-        if !self.objects.hit(&rotated_ray, ray_t, rec) {
-          return false;
-        }
+        let mut rec = self.objects.hit(&rotated_ray, ray_t)?;
         
         let mut point = rec.point;
         point = Vec3::new(
@@ -251,9 +322,19 @@ impl Hittable for RotateY {
             -self.sin_theta * normal.x + self.cos_theta * normal.z,
         );
 
+        let rotate_back = |a: Vec3| Vec3::new(
+            self.cos_theta * a.x + self.sin_theta * a.z,
+            a.y,
+            -self.sin_theta * a.x + self.cos_theta * a.z,
+        );
+
         rec.point = point;
         rec.normal = normal;
-        true
+        rec.dpdu = rotate_back(rec.dpdu);
+        rec.dpdv = rotate_back(rec.dpdv);
+        rec.dndu = rotate_back(rec.dndu);
+        rec.dndv = rotate_back(rec.dndv);
+        Some(rec)
     }
 
     fn bounding_box(&self) -> Aabb {
@@ -286,24 +367,15 @@ impl ConstantMedium {
 }
 
 impl Hittable for ConstantMedium {
-    fn hit(&self, ray: &Ray, ray_t: Interval, rec: &mut HitRecord) -> bool {
-        let mut rec1 = HitRecord::default();
-        let mut rec2 = HitRecord::default();
-
-
-        if !self.boundary.hit(ray, Interval::new(core::f64::NEG_INFINITY, core::f64::INFINITY), &mut rec1) {
-            return false;
-        }
-
-        if !self.boundary.hit(ray, Interval::new(rec1.t + 0.0001, core::f64::INFINITY), &mut rec2) {
-            return false;
-        }
+    fn hit(&self, ray: &Ray, ray_t: Interval) -> Option<HitRecord<'_>> {
+        let mut rec1 = self.boundary.hit(ray, Interval::new(core::f64::NEG_INFINITY, core::f64::INFINITY))?;
+        let mut rec2 = self.boundary.hit(ray, Interval::new(rec1.t + 0.0001, core::f64::INFINITY))?;
 
         rec1.t = ray_t.clamp(rec1.t);
         rec2.t = ray_t.clamp(rec2.t);
 
         if rec1.t >= rec2.t {
-            return false;
+            return None;
         }
 
         rec1.t = rec1.t.max(0.0);
@@ -313,13 +385,11 @@ impl Hittable for ConstantMedium {
         let hit_distance = self.neg_inv_density * random_f64_range(0.0001, 1.0 - 0.0001).log(E);
 
         if hit_distance > dis_inside_boundary {
-            return false;
+            return None;
         }
 
-        rec.t = rec1.t + hit_distance / ray_length;
-        rec.point = ray.at(rec.t);
-        rec.material = self.phase_function.clone();
-        true
+        let t = rec1.t + hit_distance / ray_length;
+        Some(HitRecord::new(t, ray.at(t), Vec3::zero(), self.phase_function.as_ref(), false, 0.0, 0.0))
     }
 
     fn bounding_box(&self) -> Aabb {
@@ -0,0 +1,95 @@
+use crate::aabb::*;
+use crate::bvh::*;
+use crate::hitable::*;
+use crate::interval::*;
+use crate::ray::*;
+use crate::transform::*;
+use crate::utils::*;
+
+use std::sync::Arc;
+
+// a placed copy of shared geometry (bottom level), the geometry itself is never duplicated
+// the ray is moved into object space instead, t stays valid because the direction is not normalized
+pub struct Instance {
+    pub object: Arc<dyn Hittable + Send + Sync>,
+    pub transform: Transform,
+    pub bbox: Aabb,
+}
+
+impl Instance {
+    pub fn new(object: Arc<dyn Hittable + Send + Sync>, transform: Transform) -> Instance {
+        let bbox = transform.apply_bbox(&object.bounding_box());
+        Instance {
+            object,
+            transform,
+            bbox,
+        }
+    }
+}
+
+// hits object through transform, shared with AnimatedObject
+pub fn hit_transformed<'a>(object: &'a (dyn Hittable + Send + Sync), transform: &Transform, ray: &Ray, ray_t: Interval) -> Option<HitRecord<'a>> {
+    let local_ray = Ray::new(
+        transform.apply_inverse_point(ray.origin()),
+        transform.apply_inverse_vector(ray.direction()),
+        ray.time(),
+    );
+    let mut rec = object.hit(&local_ray, ray_t)?;
+    // the inverse transpose keeps the normal facing against the ray
+    rec.point = transform.apply_point(rec.point);
+    let normal = transform.apply_normal(rec.normal);
+    let length = normal.length();
+    rec.normal = normal / length;
+    rec.dpdu = transform.apply_vector(rec.dpdu);
+    rec.dpdv = transform.apply_vector(rec.dpdv);
+    // derivative of the normalized normal: only the part across it is left
+    let turn = |dn: Vec3| {
+        let dn = transform.apply_normal(dn) / length;
+        dn - rec.normal * (rec.normal * dn)
+    };
+    rec.dndu = turn(rec.dndu);
+    rec.dndv = turn(rec.dndv);
+    Some(rec)
+}
+
+impl Hittable for Instance {
+    fn hit(&self, ray: &Ray, ray_t: Interval) -> Option<HitRecord<'_>> {
+        hit_transformed(self.object.as_ref(), &self.transform, ray, ray_t)
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.bbox
+    }
+
+    fn instancing(self) -> Arc<dyn Hittable + Send + Sync> {
+        Arc::new(self)
+    }
+}
+
+// top level acceleration structure: a BVH over instances, each of them pointing to a
+// bottom level structure (a mesh, or any Hittable_list::to_bvh()) that is built only once
+pub struct TopLevelBVH {
+    pub instances: Vec<Arc<dyn Hittable + Send + Sync>>,
+}
+
+impl TopLevelBVH {
+    pub fn new() -> TopLevelBVH {
+        TopLevelBVH {
+            instances: Vec::new(),
+        }
+    }
+
+    pub fn add_instance(&mut self, object: Arc<dyn Hittable + Send + Sync>, transform: Transform) {
+        self.instances.push(Instance::new(object, transform).instancing());
+    }
+
+    pub fn build(self) -> FlatBVH {
+        FlatBVH::new_from_objects(self.instances, DEFAULT_MAX_LEAF_SIZE)
+    }
+}
+
+impl Default for TopLevelBVH {
+    fn default() -> Self {
+        Self::new()
+    }
+}
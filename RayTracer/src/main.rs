@@ -1,9 +1,10 @@
-use image::{ImageBuffer, RgbImage}; //接收render传回来的图片，在main中文件输出
+use image::{DynamicImage, ImageBuffer, RgbImage}; //接收render传回来的图片，在main中文件输出
 use indicatif::ProgressBar;
 use std::fs::File;
 use std::sync::Arc;
 use std::f64::consts::PI;
 use std::path::Path;
+use std::time::Instant;
 
 mod vec3;
 mod color;
@@ -19,6 +20,35 @@ mod texture;
 mod perlin;
 mod quad;
 mod bvh;
+mod filter;
+mod film;
+mod projection;
+mod physical_camera;
+mod aperture;
+mod animation;
+mod transform;
+mod instance;
+mod mesh;
+mod primitive;
+mod onb;
+mod disk;
+mod cylinder;
+mod cone;
+mod torus;
+mod plane;
+mod planar;
+mod csg;
+mod sdf;
+mod heightfield;
+mod curve;
+mod pointcloud;
+mod texture_manager;
+mod procedural;
+mod texture_graph;
+mod cutout;
+mod displacement;
+#[cfg(feature = "simd")]
+mod simd;
 
 pub use crate::vec3::*;
 pub use crate::color::*;
@@ -34,6 +64,35 @@ pub use crate::texture::*;
 pub use crate::perlin::*;
 pub use crate::quad::*;
 pub use crate::bvh::*;
+pub use crate::filter::*;
+pub use crate::film::*;
+pub use crate::projection::*;
+pub use crate::physical_camera::*;
+pub use crate::aperture::*;
+pub use crate::animation::*;
+pub use crate::transform::*;
+pub use crate::instance::*;
+pub use crate::mesh::*;
+pub use crate::primitive::*;
+pub use crate::onb::*;
+pub use crate::disk::*;
+pub use crate::cylinder::*;
+pub use crate::cone::*;
+pub use crate::torus::*;
+pub use crate::plane::*;
+pub use crate::planar::*;
+pub use crate::csg::*;
+pub use crate::sdf::*;
+pub use crate::heightfield::*;
+pub use crate::curve::*;
+pub use crate::pointcloud::*;
+pub use crate::texture_manager::*;
+pub use crate::procedural::*;
+pub use crate::texture_graph::*;
+pub use crate::cutout::*;
+pub use crate::displacement::*;
+#[cfg(feature = "simd")]
+pub use crate::simd::*;
 
 const AUTHOR: &str = "ZhangZicong";
 
@@ -143,7 +202,9 @@ pub fn checkered_sphers() -> RgbImage {
     let max_depth = 50;
     let background = Vec3::new(0.7, 0.8, 1.0);
 
-    let camera = Camera::new(width, height, samples_per_pixel, max_depth, vfov, look_from, look_at, vup, defocus_angle, focus_dist,background);
+    let mut camera = Camera::new(width, height, samples_per_pixel, max_depth, vfov, look_from, look_at, vup, defocus_angle, focus_dist,background);
+    // the checker squares shrink toward the horizon, a wider filter than the pixel box keeps them from aliasing
+    camera.set_filter(MitchellFilter::new_default(2.0).instancing());
 
     let img = camera.render(&(world.to_bvh()));
     img
@@ -213,139 +274,1129 @@ pub fn perlin_spheres() -> RgbImage {
      
     let defocus_angle = 0.0;
     let focus_dist = 10.0;
-    let vfov: f64 = 20.0;
+    let vfov: f64 = 20.0;
+    let vup = Vec3::new(0.0, 1.0, 0.0);
+    let look_from = Vec3::new(13.0, 2.0, 3.0);
+    let look_at = Vec3::new(0.0, 0.0, 0.0);
+    let samples_per_pixel = 100;
+    let max_depth = 50;
+    let background = Vec3::new(0.7, 0.8, 1.0);
+
+    let camera = Camera::new(width, height, samples_per_pixel, max_depth, vfov, look_from, look_at, vup, defocus_angle, focus_dist,background);
+    
+    let img = camera.render(&(world.to_bvh()));
+    img
+}
+
+pub fn quads() -> RgbImage {
+    println!("choose quads");
+    let width = 400;
+    let height = 400;
+    let Rad = (PI / 4.0).cos();
+
+    let mut world = Hittable_list::default();
+
+    let left_red = Lambertian::new_from_color(Vec3::new(1.0, 0.2, 0.2)).instancing();
+    let back_green = Lambertian::new_from_color(Vec3::new(0.2, 1.0, 0.2)).instancing();
+    let right_blue = Lambertian::new_from_color(Vec3::new(0.2, 0.2, 1.0)).instancing();
+    let upper_orange = Lambertian::new_from_color(Vec3::new(1.0, 0.5, 0.0)).instancing();
+    let lower_teal = Lambertian::new_from_color(Vec3::new(0.2, 0.8, 0.8)).instancing();
+
+    world.add(Quad::new(
+            Vec3::new(-3.0, -2.0, 5.0),
+            Vec3::new(0.0, 0.0, -4.0),
+            Vec3::new(0.0, 4.0, 0.0),
+            left_red,
+        ).instancing()
+    );
+    world.add(Quad::new(
+            Vec3::new(-2.0, -2.0, 0.0),
+            Vec3::new(4.0, 0.0, 0.0),
+            Vec3::new(0.0, 4.0, 0.0),
+            back_green,
+        ).instancing()
+    );
+    world.add(Quad::new(
+            Vec3::new(3.0, -2.0, 1.0),
+            Vec3::new(0.0, 0.0, 4.0),
+            Vec3::new(0.0, 4.0, 0.0),
+            right_blue,
+        ).instancing()
+    );
+    world.add(Quad::new(
+            Vec3::new(-2.0, 3.0, 1.0),
+            Vec3::new(4.0, 0.0, 0.0),
+            Vec3::new(0.0, 0.0, 4.0),
+            upper_orange,
+        ).instancing()
+    );
+    world.add(Quad::new(
+            Vec3::new(-2.0, -3.0, 5.0),
+            Vec3::new(4.0, 0.0, 0.0),
+            Vec3::new(0.0, 0.0, -4.0),
+            lower_teal,
+        ).instancing()
+    );
+
+     
+    let defocus_angle = 0.0;
+    let focus_dist = 10.0;
+    let vfov: f64 = 80.0;
+    let vup = Vec3::new(0.0, 1.0, 0.0);
+    let look_from = Vec3::new(0.0, 0.0, 9.0);
+    let look_at = Vec3::new(0.0, 0.0, 0.0);
+    let samples_per_pixel = 100;
+    let max_depth = 50;
+    let background = Vec3::new(0.7, 0.8, 1.0);
+
+    let camera = Camera::new(width, height, samples_per_pixel, max_depth, vfov, look_from, look_at, vup, defocus_angle, focus_dist,background);
+    
+    let img = camera.render(&(world.to_bvh()));
+    img
+}
+
+pub fn planar_shapes() -> RgbImage {
+    println!("choose planar shapes");
+    let width = 400;
+    let height = 400;
+
+    let mut world = Hittable_list::default();
+
+    let left_red = Lambertian::new_from_color(Vec3::new(1.0, 0.2, 0.2)).instancing();
+    let back_green = Lambertian::new_from_color(Vec3::new(0.2, 1.0, 0.2)).instancing();
+    let right_blue = Lambertian::new_from_color(Vec3::new(0.2, 0.2, 1.0)).instancing();
+    let upper_orange = Lambertian::new_from_color(Vec3::new(1.0, 0.5, 0.0)).instancing();
+    let lower_teal = Lambertian::new_from_color(Vec3::new(0.2, 0.8, 0.8)).instancing();
+
+    // the same walls as quads(), each cut to a different shape
+    world.add(Quad::new_triangle(
+            Vec3::new(-3.0, -2.0, 5.0),
+            Vec3::new(0.0, 0.0, -4.0),
+            Vec3::new(0.0, 4.0, 0.0),
+            left_red,
+        ).instancing()
+    );
+    // a square frame with a star-shaped window
+    let star: Vec<(f64, f64)> = (0..10)
+        .map(|i| {
+            let angle = PI / 2.0 + i as f64 * PI / 5.0;
+            let radius = if i % 2 == 0 { 0.4 } else { 0.16 };
+            (0.5 + radius * angle.cos(), 0.5 + radius * angle.sin())
+        })
+        .collect();
+    world.add(Quad::new_polygon(
+            Vec3::new(-2.0, -2.0, 0.0),
+            Vec3::new(4.0, 0.0, 0.0),
+            Vec3::new(0.0, 4.0, 0.0),
+            vec![(0.0, 0.0), (1.0, 0.0), (1.0, 1.0), (0.0, 1.0)],
+            vec![star],
+            back_green,
+        ).instancing()
+    );
+    world.add(Quad::new_ellipse(
+            Vec3::new(3.0, 0.0, 3.0),
+            Vec3::new(0.0, 0.0, 2.0),
+            Vec3::new(0.0, 1.5, 0.0),
+            right_blue,
+        ).instancing()
+    );
+    world.add(Quad::new_annulus(
+            Vec3::new(0.0, 3.0, 3.0),
+            Vec3::new(2.0, 0.0, 0.0),
+            Vec3::new(0.0, 0.0, 2.0),
+            0.5,
+            upper_orange,
+        ).instancing()
+    );
+    world.add(Quad::new(
+            Vec3::new(-2.0, -3.0, 5.0),
+            Vec3::new(4.0, 0.0, 0.0),
+            Vec3::new(0.0, 0.0, -4.0),
+            lower_teal,
+        ).instancing()
+    );
+
+    let defocus_angle = 0.0;
+    let focus_dist = 10.0;
+    let vfov: f64 = 80.0;
+    let vup = Vec3::new(0.0, 1.0, 0.0);
+    let look_from = Vec3::new(0.0, 0.0, 9.0);
+    let look_at = Vec3::new(0.0, 0.0, 0.0);
+    let samples_per_pixel = 100;
+    let max_depth = 50;
+    let background = Vec3::new(0.7, 0.8, 1.0);
+
+    let camera = Camera::new(width, height, samples_per_pixel, max_depth, vfov, look_from, look_at, vup, defocus_angle, focus_dist,background);
+
+    camera.render(&(world.to_bvh()))
+}
+
+pub fn simple_light() -> RgbImage {
+    println!("choose simple light");
+    let width = 400;
+    let height = 225;
+    let Rad = (PI / 4.0).cos();
+
+    let mut world = Hittable_list::default();
+
+    let pretext = NoiseTexture::new(4.0).instancing();
+    let material_shadow = Lambertian::new(pretext).instancing();
+
+    world.add(Sphere::new(
+            Vec3::new(0.0, -1000.0, 0.0),
+            1000.0,
+            material_shadow.clone(),
+        ).instancing()
+    );
+    world.add(Sphere::new(
+            Vec3::new(0.0, 2.0, 0.0),
+            2.0,
+            material_shadow.clone(),
+        ).instancing()
+    );
+    
+    let material_difflight = Diffuselight::new_from_color(Vec3::new(4.0, 4.0, 4.0)).instancing();
+    world.add(Quad::new(
+            Vec3::new(3.0, 1.0, -2.0),
+            Vec3::new(2.0, 0.0, 0.0),
+            Vec3::new(0.0, 2.0, 0.0),
+            material_difflight.clone(),
+        ).instancing()
+    );
+    world.add(Sphere::new(
+            Vec3::new(0.0, 7.0, 0.0),
+            2.0,
+            material_difflight.clone(),
+        ).instancing()
+    );
+
+    let defocus_angle = 0.0;
+    let focus_dist = 10.0;
+    let vfov: f64 = 20.0;
+    let vup = Vec3::new(0.0, 1.0, 0.0);
+    let look_from = Vec3::new(26.0, 3.0, 6.0);
+    let look_at = Vec3::new(0.0, 2.0, 0.0);
+    let samples_per_pixel = 100;
+    let max_depth = 50;
+    let background = Vec3::new(0.0, 0.0 , 0.0);
+
+    let camera = Camera::new(width, height, samples_per_pixel, max_depth, vfov, look_from, look_at, vup, defocus_angle, focus_dist,background);
+    
+    let img = camera.render(&(world.to_bvh()));
+    img
+}
+
+pub fn analytic_shapes() -> RgbImage {
+    println!("choose analytic shapes");
+    let width = 600;
+    let height = 400;
+
+    let mut world = Hittable_list::default();
+
+    let checker = CheckerTexture::new_from_color(0.5, Vec3::new(0.2, 0.3, 0.1), Vec3::new(0.9, 0.9, 0.9)).instancing();
+    world.add(InfinitePlane::new(Vec3::zero(), Vec3::new(0.0, 1.0, 0.0), Lambertian::new(checker).instancing()).instancing());
+
+    // a round table: disk top on a single leg standing on a thin base
+    let wood = Lambertian::new_from_color(Vec3::new(0.55, 0.35, 0.2)).instancing();
+    world.add(Cylinder::new(Vec3::new(0.0, 1.9, 0.0), Vec3::new(0.0, 2.0, 0.0), 2.0, wood.clone()).instancing());
+    world.add(Cylinder::new(Vec3::new(0.0, 0.1, 0.0), Vec3::new(0.0, 1.9, 0.0), 0.15, wood.clone()).instancing());
+    world.add(Cylinder::new(Vec3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 0.1, 0.0), 0.8, wood).instancing());
+
+    world.add(Torus::new(
+            Vec3::new(-0.6, 2.3, 0.4),
+            Vec3::new(0.0, 1.0, 0.3),
+            0.5,
+            0.15,
+            Metal::new(Vec3::new(0.9, 0.75, 0.3), 0.1).instancing()
+        ).instancing()
+    );
+    world.add(Cone::new(
+            Vec3::new(0.8, 2.0, -0.3),
+            Vec3::new(0.8, 3.2, -0.3),
+            0.4,
+            Lambertian::new_from_color(Vec3::new(0.8, 0.1, 0.1)).instancing()
+        ).instancing()
+    );
+    // an open glass tube and an annulus leaning against the table
+    world.add(Cylinder::new_uncapped(
+            Vec3::new(0.5, 2.0, 1.0),
+            Vec3::new(0.5, 2.8, 1.0),
+            0.25,
+            Dielectric::new(1.5).instancing()
+        ).instancing()
+    );
+    world.add(Disk::new_annulus(
+            Vec3::new(3.0, 1.0, 1.5),
+            Vec3::new(1.0, 0.2, 0.3),
+            0.5,
+            1.0,
+            Lambertian::new_from_color(Vec3::new(0.1, 0.3, 0.8)).instancing()
+        ).instancing()
+    );
+
+    let defocus_angle = 0.0;
+    let focus_dist = 10.0;
+    let vfov: f64 = 30.0;
+    let vup = Vec3::new(0.0, 1.0, 0.0);
+    let look_from = Vec3::new(8.0, 5.0, 10.0);
+    let look_at = Vec3::new(0.5, 1.5, 0.0);
+    let samples_per_pixel = 100;
+    let max_depth = 50;
+    let background = Vec3::new(0.7, 0.8, 1.0);
+
+    let camera = Camera::new(width, height, samples_per_pixel, max_depth, vfov, look_from, look_at, vup, defocus_angle, focus_dist,background);
+
+    camera.render(&(world.to_bvh()))
+}
+
+pub fn csg_shapes() -> RgbImage {
+    println!("choose csg shapes");
+    let width = 600;
+    let height = 400;
+
+    let mut world = Hittable_list::default();
+
+    let checker = CheckerTexture::new_from_color(0.5, Vec3::new(0.2, 0.3, 0.1), Vec3::new(0.9, 0.9, 0.9)).instancing();
+    world.add(InfinitePlane::new(Vec3::zero(), Vec3::new(0.0, 1.0, 0.0), Lambertian::new(checker).instancing()).instancing());
+
+    // biconvex lens: the overlap of two large spheres
+    let glass = Dielectric::new(1.5).instancing();
+    world.add(Csg::new_intersection(
+            Sphere::new(Vec3::new(0.0, 1.6, 2.6), 3.0, glass.clone()).instancing(),
+            Sphere::new(Vec3::new(0.0, 1.6, -2.6), 3.0, glass).instancing(),
+        ).instancing()
+    );
+
+    // a block with a drilled hole and a scooped corner, the cuts are painted red
+    let steel = Metal::new(Vec3::new(0.8, 0.8, 0.85), 0.2).instancing();
+    let cut = Lambertian::new_from_color(Vec3::new(0.8, 0.1, 0.1)).instancing();
+    let block = create_box(Vec3::new(-4.5, 0.0, -1.0), Vec3::new(-2.0, 1.5, 1.0), steel).instancing();
+    let drilled = Csg::new_difference(
+        block,
+        Cylinder::new(Vec3::new(-3.25, -0.5, 0.0), Vec3::new(-3.25, 2.0, 0.0), 0.4, cut.clone()).instancing(),
+    ).instancing();
+    world.add(Csg::new_difference(
+            drilled,
+            Sphere::new(Vec3::new(-2.0, 1.5, 1.0), 0.8, cut).instancing(),
+        ).instancing()
+    );
+
+    // smoke filling the union of two spheres
+    let boundary = Lambertian::new_from_color(Vec3::ones()).instancing();
+    let blob = Csg::new_union(
+        Sphere::new(Vec3::new(3.0, 1.0, 0.0), 1.0, boundary.clone()).instancing(),
+        Sphere::new(Vec3::new(3.7, 1.6, -0.5), 0.8, boundary).instancing(),
+    ).instancing();
+    world.add(ConstantMedium::new_from_color(blob, 2.0, Vec3::new(0.2, 0.4, 0.9)).instancing());
+
+    let defocus_angle = 0.0;
+    let focus_dist = 10.0;
+    let vfov: f64 = 30.0;
+    let vup = Vec3::new(0.0, 1.0, 0.0);
+    let look_from = Vec3::new(2.0, 5.0, 14.0);
+    let look_at = Vec3::new(0.0, 1.0, 0.0);
+    let samples_per_pixel = 100;
+    let max_depth = 50;
+    let background = Vec3::new(0.7, 0.8, 1.0);
+
+    let camera = Camera::new(width, height, samples_per_pixel, max_depth, vfov, look_from, look_at, vup, defocus_angle, focus_dist,background);
+
+    camera.render(&(world.to_bvh()))
+}
+
+pub fn sdf_shapes() -> RgbImage {
+    println!("choose sdf shapes");
+    let width = 800;
+    let height = 300;
+
+    let mut world = Hittable_list::default();
+
+    let checker = CheckerTexture::new_from_color(0.5, Vec3::new(0.2, 0.3, 0.1), Vec3::new(0.9, 0.9, 0.9)).instancing();
+    world.add(InfinitePlane::new(Vec3::zero(), Vec3::new(0.0, 1.0, 0.0), Lambertian::new(checker).instancing()).instancing());
+
+    // every shape is modeled around the origin, then moved to its slot on the ground
+    let slot = |x: f64| Vec3::new(x, 1.0, 0.0);
+    let bounds = |x: f64, half: f64| Aabb::new_from_point(slot(x) - Vec3::ones() * half, slot(x) + Vec3::ones() * half);
+
+    let rounded_box = SdfBox::new_rounded(Vec3::zero(), Vec3::new(0.7, 0.7, 0.7), 0.2).instancing();
+    world.add(SdfObject::new(
+            SdfTranslate::new(rounded_box, slot(-4.0)).instancing(),
+            bounds(-4.0, 0.8),
+            Lambertian::new_from_color(Vec3::new(0.8, 0.3, 0.1)).instancing()
+        ).instancing()
+    );
+
+    let blend = SdfUnion::new_smooth(
+        SdfSphere::new(Vec3::new(0.0, 0.2, 0.0), 0.5).instancing(),
+        SdfTorus::new(Vec3::new(0.0, -0.3, 0.0), 0.6, 0.2).instancing(),
+        0.3,
+    ).instancing();
+    world.add(SdfObject::new(
+            SdfTranslate::new(blend, slot(-2.0)).instancing(),
+            bounds(-2.0, 1.0),
+            Metal::new(Vec3::new(0.9, 0.75, 0.3), 0.05).instancing()
+        ).instancing()
+    );
+
+    let twisted = SdfTwist::new(SdfBox::new(Vec3::zero(), Vec3::new(0.4, 0.9, 0.4)).instancing(), 1.5).instancing();
+    world.add(SdfObject::new_with_step_scale(
+            SdfTranslate::new(twisted, slot(0.0)).instancing(),
+            bounds(0.0, 1.0),
+            0.5,
+            Lambertian::new_from_color(Vec3::new(0.2, 0.5, 0.8)).instancing()
+        ).instancing()
+    );
+
+    let bumpy = SdfDisplace::new(SdfSphere::new(Vec3::zero(), 0.8).instancing(), 0.08, 10.0).instancing();
+    world.add(SdfObject::new_with_step_scale(
+            SdfTranslate::new(bumpy, slot(2.0)).instancing(),
+            bounds(2.0, 1.0),
+            0.5,
+            Dielectric::new(1.5).instancing()
+        ).instancing()
+    );
+
+    let fractal = SdfScale::new(SdfMandelbulb::new(8.0, 10).instancing(), 0.8).instancing();
+    world.add(SdfObject::new(
+            SdfTranslate::new(fractal, slot(4.0)).instancing(),
+            bounds(4.0, 1.0),
+            Lambertian::new_from_color(Vec3::new(0.7, 0.2, 0.6)).instancing()
+        ).instancing()
+    );
+
+    let defocus_angle = 0.0;
+    let focus_dist = 10.0;
+    let vfov: f64 = 25.0;
+    let vup = Vec3::new(0.0, 1.0, 0.0);
+    let look_from = Vec3::new(0.0, 4.0, 15.0);
+    let look_at = Vec3::new(0.0, 1.0, 0.0);
+    let samples_per_pixel = 100;
+    let max_depth = 50;
+    let background = Vec3::new(0.7, 0.8, 1.0);
+
+    let camera = Camera::new(width, height, samples_per_pixel, max_depth, vfov, look_from, look_at, vup, defocus_angle, focus_dist,background);
+
+    camera.render(&(world.to_bvh()))
+}
+
+pub fn terrain() -> RgbImage {
+    println!("choose terrain");
+    let width = 600;
+    let height = 300;
+
+    let mut world = Hittable_list::default();
+
+    // the brightness of the earth map as elevation, with the same map draped over it
+    let path = std::env::current_dir()
+        .unwrap()
+        .join(Path::new("earth_map.jpg"));
+    let earth_texture = ImageTexture::new(&path).instancing();
+    let (corner, size) = (Vec3::new(-10.0, 0.0, -5.0), Vec3::new(20.0, 0.6, 10.0));
+    let material = Lambertian::new(earth_texture).instancing();
+    let terrain = match Heightfield::new_from_image(&path, corner, size, material.clone()) {
+        Ok(terrain) => terrain,
+        Err(err) => {
+            eprintln!("heightfield {}: {}, using flat ground", path.display(), err);
+            Heightfield::new(vec![0.0; 4], 2, 2, corner, size, material)
+        }
+    };
+    world.add(terrain.instancing());
+
+    let defocus_angle = 0.0;
+    let focus_dist = 10.0;
+    let vfov: f64 = 40.0;
+    let vup = Vec3::new(0.0, 1.0, 0.0);
+    let look_from = Vec3::new(0.0, 9.0, 13.0);
+    let look_at = Vec3::new(0.0, 0.0, 0.0);
+    let samples_per_pixel = 100;
+    let max_depth = 50;
+    let background = Vec3::new(0.7, 0.8, 1.0);
+
+    let camera = Camera::new(width, height, samples_per_pixel, max_depth, vfov, look_from, look_at, vup, defocus_angle, focus_dist,background);
+
+    camera.render(&(world.to_bvh()))
+}
+
+pub fn hair() -> RgbImage {
+    println!("choose hair");
+    let width = 600;
+    let height = 400;
+
+    let mut world = Hittable_list::default();
+
+    let checker = CheckerTexture::new_from_color(0.5, Vec3::new(0.2, 0.3, 0.1), Vec3::new(0.9, 0.9, 0.9)).instancing();
+    world.add(InfinitePlane::new(Vec3::zero(), Vec3::new(0.0, 1.0, 0.0), Lambertian::new(checker).instancing()).instancing());
+
+    // a furry ball: tapered strands growing out of a sphere and bending down
+    let center = Vec3::new(0.0, 1.2, 0.0);
+    world.add(Sphere::new(center, 1.0, Lambertian::new_from_color(Vec3::new(0.3, 0.15, 0.05)).instancing()).instancing());
+    let mut fur = CurveList::new(KajiyaKay::new_from_color(Vec3::new(0.6, 0.35, 0.15), 0.2, 0.1).instancing());
+    for _ in 0..4000 {
+        let dir = random_in_unit_shpere();
+        if dir.y < -0.6 {
+            continue;
+        }
+        let root = center + dir;
+        let gravity = Vec3::new(0.0, -1.0, 0.0);
+        let points = [
+            root - dir * 0.1,
+            root,
+            root + dir * 0.3 + gravity * 0.05,
+            root + dir * 0.55 + gravity * 0.2,
+            root + dir * 0.75 + gravity * 0.45,
+        ];
+        fur.add_bspline(&points, &[0.02, 0.02, 0.014, 0.008, 0.002], CurveType::Cylinder);
+    }
+    world.add(fur.build().instancing());
+
+    // grass blades, flat ribbons turned to random directions
+    let mut grass = CurveList::new(Lambertian::new_from_color(Vec3::new(0.2, 0.6, 0.1)).instancing());
+    for _ in 0..1500 {
+        let base = Vec3::new(random_f64_range(-4.0, 4.0), 0.0, random_f64_range(-2.0, 2.5));
+        if base.length() < 1.2 {
+            continue;
+        }
+        let angle = random_f64_range(0.0, 2.0 * PI);
+        let normal = Vec3::new(angle.cos(), 0.0, angle.sin());
+        // each blade leans towards its face, the tip normal stays perpendicular to the blade
+        let bend = normal * random_f64_range(0.05, 0.2);
+        let blade = random_f64_range(0.25, 0.5);
+        let cp = [
+            base,
+            base + Vec3::new(0.0, blade * 0.4, 0.0),
+            base + Vec3::new(0.0, blade * 0.8, 0.0) + bend * 0.5,
+            base + Vec3::new(0.0, blade, 0.0) + bend,
+        ];
+        let tip = cp[3] - cp[2];
+        let tip_normal = normal * tip.y - Vec3::new(0.0, tip * normal, 0.0);
+        grass.add(Curve::new_ribbon(cp, 0.04, 0.0, normal, tip_normal));
+    }
+    world.add(grass.build().instancing());
+
+    // a cable arching behind the ball
+    let mut cable = CurveList::new(Metal::new(Vec3::new(0.7, 0.3, 0.2), 0.3).instancing());
+    cable.add(Curve::new(
+        [
+            Vec3::new(-3.5, 0.0, -1.5),
+            Vec3::new(-2.0, 4.0, -1.5),
+            Vec3::new(2.0, 4.0, -1.5),
+            Vec3::new(3.5, 0.0, -1.5),
+        ],
+        0.12,
+        0.12,
+        CurveType::Cylinder,
+    ));
+    world.add(cable.build().instancing());
+
+    let defocus_angle = 0.0;
+    let focus_dist = 10.0;
+    let vfov: f64 = 35.0;
+    let vup = Vec3::new(0.0, 1.0, 0.0);
+    let look_from = Vec3::new(0.0, 2.5, 8.0);
+    let look_at = Vec3::new(0.0, 1.2, 0.0);
+    let samples_per_pixel = 100;
+    let max_depth = 50;
+    let background = Vec3::new(0.7, 0.8, 1.0);
+
+    let mut camera = Camera::new(width, height, samples_per_pixel, max_depth, vfov, look_from, look_at, vup, defocus_angle, focus_dist,background);
+    // the fibers are thinner than a pixel, a gaussian spreads each one over its neighbors instead of leaving gaps
+    camera.set_filter(GaussianFilter::new(1.5, 2.0).instancing());
+
+    camera.render(&(world.to_bvh()))
+}
+
+pub fn point_cloud() -> RgbImage {
+    println!("choose point cloud");
+    let width = 600;
+    let height = 400;
+
+    let mut world = Hittable_list::default();
+    let white = Lambertian::new_from_color(Vec3::ones()).instancing();
+
+    // a scanned looking ground: oriented disks on a wavy surface, colored by height
+    let (mut positions, mut colors, mut normals) = (Vec::new(), Vec::new(), Vec::new());
+    for i in 0..200 {
+        for j in 0..200 {
+            let x = -5.0 + i as f64 * 0.05 + random_f64_range(-0.01, 0.01);
+            let z = -5.0 + j as f64 * 0.05 + random_f64_range(-0.01, 0.01);
+            let y = 0.2 * (x * 1.5).sin() * (z * 1.5).cos();
+            let dydx = 0.3 * (x * 1.5).cos() * (z * 1.5).cos();
+            let dydz = -0.3 * (x * 1.5).sin() * (z * 1.5).sin();
+            positions.push(Vec3::new(x, y, z));
+            normals.push(unit_vec(Vec3::new(-dydx, 1.0, -dydz)));
+            let h = (y + 0.2) / 0.4;
+            colors.push(Vec3::new(0.2 + 0.6 * h, 0.5, 0.8 - 0.6 * h));
+        }
+    }
+    let radii = vec![0.04; positions.len()];
+    world.add(PointCloud::new(positions, radii, colors, normals, PointShape::Disk, white.clone()).instancing());
+
+    // a spiral galaxy of particles, hot in the middle and blue on the arms
+    let (mut positions, mut radii, mut colors) = (Vec::new(), Vec::new(), Vec::new());
+    for i in 0..30000 {
+        let arm = (i % 3) as f64;
+        let r = random_f64_0_1().sqrt() * 2.5;
+        let angle = arm * 2.0 * PI / 3.0 + r * 1.8 + random_f64_range(-0.3, 0.3);
+        let spread = 0.15 * (1.0 - r / 3.0);
+        positions.push(Vec3::new(r * angle.cos(), 2.0, r * angle.sin()) + random_vec3_range(-spread, spread));
+        radii.push(0.012 + 0.02 * random_f64_0_1());
+        let heat = (1.0 - r / 2.5).max(0.0);
+        colors.push(Vec3::new(0.4 + 0.6 * heat, 0.5 + 0.4 * heat, 1.0 - 0.5 * heat));
+    }
+    world.add(PointCloud::new(positions, radii, colors, Vec::new(), PointShape::Sphere, white).instancing());
+
+    let defocus_angle = 0.0;
+    let focus_dist = 10.0;
+    let vfov: f64 = 40.0;
+    let vup = Vec3::new(0.0, 1.0, 0.0);
+    let look_from = Vec3::new(0.0, 5.0, 7.0);
+    let look_at = Vec3::new(0.0, 1.0, 0.0);
+    let samples_per_pixel = 100;
+    let max_depth = 50;
+    let background = Vec3::new(0.7, 0.8, 1.0);
+
+    let camera = Camera::new(width, height, samples_per_pixel, max_depth, vfov, look_from, look_at, vup, defocus_angle, focus_dist,background);
+
+    camera.render(&(world.to_bvh()))
+}
+
+pub fn texture_mapping() -> RgbImage {
+    println!("choose texture mapping");
+    let width = 600;
+    let height = 400;
+
+    let mut world = Hittable_list::default();
+
+    let path = std::env::current_dir()
+        .unwrap()
+        .join(Path::new("earth_map.jpg"));
+    let texture_with_wrap = |wrap: WrapMode| {
+        let mut tex = ImageTexture::new(&path);
+        tex.set_wrap(wrap);
+        tex.instancing()
+    };
+
+    // ground tiled 4 x 4 and turned
+    let ground = UvTransformTexture::new(texture_with_wrap(WrapMode::Repeat)).tile(4.0, 4.0).rotate(20.0);
+    world.add(Quad::new(
+            Vec3::new(-8.0, -1.0, 8.0),
+            Vec3::new(16.0, 0.0, 0.0),
+            Vec3::new(0.0, 0.0, -16.0),
+            Lambertian::new(ground.instancing()).instancing(),
+        ).instancing()
+    );
+
+    // mirrored copies meet without seams
+    let mirrored = UvTransformTexture::new(texture_with_wrap(WrapMode::Mirror)).tile(2.0, 2.0);
+    world.add(Quad::new(
+            Vec3::new(-5.5, -1.0, -3.0),
+            Vec3::new(4.0, 0.0, 0.0),
+            Vec3::new(0.0, 3.0, 0.0),
+            Lambertian::new(mirrored.instancing()).instancing(),
+        ).instancing()
+    );
+
+    // shrunk around the center, with a frame of border color
+    let framed = UvTransformTexture::new(texture_with_wrap(WrapMode::Border(Vec3::new(0.9, 0.9, 0.9))))
+        .tile(1.6, 1.6)
+        .offset(-0.3, -0.3);
+    world.add(Quad::new(
+            Vec3::new(1.5, -1.0, -3.0),
+            Vec3::new(4.0, 0.0, 0.0),
+            Vec3::new(0.0, 3.0, 0.0),
+            Lambertian::new(framed.instancing()).instancing(),
+        ).instancing()
+    );
+
+    // a box has no useful uv, the triplanar projection covers every side
+    let projected = TriplanarTexture::new(texture_with_wrap(WrapMode::Repeat), 0.5, 4.0);
+    world.add(create_box(
+            Vec3::new(-0.9, -1.0, -1.0),
+            Vec3::new(0.9, 0.6, 0.6),
+            Lambertian::new(projected.instancing()).instancing(),
+        ).instancing()
+    );
+
+    // the globe mirrored east to west and turned half a revolution
+    let flipped = UvTransformTexture::new(texture_with_wrap(WrapMode::Repeat)).mirror(true, false).offset(0.5, 0.0);
+    world.add(Sphere::new(
+            Vec3::new(2.2, -0.3, 1.5),
+            0.7,
+            Lambertian::new(flipped.instancing()).instancing(),
+        ).instancing()
+    );
+
+    let defocus_angle = 0.0;
+    let focus_dist = 10.0;
+    let vfov: f64 = 45.0;
+    let vup = Vec3::new(0.0, 1.0, 0.0);
+    let look_from = Vec3::new(0.0, 3.0, 9.0);
+    let look_at = Vec3::new(0.0, 0.5, 0.0);
+    let samples_per_pixel = 100;
+    let max_depth = 50;
+    let background = Vec3::new(0.7, 0.8, 1.0);
+
+    let camera = Camera::new(width, height, samples_per_pixel, max_depth, vfov, look_from, look_at, vup, defocus_angle, focus_dist,background);
+
+    camera.render(&(world.to_bvh()))
+}
+
+pub fn procedural_textures() -> RgbImage {
+    println!("choose procedural textures");
+    let width = 600;
+    let height = 400;
+
+    let mut world = Hittable_list::default();
+
+    // cobblestones: dark mortar along the cell borders
+    let stone = ColorRamp::new(vec![
+        (0.0, Vec3::new(0.08, 0.07, 0.06)),
+        (0.08, Vec3::new(0.35, 0.33, 0.3)),
+        (0.5, Vec3::new(0.6, 0.58, 0.55)),
+    ]);
+    let ground = WorleyTexture::new(1.5).seed(7).feature(WorleyFeature::F2MinusF1).ramp(stone);
+    world.add(Quad::new(
+            Vec3::new(-8.0, -1.0, 8.0),
+            Vec3::new(16.0, 0.0, 0.0),
+            Vec3::new(0.0, 0.0, -16.0),
+            Lambertian::new(ground.instancing()).instancing(),
+        ).instancing()
+    );
+
+    // a sky from the horizon up
+    let sky = ColorRamp::new_two_colors(Vec3::new(0.95, 0.75, 0.55), Vec3::new(0.25, 0.45, 0.8));
+    let backdrop = GradientTexture::new_linear(Vec3::new(0.0, -1.0, 0.0), Vec3::new(0.0, 5.0, 0.0), sky);
+    world.add(Quad::new(
+            Vec3::new(-10.0, -1.0, -4.0),
+            Vec3::new(20.0, 0.0, 0.0),
+            Vec3::new(0.0, 8.0, 0.0),
+            Lambertian::new(backdrop.instancing()).instancing(),
+        ).instancing()
+    );
+
+    // green marble with its veins running across x
+    let marble = ColorRamp::new(vec![
+        (0.0, Vec3::new(0.05, 0.2, 0.12)),
+        (0.5, Vec3::new(0.3, 0.55, 0.4)),
+        (1.0, Vec3::new(0.9, 0.95, 0.9)),
+    ]);
+    let marble = NoiseTexture::new(5.0).seed(1).axis(Vec3::new(1.0, 0.2, 0.0)).turbulence(3.0, 6).ramp(marble);
+    // wood cut along its grain
+    let wood = WoodTexture::new(Vec3::new(-1.2, 0.0, -3.0), Vec3::new(0.0, 0.3, 1.0), 5.0).seed(2);
+    let clouds = FbmTexture::new(2.5).seed(3).ramp(ColorRamp::new_two_colors(Vec3::new(0.1, 0.2, 0.6), Vec3::new(1.0, 1.0, 1.0)));
+    let mountains = ColorRamp::new(vec![
+        (0.3, Vec3::new(0.15, 0.1, 0.05)),
+        (0.7, Vec3::new(0.5, 0.4, 0.3)),
+        (0.9, Vec3::new(1.0, 1.0, 1.0)),
+    ]);
+    let ridges = FbmTexture::new(2.0).seed(4).kind(NoiseKind::Ridged).fractal(Fractal::new(6, 2.1, 0.55)).ramp(mountains);
+    let textures = [marble.instancing(), wood.instancing(), clouds.instancing(), ridges.instancing()];
+    for (i, texture) in textures.into_iter().enumerate() {
+        world.add(Sphere::new(
+                Vec3::new(-3.3 + 2.2 * i as f64, 0.0, 0.0),
+                1.0,
+                Lambertian::new(texture).instancing(),
+            ).instancing()
+        );
+    }
+
+    let defocus_angle = 0.0;
+    let focus_dist = 10.0;
+    let vfov: f64 = 40.0;
+    let vup = Vec3::new(0.0, 1.0, 0.0);
+    let look_from = Vec3::new(0.0, 2.0, 10.0);
+    let look_at = Vec3::new(0.0, 0.3, 0.0);
+    let samples_per_pixel = 100;
+    let max_depth = 50;
+    let background = Vec3::new(0.7, 0.8, 1.0);
+
+    let camera = Camera::new(width, height, samples_per_pixel, max_depth, vfov, look_from, look_at, vup, defocus_angle, focus_dist,background);
+
+    camera.render(&(world.to_bvh()))
+}
+
+pub fn texture_graph() -> RgbImage {
+    println!("choose texture graph");
+    let width = 600;
+    let height = 400;
+
+    let mut world = Hittable_list::default();
+
+    let path = std::env::current_dir()
+        .unwrap()
+        .join(Path::new("textures.graph"));
+    let textures = TextureManager::default();
+    let graph = TextureGraph::new_from_file(&path, &textures);
+    let node = |name: &str| graph.get(name).expect("texture not in the graph");
+
+    world.add(Quad::new(
+            Vec3::new(-8.0, -1.0, 8.0),
+            Vec3::new(16.0, 0.0, 0.0),
+            Vec3::new(0.0, 0.0, -16.0),
+            Lambertian::new(node("ground")).instancing(),
+        ).instancing()
+    );
+    for (i, name) in ["stained", "rusty", "alien", "swapped"].into_iter().enumerate() {
+        world.add(Sphere::new(
+                Vec3::new(-3.3 + 2.2 * i as f64, 0.0, 0.0),
+                1.0,
+                Lambertian::new(node(name)).instancing(),
+            ).instancing()
+        );
+    }
+
+    let defocus_angle = 0.0;
+    let focus_dist = 10.0;
+    let vfov: f64 = 40.0;
+    let vup = Vec3::new(0.0, 1.0, 0.0);
+    let look_from = Vec3::new(0.0, 2.0, 10.0);
+    let look_at = Vec3::new(0.0, 0.3, 0.0);
+    let samples_per_pixel = 100;
+    let max_depth = 50;
+    let background = Vec3::new(0.7, 0.8, 1.0);
+
+    let camera = Camera::new(width, height, samples_per_pixel, max_depth, vfov, look_from, look_at, vup, defocus_angle, focus_dist,background);
+
+    camera.render(&(world.to_bvh()))
+}
+
+pub fn bump_mapping() -> RgbImage {
+    println!("choose bump mapping");
+    let width = 600;
+    let height = 400;
+
+    let mut world = Hittable_list::default();
+
+    // cobblestones: cracks where worley f2 - f1 drops to zero
+    let stones = WorleyTexture::new(1.2).seed(3).feature(WorleyFeature::F2MinusF1).instancing();
+    let ground = Lambertian::new_from_color(Vec3::new(0.55, 0.5, 0.45)).instancing();
+    world.add(Quad::new(
+            Vec3::new(-8.0, -1.0, 8.0),
+            Vec3::new(16.0, 0.0, 0.0),
+            Vec3::new(0.0, 0.0, -16.0),
+            BumpMapMaterial::new(ground, stones, 0.15).instancing(),
+        ).instancing()
+    );
+
+    let bumpy = FbmTexture::new(3.0).seed(7).kind(NoiseKind::Ridged).instancing();
+    let orange = Lambertian::new_from_color(Vec3::new(0.8, 0.4, 0.1)).instancing();
+    world.add(Sphere::new(
+            Vec3::new(-2.4, 0.0, 0.0),
+            1.0,
+            BumpMapMaterial::new(orange, bumpy, 0.08).instancing(),
+        ).instancing()
+    );
+
+    // tangent space normals made of two noise channels, pointing mostly along the normal
+    let wavy = CombineTexture::new(
+        FbmTexture::new(2.0).seed(11).instancing(),
+        FbmTexture::new(2.0).seed(12).instancing(),
+        SolidColor::new(Vec3::ones()).instancing(),
+    ).instancing();
+    let metal = Metal::new(Vec3::new(0.8, 0.85, 0.9), 0.05).instancing();
+    world.add(Sphere::new(
+            Vec3::new(0.0, 0.0, 0.0),
+            1.0,
+            NormalMapMaterial::new(metal, wavy, 1.0).instancing(),
+        ).instancing()
+    );
+
+    let rings = WoodTexture::new(Vec3::zero(), Vec3::new(0.0, 1.0, 0.0), 8.0).seed(5).instancing();
+    let green = Lambertian::new_from_color(Vec3::new(0.2, 0.5, 0.3)).instancing();
+    world.add(Torus::new(
+            Vec3::new(2.4, -0.3, 0.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            0.8,
+            0.35,
+            BumpMapMaterial::new(green, rings, 0.03).instancing(),
+        ).instancing()
+    );
+
+    let defocus_angle = 0.0;
+    let focus_dist = 10.0;
+    let vfov: f64 = 40.0;
     let vup = Vec3::new(0.0, 1.0, 0.0);
-    let look_from = Vec3::new(13.0, 2.0, 3.0);
+    let look_from = Vec3::new(0.0, 2.5, 9.0);
     let look_at = Vec3::new(0.0, 0.0, 0.0);
     let samples_per_pixel = 100;
     let max_depth = 50;
     let background = Vec3::new(0.7, 0.8, 1.0);
 
     let camera = Camera::new(width, height, samples_per_pixel, max_depth, vfov, look_from, look_at, vup, defocus_angle, focus_dist,background);
-    
-    let img = camera.render(&(world.to_bvh()));
-    img
+
+    camera.render(&(world.to_bvh()))
 }
 
-pub fn quads() -> RgbImage {
-    println!("choose quads");
-    let width = 400;
+pub fn alpha_cutout() -> RgbImage {
+    println!("choose alpha cutout");
+    let width = 600;
     let height = 400;
-    let Rad = (PI / 4.0).cos();
 
     let mut world = Hittable_list::default();
 
-    let left_red = Lambertian::new_from_color(Vec3::new(1.0, 0.2, 0.2)).instancing();
-    let back_green = Lambertian::new_from_color(Vec3::new(0.2, 1.0, 0.2)).instancing();
-    let right_blue = Lambertian::new_from_color(Vec3::new(0.2, 0.2, 1.0)).instancing();
-    let upper_orange = Lambertian::new_from_color(Vec3::new(1.0, 0.5, 0.0)).instancing();
-    let lower_teal = Lambertian::new_from_color(Vec3::new(0.2, 0.8, 0.8)).instancing();
-
-    world.add(Quad::new(
-            Vec3::new(-3.0, -2.0, 5.0),
-            Vec3::new(0.0, 0.0, -4.0),
-            Vec3::new(0.0, 4.0, 0.0),
-            left_red,
-        ).instancing()
-    );
-    world.add(Quad::new(
-            Vec3::new(-2.0, -2.0, 0.0),
-            Vec3::new(4.0, 0.0, 0.0),
-            Vec3::new(0.0, 4.0, 0.0),
-            back_green,
-        ).instancing()
-    );
     world.add(Quad::new(
-            Vec3::new(3.0, -2.0, 1.0),
-            Vec3::new(0.0, 0.0, 4.0),
-            Vec3::new(0.0, 4.0, 0.0),
-            right_blue,
-        ).instancing()
-    );
-    world.add(Quad::new(
-            Vec3::new(-2.0, 3.0, 1.0),
-            Vec3::new(4.0, 0.0, 0.0),
-            Vec3::new(0.0, 0.0, 4.0),
-            upper_orange,
-        ).instancing()
-    );
-    world.add(Quad::new(
-            Vec3::new(-2.0, -3.0, 5.0),
-            Vec3::new(4.0, 0.0, 0.0),
-            Vec3::new(0.0, 0.0, -4.0),
-            lower_teal,
+            Vec3::new(-8.0, -1.0, 8.0),
+            Vec3::new(16.0, 0.0, 0.0),
+            Vec3::new(0.0, 0.0, -16.0),
+            Lambertian::new_from_color(Vec3::new(0.5, 0.5, 0.45)).instancing(),
         ).instancing()
     );
 
-     
+    // a leaf shaped alpha mask, the way a png with transparency would be loaded
+    let leaf_image = image::RgbaImage::from_fn(128, 128, |x, y| {
+        let u = (x as f64 + 0.5) / 64.0 - 1.0;
+        let v = (y as f64 + 0.5) / 64.0 - 1.0;
+        let inside = u * u + (v * v) / (1.0 - u * u).max(1e-3) * 1.5;
+        let alpha = ((1.0 - inside) * 8.0).clamp(0.0, 1.0);
+        image::Rgba([60, 120, 40, (alpha * 255.0) as u8])
+    });
+    let leaf_alpha = ImageTexture::new_alpha_from_image(&DynamicImage::ImageRgba8(leaf_image), TextureFilter::Bilinear).instancing();
+    let leaf_green = Lambertian::new_from_color(Vec3::new(0.2, 0.45, 0.1)).instancing();
+    for _ in 0..60 {
+        let center = Vec3::new(random_f64_range(-3.8, -1.2), random_f64_range(0.2, 2.2), random_f64_range(-1.0, 1.0));
+        let u = unit_vec(random_vec3_range(-1.0, 1.0)) * 0.7;
+        let v = unit_vec(random_vec3_range(-1.0, 1.0)) * 0.35;
+        let leaf = Quad::new(center - u * 0.5 - v * 0.5, u, v, leaf_green.clone()).instancing();
+        world.add(Cutout::new(leaf, leaf_alpha.clone()).instancing());
+    }
+
+    // a fence: the checker is either fully opaque or fully clear
+    let fence = Quad::new(
+        Vec3::new(-1.0, -1.0, 0.0),
+        Vec3::new(2.0, 0.0, 0.0),
+        Vec3::new(0.0, 2.5, 0.0),
+        Lambertian::new_from_color(Vec3::new(0.6, 0.3, 0.2)).instancing(),
+    ).instancing();
+    let slats = CheckerTexture::new_from_color(0.15, Vec3::ones(), Vec3::zero()).instancing();
+    world.add(Cutout::new(fence, slats).instancing());
+
+    // partial alpha fades a sphere out towards its top, shadows fade with it
+    let fading = Sphere::new(Vec3::new(2.6, 0.2, 0.0), 1.2, Lambertian::new_from_color(Vec3::new(0.2, 0.3, 0.8)).instancing()).instancing();
+    let fade = GradientTexture::new_linear(Vec3::new(0.0, 1.4, 0.0), Vec3::new(0.0, -0.4, 0.0), ColorRamp::grayscale()).instancing();
+    world.add(Cutout::new(fading, fade).instancing());
+
     let defocus_angle = 0.0;
     let focus_dist = 10.0;
-    let vfov: f64 = 80.0;
+    let vfov: f64 = 40.0;
     let vup = Vec3::new(0.0, 1.0, 0.0);
-    let look_from = Vec3::new(0.0, 0.0, 9.0);
-    let look_at = Vec3::new(0.0, 0.0, 0.0);
+    let look_from = Vec3::new(0.0, 2.0, 10.0);
+    let look_at = Vec3::new(0.0, 0.5, 0.0);
     let samples_per_pixel = 100;
     let max_depth = 50;
     let background = Vec3::new(0.7, 0.8, 1.0);
 
     let camera = Camera::new(width, height, samples_per_pixel, max_depth, vfov, look_from, look_at, vup, defocus_angle, focus_dist,background);
-    
-    let img = camera.render(&(world.to_bvh()));
-    img
+
+    camera.render(&(world.to_bvh()))
 }
 
-pub fn simple_light() -> RgbImage {
-    println!("choose simple light");
-    let width = 400;
-    let height = 225;
-    let Rad = (PI / 4.0).cos();
+pub fn displacement_mapping() -> RgbImage {
+    println!("choose displacement mapping");
+    let width = 600;
+    let height = 400;
 
-    let mut world = Hittable_list::default();
+    let defocus_angle = 0.0;
+    let focus_dist = 10.0;
+    let vfov: f64 = 40.0;
+    let vup = Vec3::new(0.0, 1.0, 0.0);
+    let look_from = Vec3::new(0.0, 5.0, 7.0);
+    let look_at = Vec3::new(0.0, -1.0, -0.5);
+    let samples_per_pixel = 100;
+    let max_depth = 50;
+    let background = Vec3::new(0.7, 0.8, 1.0);
 
-    let pretext = NoiseTexture::new(4.0).instancing();
-    let material_shadow = Lambertian::new(pretext).instancing();
+    // the camera comes first, the tessellation depends on it
+    let camera = Camera::new(width, height, samples_per_pixel, max_depth, vfov, look_from, look_at, vup, defocus_angle, focus_dist,background);
+    let tolerance = EdgeTolerance::screen(&camera, 2.0);
 
-    world.add(Sphere::new(
-            Vec3::new(0.0, -1000.0, 0.0),
-            1000.0,
-            material_shadow.clone(),
+    let mut world = Hittable_list::default();
+
+    // the earth map is both the albedo and the height, two triangles before tessellation
+    let earth_path = std::env::current_dir()
+        .unwrap()
+        .join(Path::new("earth_map.jpg"));
+    let earth = ImageTexture::new(&earth_path).instancing();
+    let terrain = Displacement::new(earth.clone(), 0.25, tolerance);
+    world.add(TriangleMesh::new_displaced(
+            vec![Vec3::new(-4.0, -1.0, 1.0), Vec3::new(4.0, -1.0, 1.0), Vec3::new(4.0, -1.0, -3.0), Vec3::new(-4.0, -1.0, -3.0)],
+            vec![[0, 1, 2], [0, 2, 3]],
+            Vec::new(),
+            vec![(0.0, 0.0), (1.0, 0.0), (1.0, 1.0), (0.0, 1.0)],
+            Lambertian::new(earth).instancing(),
+            &terrain,
         ).instancing()
     );
-    world.add(Sphere::new(
-            Vec3::new(0.0, 2.0, 0.0),
-            2.0,
-            material_shadow.clone(),
+
+    // a cube with hard edges, its faces stay joined once displaced
+    let mut positions = Vec::new();
+    let mut indices = Vec::new();
+    let mut normals = Vec::new();
+    for n in [Vec3::new(1.0, 0.0, 0.0), Vec3::new(0.0, 1.0, 0.0), Vec3::new(0.0, 0.0, 1.0)] {
+        for n in [n, n * -1.0] {
+            let onb = Onb::new(n);
+            let base = positions.len();
+            for (s, t) in [(-1.0, -1.0), (1.0, -1.0), (1.0, 1.0), (-1.0, 1.0)] {
+                positions.push(Vec3::new(-1.2, -0.5, -0.2) + onb.local(s, t, 1.0) * 0.4);
+                normals.push(n);
+            }
+            indices.push([base, base + 1, base + 2]);
+            indices.push([base, base + 2, base + 3]);
+        }
+    }
+    let stones = WorleyTexture::new(3.0).seed(2).feature(WorleyFeature::F2MinusF1).instancing();
+    let rock = Displacement::new(stones, 0.1, tolerance);
+    world.add(TriangleMesh::new_displaced(
+            positions,
+            indices,
+            normals,
+            Vec::new(),
+            Lambertian::new_from_color(Vec3::new(0.6, 0.55, 0.5)).instancing(),
+            &rock,
         ).instancing()
     );
-    
-    let material_difflight = Diffuselight::new_from_color(Vec3::new(4.0, 4.0, 4.0)).instancing();
+
+    camera.render(&(world.to_bvh()))
+}
+
+pub fn projections() -> RgbImage {
+    println!("choose projections");
+    let width = 480;
+    let height = 240;
+
+    // a ring of spheres around the camera, so that the panoramas have something on every side
+    let mut world = Hittable_list::default();
+    let checker = CheckerTexture::new_from_color(0.5, Vec3::new(0.2, 0.3, 0.1), Vec3::new(0.9, 0.9, 0.9)).instancing();
+    world.add(InfinitePlane::new(Vec3::zero(), Vec3::new(0.0, 1.0, 0.0), Lambertian::new(checker).instancing()).instancing());
+    for i in 0..8 {
+        let angle = i as f64 * PI / 4.0;
+        let color = Vec3::new(0.5 + 0.4 * angle.cos(), 0.5 + 0.4 * angle.sin(), 0.5 - 0.4 * angle.cos());
+        world.add(Sphere::new(
+                Vec3::new(4.0 * angle.sin(), 1.0, -4.0 * angle.cos()),
+                1.0,
+                Lambertian::new_from_color(color).instancing()
+            ).instancing()
+        );
+    }
+    let world = world.to_bvh();
+
+    let defocus_angle = 0.0;
+    let focus_dist = 10.0;
+    let vfov: f64 = 60.0;
+    let vup = Vec3::new(0.0, 1.0, 0.0);
+    let look_from = Vec3::new(0.0, 2.0, 0.0);
+    let look_at = Vec3::new(0.0, 1.0, -4.0);
+    let samples_per_pixel = 50;
+    let max_depth = 20;
+    let background = Vec3::new(0.7, 0.8, 1.0);
+
+    // the same view through every projection, stacked from top to bottom
+    let projections = [
+        Perspective::new().instancing(),
+        // narrow enough to leave out the spheres level with the camera
+        Orthographic::new(2.5).instancing(),
+        Fisheye::new(180.0).instancing(),
+        Equirectangular::new().instancing(),
+        Cylindrical::new(240.0).instancing(),
+    ];
+    let mut img: RgbImage = ImageBuffer::new(width, height * projections.len() as u32);
+    for (i, projection) in projections.into_iter().enumerate() {
+        let mut camera = Camera::new(width, height, samples_per_pixel, max_depth, vfov, look_from, look_at, vup, defocus_angle, focus_dist,background);
+        camera.set_projection(projection);
+        image::imageops::replace(&mut img, &camera.render(&world), 0, (i as u32 * height) as i64);
+    }
+    img
+}
+
+// a portrait lens wide open: only the middle sphere is sharp, scene units are meters
+pub fn physical_camera() -> RgbImage {
+    println!("choose physical camera");
+
+    let mut world = Hittable_list::default();
+    let checker = CheckerTexture::new_from_color(0.5, Vec3::new(0.2, 0.3, 0.1), Vec3::new(0.9, 0.9, 0.9)).instancing();
+    world.add(InfinitePlane::new(Vec3::zero(), Vec3::new(0.0, 1.0, 0.0), Lambertian::new(checker).instancing()).instancing());
+    for i in 0..5 {
+        let color = Vec3::new(0.9 - 0.2 * i as f64, 0.3, 0.1 + 0.2 * i as f64);
+        world.add(Sphere::new(
+                Vec3::new(-1.0 + 0.6 * i as f64, 0.4, -2.0 - 2.0 * i as f64),
+                0.4,
+                Lambertian::new_from_color(color).instancing()
+            ).instancing()
+        );
+    }
+
+    // 85mm at f/1.8 on full frame; 1/8000 s makes up for the wide aperture, about as bright as sunny 16
+    let camera = PhysicalCameraBuilder::new(600, 400)
+        .samples_per_pixel(100)
+        .max_depth(50)
+        .look_from(Vec3::new(0.0, 1.0, 3.0))
+        .look_at(Vec3::new(0.2, 0.4, -6.0))
+        .background(Vec3::new(0.7, 0.8, 1.0))
+        .sensor(SensorFormat::full_frame())
+        .focal_length(85.0)
+        .f_number(1.8)
+        .shutter_speed(1.0 / 8000.0)
+        .iso(100.0)
+        .focus_dist((Vec3::new(0.2, 0.4, -6.0) - Vec3::new(0.0, 1.0, 3.0)).length())
+        .build();
+
+    camera.render(&(world.to_bvh()))
+}
+
+// out of focus lights behind a sharp sphere, through a six blade diaphragm, a star shaped mask
+// and a round lens with cat's eye vignetting, stacked from top to bottom
+pub fn bokeh() -> RgbImage {
+    println!("choose bokeh");
+    let width = 480;
+    let height = 270;
+
+    let mut world = Hittable_list::default();
+    world.add(Sphere::new(Vec3::new(0.0, 0.0, 0.0), 1.0, Lambertian::new_from_color(Vec3::new(0.8, 0.3, 0.2)).instancing()).instancing());
     world.add(Quad::new(
-            Vec3::new(3.0, 1.0, -2.0),
-            Vec3::new(2.0, 0.0, 0.0),
-            Vec3::new(0.0, 2.0, 0.0),
-            material_difflight.clone(),
-        ).instancing()
-    );
-    world.add(Sphere::new(
-            Vec3::new(0.0, 7.0, 0.0),
-            2.0,
-            material_difflight.clone(),
+            Vec3::new(-2.0, 4.0, -2.0),
+            Vec3::new(4.0, 0.0, 0.0),
+            Vec3::new(0.0, 0.0, 4.0),
+            Diffuselight::new_from_color(Vec3::new(2.0, 2.0, 2.0)).instancing()
         ).instancing()
     );
+    // a wall of small lamps far behind
+    for j in 0..5 {
+        for i in 0..9 {
+            let color = Vec3::new(1.0, 0.6 + 0.1 * j as f64, 0.2 + 0.1 * i as f64) * 20.0;
+            world.add(Sphere::new(
+                    Vec3::new(-16.0 + 4.0 * i as f64, -4.0 + 3.0 * j as f64, -30.0),
+                    0.1,
+                    Diffuselight::new_from_color(color).instancing()
+                ).instancing()
+            );
+        }
+    }
+    let world = world.to_bvh();
 
-    let defocus_angle = 0.0;
-    let focus_dist = 10.0;
-    let vfov: f64 = 20.0;
+    let defocus_angle = 3.0;
+    let focus_dist = 6.0;
+    let vfov: f64 = 40.0;
     let vup = Vec3::new(0.0, 1.0, 0.0);
-    let look_from = Vec3::new(26.0, 3.0, 6.0);
-    let look_at = Vec3::new(0.0, 2.0, 0.0);
-    let samples_per_pixel = 100;
-    let max_depth = 50;
-    let background = Vec3::new(0.0, 0.0 , 0.0);
+    let look_from = Vec3::new(0.0, 0.0, 6.0);
+    let look_at = Vec3::new(0.0, 0.0, 0.0);
+    let samples_per_pixel = 200;
+    let max_depth = 20;
+    let background = Vec3::new(0.01, 0.01, 0.03);
 
-    let camera = Camera::new(width, height, samples_per_pixel, max_depth, vfov, look_from, look_at, vup, defocus_angle, focus_dist,background);
-    
-    let img = camera.render(&(world.to_bvh()));
+    let star_path = std::env::current_dir()
+        .unwrap()
+        .join(Path::new("aperture_star.png"));
+    let apertures = [
+        (PolygonAperture::new(6, 0.0).instancing(), 0.0),
+        (ImageAperture::load(&star_path), 0.0),
+        (CircularAperture::new().instancing(), 0.5),
+    ];
+    let mut img: RgbImage = ImageBuffer::new(width, height * apertures.len() as u32);
+    for (i, (aperture, cat_eye)) in apertures.into_iter().enumerate() {
+        let mut camera = Camera::new(width, height, samples_per_pixel, max_depth, vfov, look_from, look_at, vup, defocus_angle, focus_dist,background);
+        camera.set_aperture(aperture);
+        camera.set_cat_eye_vignetting(cat_eye);
+        image::imageops::replace(&mut img, &camera.render(&world), 0, (i as u32 * height) as i64);
+    }
     img
 }
 
@@ -531,15 +1582,14 @@ pub fn cornell_smoke() -> RgbImage {
     img
 }
 
-pub fn final_scene(width: i32, samples_per_pixel: i32, max_depth: i32) -> RgbImage {
-    println!("choose final scene");
-    let height = width;
-    let Rad = (PI / 4.0).cos();
-
-    let mut world = Hittable_list::default();
+// the world of final_scene, spheres and quads use the static primitive path
+pub fn final_scene_world() -> PrimitiveList {
+    let mut world = PrimitiveList::new();
 
     let ground = Lambertian::new_from_color(Vec3::new(0.48, 0.83, 0.53)).instancing();
-    let mut boxes1 = Hittable_list::default();
+    // a single unit box is shared by every instance, the boxes cost memory once
+    let unit_box = create_box(Vec3::zero(), Vec3::ones(), ground).to_bvh();
+    let mut boxes1 = TopLevelBVH::new();
     let boxes_per_side = 20;
     for i in 0..boxes_per_side {
         for j in 0..boxes_per_side {
@@ -555,54 +1605,36 @@ pub fn final_scene(width: i32, samples_per_pixel: i32, max_depth: i32) -> RgbIma
                 p0.z + w
             );
 
-            boxes1.add(create_box(p0, p1, ground.clone()).instancing());
+            boxes1.add_instance(unit_box.clone(), Transform::translate(p0) * Transform::scale(p1 - p0));
         }
     }
-    world.add(boxes1.to_bvh());
+    world.add_object(boxes1.build().instancing());
 
     let light = Diffuselight::new_from_color(Vec3::new(7.0, 7.0, 7.0)).instancing();
-    world.add(Quad::new(
-            Vec3::new(123.0, 554.0, 147.0),
-            Vec3::new(300.0, 0.0, 0.0),
-            Vec3::new(0.0, 0.0, 265.0),
-            light
-        ).instancing()
-    ); 
+    world.add_quad(
+        Vec3::new(123.0, 554.0, 147.0),
+        Vec3::new(300.0, 0.0, 0.0),
+        Vec3::new(0.0, 0.0, 265.0),
+        light
+    );
 
     let center1 = Vec3::new(400.0, 400.0, 200.0);
     let center2 = center1 + Vec3::new(30.0, 0.0, 0.0);
     let sphere_material = Lambertian::new_from_color(Vec3::new(0.7, 0.3, 0.1)).instancing();
-    world.add(Sphere::new_moving(
-            center1, 
-            center2,
-            50.0, 
-            sphere_material
-        ).instancing()
-    );
+    world.add_moving_sphere(center1, center2, 50.0, sphere_material);
 
-    world.add(Sphere::new(
-            Vec3::new(260.0, 150.0, 45.0),
-            50.0,
-            Dielectric::new(1.5).instancing()
-        ).instancing()
-    );
+    world.add_sphere(Vec3::new(260.0, 150.0, 45.0), 50.0, Dielectric::new(1.5).instancing());
 
-    world.add(Sphere::new(
-            Vec3::new(0.0, 150.0, 145.0),
-            50.0,
-            Metal::new(Vec3::new(0.8, 0.8, 0.9), 1.0).instancing()
-        ).instancing()
+    world.add_sphere(
+        Vec3::new(0.0, 150.0, 145.0),
+        50.0,
+        Metal::new(Vec3::new(0.8, 0.8, 0.9), 1.0).instancing()
     );
 
-    let boundary = Sphere::new(
-        Vec3::new(360.0, 150.0, 145.0),
-        70.0,
-        Dielectric::new(1.5).instancing()
-    ).instancing();
-    world.add(boundary.clone());
-
-    world.add(ConstantMedium::new_from_color(
-            boundary.clone(),
+    let boundary_material = Dielectric::new(1.5).instancing();
+    world.add_sphere(Vec3::new(360.0, 150.0, 145.0), 70.0, boundary_material.clone());
+    world.add_object(ConstantMedium::new_from_color(
+            Sphere::new(Vec3::new(360.0, 150.0, 145.0), 70.0, boundary_material).instancing(),
             0.2, 
             Vec3::new(0.2, 0.4, 0.9)
         ).instancing()
@@ -613,7 +1645,7 @@ pub fn final_scene(width: i32, samples_per_pixel: i32, max_depth: i32) -> RgbIma
         5000.0,
         Dielectric::new(1.5).instancing()
     ).instancing();
-    world.add(ConstantMedium::new_from_color(
+    world.add_object(ConstantMedium::new_from_color(
             boundary.clone(),
             0.0001, 
             Vec3::new(1.0, 1.0, 1.0)
@@ -625,41 +1657,31 @@ pub fn final_scene(width: i32, samples_per_pixel: i32, max_depth: i32) -> RgbIma
         .join(Path::new("earth_map.jpg"));
     let earth_texture = ImageTexture::new(&path).instancing();
     let material_earth = Lambertian::new(earth_texture).instancing();
-
-    world.add(Sphere::new(
-        Vec3::new(400.0, 200.0, 400.0),
-        100.0,
-        material_earth,
-    ).instancing());
+    world.add_sphere(Vec3::new(400.0, 200.0, 400.0), 100.0, material_earth);
 
     let pertext = NoiseTexture::new(0.2).instancing();
-    world.add(Sphere::new(
-            Vec3::new(220.0, 280.0, 300.0),
-            80.0,
-            Lambertian::new(pertext).instancing()
-        ).instancing()
-    );
+    world.add_sphere(Vec3::new(220.0, 280.0, 300.0), 80.0, Lambertian::new(pertext).instancing());
 
-    let mut boxes2 = Hittable_list::default();
+    let mut boxes2 = PrimitiveList::new();
     let white = Lambertian::new_from_color(Vec3::new(0.73, 0.73, 0.73)).instancing();
     let ns = 1000;
     for j in 0..ns {
-        boxes2.add(Sphere::new(
-                random_vec3_range(0.0, 165.0),
-                10.0,
-                white.clone()
-            ).instancing()
-        );
+        boxes2.add_sphere(random_vec3_range(0.0, 165.0), 10.0, white.clone());
     }
 
-    world.add(Translate::new(
+    world.add_object(Translate::new(
         RotateY::new(
-            boxes2.to_bvh(),
+            boxes2.build().instancing(),
             15.0
         ).instancing(),
         Vec3::new(-100.0, 270.0, 395.0)
     ).instancing());
 
+    world
+}
+
+pub fn final_scene_camera(width: i32, samples_per_pixel: i32, max_depth: i32) -> Camera {
+    let height = width;
     let defocus_angle = 0.0;
     let focus_dist = 10.0;
     let vfov: f64 = 40.0;
@@ -668,10 +1690,120 @@ pub fn final_scene(width: i32, samples_per_pixel: i32, max_depth: i32) -> RgbIma
     let look_at = Vec3::new(278.0, 278.0, 0.0);
     let background = Vec3::new(0.0, 0.0 , 0.0);
 
-    let camera = Camera::new(width as u32, height as u32, samples_per_pixel as u32, max_depth as u32, vfov, look_from, look_at, vup, defocus_angle, focus_dist,background);
-    
-    let img = camera.render(&(world.to_bvh()));
-    img
+    Camera::new(width as u32, height as u32, samples_per_pixel as u32, max_depth as u32, vfov, look_from, look_at, vup, defocus_angle, focus_dist,background)
+}
+
+pub fn final_scene(width: i32, samples_per_pixel: i32, max_depth: i32) -> RgbImage {
+    println!("choose final scene");
+    let world = final_scene_world().build().instancing();
+    let camera = final_scene_camera(width, samples_per_pixel, max_depth);
+    camera.render(&world)
+}
+
+// traces the same camera rays through final_scene built from the static primitives and from
+// the equivalent dyn Hittable objects, prints the time of each
+pub fn final_scene_benchmark(width: i32, samples_per_pixel: i32, max_depth: i32) {
+    println!("choose final scene benchmark");
+    let scene = final_scene_world();
+    let dynamic_world = scene.to_hittable_list().to_bvh();
+    let static_world = scene.build();
+    static_world.stats.info();
+    let static_world = static_world.instancing();
+    let camera = final_scene_camera(width, samples_per_pixel, max_depth);
+
+    let mut rays = Vec::new();
+    for j in 0..camera.height {
+        for i in 0..camera.width {
+            rays.extend(camera.get_ray(i, j, 0, 0));
+        }
+    }
+
+    // closest hit queries only, then whole paths (shading and sampling included)
+    let intersect = |world: &Arc<dyn Hittable + Send + Sync>| {
+        let start = Instant::now();
+        let mut hits = 0;
+        for _ in 0..samples_per_pixel {
+            for ray in &rays {
+                if world.hit(ray, Interval::new(0.001, f64::INFINITY)).is_some() {
+                    hits += 1;
+                }
+            }
+        }
+        (start.elapsed().as_secs_f64(), hits)
+    };
+    let trace = |world: &Arc<dyn Hittable + Send + Sync>| {
+        let start = Instant::now();
+        for _ in 0..samples_per_pixel {
+            for ray in &rays {
+                camera.ray_color(ray, world, camera.max_depth);
+            }
+        }
+        start.elapsed().as_secs_f64()
+    };
+
+    let queries = (rays.len() * samples_per_pixel as usize) as f64;
+    let (dynamic_time, dynamic_hits) = intersect(&dynamic_world);
+    let (static_time, static_hits) = intersect(&static_world);
+    println!("closest hit, dyn Hittable:    {:.3}s, {:.0} rays/s, {} hits", dynamic_time, queries / dynamic_time, dynamic_hits);
+    println!("closest hit, enum primitives: {:.3}s, {:.0} rays/s, {} hits", static_time, queries / static_time, static_hits);
+    println!("speedup: {:.2}x", dynamic_time / static_time);
+
+    let dynamic_time = trace(&dynamic_world);
+    let static_time = trace(&static_world);
+    println!("paths, dyn Hittable:    {:.3}s, {:.0} paths/s", dynamic_time, queries / dynamic_time);
+    println!("paths, enum primitives: {:.3}s, {:.0} paths/s", static_time, queries / static_time);
+    println!("speedup: {:.2}x", dynamic_time / static_time);
+}
+
+// closest hit queries through the f64 binary BVH and the f32 4-wide BVH, on the camera rays of
+// final_scene and on random rays crossing a cloud of small spheres
+#[cfg(feature = "simd")]
+pub fn bvh4_benchmark(width: i32, samples_per_pixel: i32) {
+    println!("choose bvh4 benchmark");
+    let camera = final_scene_camera(width, samples_per_pixel, 1);
+    let mut camera_rays = Vec::new();
+    for j in 0..camera.height {
+        for i in 0..camera.width {
+            for _ in 0..samples_per_pixel {
+                camera_rays.extend(camera.get_ray(i, j, 0, 0));
+            }
+        }
+    }
+
+    let mut cloud = Hittable_list::default();
+    let white = Lambertian::new_from_color(Vec3::new(0.73, 0.73, 0.73)).instancing();
+    for _ in 0..2000 {
+        cloud.add(Sphere::new(random_vec3_range(-100.0, 100.0), 2.0, white.clone()).instancing());
+    }
+    let cloud_rays: Vec<Ray> = (0..camera_rays.len())
+        .map(|_| Ray::new(random_vec3_range(-150.0, 150.0), random_vec3_range(-1.0, 1.0), 0.0))
+        .collect();
+
+    let scenes = [
+        ("final_scene", final_scene_world().to_hittable_list(), camera_rays),
+        ("sphere cloud", cloud, cloud_rays),
+    ];
+    for (name, list, rays) in &scenes {
+        let binary: Arc<dyn Hittable + Send + Sync> = FlatBVH::new(list).instancing();
+        let wide: Arc<dyn Hittable + Send + Sync> = BVH4::new(list).instancing();
+        let intersect = |world: &Arc<dyn Hittable + Send + Sync>| {
+            let start = Instant::now();
+            let mut hits = 0;
+            for ray in rays {
+                if world.hit(ray, Interval::new(0.001, f64::INFINITY)).is_some() {
+                    hits += 1;
+                }
+            }
+            (start.elapsed().as_secs_f64(), hits)
+        };
+        let (binary_time, binary_hits) = intersect(&binary);
+        let (wide_time, wide_hits) = intersect(&wide);
+        let queries = rays.len() as f64;
+        println!("{}:", name);
+        println!("  f64 binary BVH: {:.3}s, {:.0} rays/s, {} hits", binary_time, queries / binary_time, binary_hits);
+        println!("  f32 BVH4:       {:.3}s, {:.0} rays/s, {} hits", wide_time, queries / wide_time, wide_hits);
+        println!("  speedup: {:.2}x", binary_time / wide_time);
+    }
 }
 
 pub fn cornell_box_update() -> RgbImage {
@@ -771,11 +1903,13 @@ pub fn solar_system() -> RgbImage {
     let Rad = (PI / 4.0).cos();
 
     let mut world = Hittable_list::default();
+    // every map is decoded once, when a ray first hits it
+    let textures = TextureManager::default();
 
     let galaxy_path = std::env::current_dir()
         .unwrap()
         .join(Path::new("galaxy.jpeg"));
-    let galaxy_texture = ImageTexture::new(&galaxy_path).instancing();
+    let galaxy_texture = textures.get(&galaxy_path, ColorSpace::Srgb);
     let material_galaxy = Diffuselight::new(galaxy_texture).instancing();
     world.add(Quad::new(
             Vec3::new(-300.0, -200.0, -200.0),
@@ -788,55 +1922,55 @@ pub fn solar_system() -> RgbImage {
     let earth_path = std::env::current_dir()
         .unwrap()
         .join(Path::new("earth_map.jpg"));
-    let earth_texture = ImageTexture::new(&earth_path).instancing();
+    let earth_texture = textures.get(&earth_path, ColorSpace::Srgb);
     let material_earth = Diffuselight::new(earth_texture).instancing();
 
     let mars_path = std::env::current_dir()
         .unwrap()
         .join(Path::new("mars_map.jpg"));
-    let mars_texture = ImageTexture::new(&mars_path).instancing();
+    let mars_texture = textures.get(&mars_path, ColorSpace::Srgb);
     let material_mars = Diffuselight::new(mars_texture).instancing();
 
     let mercury_path = std::env::current_dir()
         .unwrap()
         .join(Path::new("mercury_map.jpg"));
-    let mercury_texture = ImageTexture::new(&mercury_path).instancing();
+    let mercury_texture = textures.get(&mercury_path, ColorSpace::Srgb);
     let material_mercury = Diffuselight::new(mercury_texture).instancing();
 
     let neptune_path = std::env::current_dir()
         .unwrap()
         .join(Path::new("neptune_map.jpg"));
-    let neptune_texture = ImageTexture::new(&neptune_path).instancing();
+    let neptune_texture = textures.get(&neptune_path, ColorSpace::Srgb);
     let material_neptune = Diffuselight::new(neptune_texture).instancing();
 
     let venus_path = std::env::current_dir()
         .unwrap()
         .join(Path::new("venus_map.jpg"));
-    let venus_texture = ImageTexture::new(&venus_path).instancing();
+    let venus_texture = textures.get(&venus_path, ColorSpace::Srgb);
     let material_venus = Diffuselight::new(venus_texture).instancing();
 
     let uranus_path = std::env::current_dir()
         .unwrap()
         .join(Path::new("uranus_map.jpg"));
-    let uranus_texture = ImageTexture::new(&uranus_path).instancing();
+    let uranus_texture = textures.get(&uranus_path, ColorSpace::Srgb);
     let material_uranus = Diffuselight::new(uranus_texture).instancing();
 
     let jupyter_path = std::env::current_dir()
         .unwrap()
         .join(Path::new("jupyter_map.jpg"));
-    let jupyter_texture = ImageTexture::new(&jupyter_path).instancing();
+    let jupyter_texture = textures.get(&jupyter_path, ColorSpace::Srgb);
     let material_jupyter = Diffuselight::new(jupyter_texture).instancing();
 
     let saturn_path = std::env::current_dir()
         .unwrap()
         .join(Path::new("saturn_map.jpg"));
-    let saturn_texture = ImageTexture::new(&saturn_path).instancing();
+    let saturn_texture = textures.get(&saturn_path, ColorSpace::Srgb);
     let material_saturn = Diffuselight::new(saturn_texture).instancing();
 
     let sun_path = std::env::current_dir()
         .unwrap()
         .join(Path::new("sun_map.jpg"));
-    let sun_texture = ImageTexture::new(&sun_path).instancing();
+    let sun_texture = textures.get(&sun_path, ColorSpace::Srgb);
     let material_sun = Diffuselight::new(sun_texture.clone()).instancing();
 
     world.add(Sphere::new(
@@ -887,6 +2021,14 @@ pub fn solar_system() -> RgbImage {
             material_saturn,
         ).instancing()
     );
+    world.add(Disk::new_annulus(
+            Vec3::new(-15.0, 25.0, -70.0),
+            Vec3::new(0.3, 1.0, 0.4),
+            8.0,
+            13.0,
+            Diffuselight::new_from_color(Vec3::new(0.75, 0.65, 0.5)).instancing(),
+        ).instancing()
+    );
 
     world.add(Sphere::new(
             Vec3::new(35.0, 15.0, -50.0), 
@@ -920,6 +2062,121 @@ pub fn solar_system() -> RgbImage {
     img
 }
 
+pub fn solar_system_animation(frame_count: usize) {
+    println!("choose solar system animation");
+    let width = 480;
+    let height = 270;
+    let fps = 24.0;
+    let duration = frame_count as f64 / fps;
+
+    let textures = TextureManager::default();
+    let load = |name: &str| {
+        let path = std::env::current_dir()
+            .unwrap()
+            .join(Path::new(name));
+        textures.get(&path, ColorSpace::Srgb)
+    };
+    let material_galaxy = Diffuselight::new(load("galaxy.jpeg")).instancing();
+    let material_sun = Diffuselight::new(load("sun_map.jpg")).instancing();
+
+    // the sun flares up halfway through
+    let sun_intensity = AnimationCurve::new(vec![
+        Keyframe::new_bezier(0.0, 1.0, 1.0, 1.0),
+        Keyframe::new_bezier(duration * 0.5, 2.5, 2.5, 2.5),
+        Keyframe::new_bezier(duration, 1.0, 1.0, 1.0),
+    ]);
+    // and swells with it
+    let sun_scale = AnimationCurve::new(vec![
+        Keyframe::new_bezier(0.0, Vec3::ones(), Vec3::ones(), Vec3::ones()),
+        Keyframe::new_bezier(duration * 0.5, Vec3::ones() * 1.15, Vec3::ones() * 1.15, Vec3::ones() * 1.15),
+        Keyframe::new_bezier(duration, Vec3::ones(), Vec3::ones(), Vec3::ones()),
+    ]);
+    // and a dust storm turns mars orange
+    let white = AnimationCurve::new_constant(Vec3::ones());
+    let dust_storm = AnimationCurve::new_linear(0.0, Vec3::ones(), duration, Vec3::new(1.4, 0.8, 0.5));
+
+    // (map, tint, radius, orbit radius, starting angle, seconds per orbit, seconds per spin)
+    let planets = vec![
+        (load("mercury_map.jpg"), white.clone(), 1.6, 10.0, 160.0, 4.0, 3.0),
+        (load("venus_map.jpg"), white.clone(), 1.3, 13.0, 20.0, 6.0, 5.0),
+        (load("earth_map.jpg"), white.clone(), 1.8, 16.5, 250.0, 8.0, 2.0),
+        (load("mars_map.jpg"), dust_storm, 1.6, 20.0, 110.0, 11.0, 2.0),
+        (load("jupyter_map.jpg"), white, 2.5, 25.0, 300.0, 16.0, 1.0),
+    ];
+
+    let defocus_angle = 0.0;
+    let focus_dist = 10.0;
+    let vfov: f64 = 60.0;
+    let vup = Vec3::new(0.0, 1.0, 0.0);
+    let look_from = Vec3::new(0.0, 0.0, 30.0);
+    let look_at = Vec3::new(0.0, 0.0, 0.0);
+    let samples_per_pixel = 64;
+    let max_depth = 20;
+    let background = Vec3::new(0.0, 0.0 , 0.0);
+
+    let mut template = Camera::new(width, height, samples_per_pixel, max_depth, vfov, look_from, look_at, vup, defocus_angle, focus_dist,background);
+    template.set_shutter(0.0, 0.5);
+
+    // rise above the orbital plane with an ease in / ease out
+    let mut rig = CameraRig::new(&template);
+    rig.look_from = AnimationCurve::new(vec![
+        Keyframe::new_bezier(0.0, look_from, look_from, look_from),
+        Keyframe::new_bezier(duration, Vec3::new(0.0, 18.0, 32.0), Vec3::new(0.0, 18.0, 32.0), Vec3::new(0.0, 18.0, 32.0)),
+    ]);
+
+    let output_dir = std::env::current_dir()
+        .unwrap()
+        .join(Path::new("output/solar_system_animation"));
+    render_sequence(&output_dir, frame_count, fps, 60, |time, frame_duration| {
+        let mut world = Hittable_list::default();
+        world.add(Quad::new(
+                Vec3::new(-300.0, -200.0, -200.0),
+                Vec3::new(0.0, 400.0, 0.0),
+                Vec3::new(600.0, 0.0, 0.0),
+                material_galaxy.clone()
+            ).instancing()
+        );
+
+        let sun_light = AnimatedLight::new(material_sun.clone(), sun_intensity.clone(), time, frame_duration).instancing();
+        let sun = Sphere::new(Vec3::zero(), 6.0, sun_light).instancing();
+        world.add(AnimatedObject::new(
+                sun,
+                AnimationCurve::new_constant(Vec3::zero()),
+                AnimationCurve::new_linear(0.0, AxisAngle::around_y(0.0), duration, AxisAngle::around_y(360.0 * duration / 25.0)),
+                sun_scale.clone(),
+                time,
+                frame_duration,
+            ).instancing()
+        );
+
+        for (map, tint, radius, orbit_radius, phase, orbit_period, spin_period) in &planets {
+            let material = Diffuselight::new(AnimatedTexture::new(map.clone(), tint.clone(), time, frame_duration).instancing()).instancing();
+            let planet = Sphere::new(Vec3::zero(), *radius, material).instancing();
+            // the spin axis leans toward the sun at the start of the orbit
+            let spin_axis = Vec3::new(-0.4, 1.0, 0.0);
+            let spinning = AnimatedObject::new(
+                planet,
+                AnimationCurve::new_constant(Vec3::new(*orbit_radius, 0.0, 0.0)),
+                AnimationCurve::new_linear(0.0, AxisAngle::new(spin_axis, 0.0), duration, AxisAngle::new(spin_axis, 360.0 * duration / spin_period)),
+                AnimationCurve::new_constant(Vec3::ones()),
+                time,
+                frame_duration,
+            ).instancing();
+            world.add(AnimatedObject::new(
+                    spinning,
+                    AnimationCurve::new_constant(Vec3::zero()),
+                    AnimationCurve::new_linear(0.0, AxisAngle::around_y(*phase), duration, AxisAngle::around_y(phase + 360.0 * duration / orbit_period)),
+                    AnimationCurve::new_constant(Vec3::ones()),
+                    time,
+                    frame_duration,
+                ).instancing()
+            );
+        }
+
+        (rig.camera_at(&template, time), world.to_bvh())
+    });
+}
+
 pub fn random_scene() -> RgbImage {
 
     println!("choose random scene");
@@ -1033,6 +2290,20 @@ fn main() {
     let quality = 60;
     let choice = 13;
 
+    if choice == 14 {
+        solar_system_animation(48);
+        return;
+    }
+    if choice == 15 {
+        final_scene_benchmark(400, 8, 40);
+        return;
+    }
+    #[cfg(feature = "simd")]
+    if choice == 16 {
+        bvh4_benchmark(400, 8);
+        return;
+    }
+
     let img = match choice {
         1 => bouncing_spheres(),
         2 => checkered_sphers(),
@@ -1046,6 +2317,22 @@ fn main() {
         10 => final_scene(400, 250, 4),
         11 => cornell_box_update(),
         12 => solar_system(),
+        17 => analytic_shapes(),
+        18 => planar_shapes(),
+        19 => csg_shapes(),
+        20 => sdf_shapes(),
+        21 => terrain(),
+        22 => hair(),
+        23 => point_cloud(),
+        24 => texture_mapping(),
+        25 => procedural_textures(),
+        26 => texture_graph(),
+        27 => bump_mapping(),
+        28 => alpha_cutout(),
+        29 => displacement_mapping(),
+        30 => projections(),
+        31 => physical_camera(),
+        32 => bokeh(),
         _ => random_scene(),
     };
 
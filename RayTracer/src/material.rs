@@ -4,6 +4,8 @@ use crate::hitable::*;
 use crate::ray::*;
 use crate::interval::*;
 use crate::texture::*;
+use crate::color::*;
+use crate::onb::*;
 
 use std::rc::Rc;
 use std::sync::Arc;
@@ -13,12 +15,26 @@ pub trait MaterialTrait {
     fn scatter(&self, r: &Ray, hit_record: &HitRecord, attenuation: &mut Vec3, scattered: &mut Ray) -> bool{
         false
     }
-    fn emitted(&self, u: f64, v: f64, p: Vec3) -> Vec3 {
+    fn emitted(&self, _hit_record: &HitRecord) -> Vec3 {
         Vec3::zero()
     }
     fn instancing(self) -> Arc<dyn MaterialTrait + Send + Sync>;
 }
 
+// offset rays of a specular bounce: each one leaves from its own point of the tangent plane and is
+// bent by the normal there, moved by dndx / dndy, so that curved mirrors and lenses widen or narrow
+// the footprint; bend takes the direction and the normal
+fn specular_differential(r: &Ray, hit_record: &HitRecord, bend: impl Fn(Vec3, Vec3) -> Vec3) -> Option<RayDifferential> {
+    let d = r.differential?;
+    let surface = hit_record.differentials?;
+    Some(RayDifferential {
+        rx_origin: hit_record.point + surface.dpdx,
+        rx_direction: bend(d.rx_direction, unit_vec(hit_record.normal + surface.dndx)),
+        ry_origin: hit_record.point + surface.dpdy,
+        ry_direction: bend(d.ry_direction, unit_vec(hit_record.normal + surface.dndy)),
+    })
+}
+
 pub struct Lambertian {
     pub tex: Arc<dyn TextureTrait + Send + Sync>,
 }
@@ -55,7 +71,7 @@ impl MaterialTrait for Lambertian {
         }
 
         *scattered = Ray::new(hit_record.point, scatter_direction, r.time);
-        *attenuation = self.tex.value(hit_record.u, hit_record.v, hit_record.point);
+        *attenuation = hit_record.tint(self.tex.sample(&TexCoord::from_hit(hit_record)));
         true
     }
 
@@ -80,9 +96,11 @@ impl Metal {
 
 impl MaterialTrait for Metal {
     fn scatter(&self, r: &Ray, hit_record: &HitRecord, attenuation: &mut Vec3, scattered: &mut Ray) -> bool {
+        let fuzz = unit_vec(random_in_unit_shpere()) * self.fuzz;
         let mut reflected = reflect(r.direction(), hit_record.normal);
-        reflected = unit_vec(reflected) + unit_vec(random_in_unit_shpere()) * self.fuzz;
+        reflected = unit_vec(reflected) + fuzz;
         *scattered = Ray::new(hit_record.point, reflected, r.time());
+        scattered.differential = specular_differential(r, hit_record, |d, n| unit_vec(reflect(d, n)) + fuzz);
         *attenuation = self.albedo;
         reflected * hit_record.normal > 0.0
     }
@@ -112,8 +130,14 @@ impl MaterialTrait for Dielectric {
         } else {
             self.refraction_index
         };
-        let refracted = refract(unit_vec(r.direction()), hit_record.normal, ratio);
+        let unit_direction = unit_vec(r.direction());
+        let refracted = refract(unit_direction, hit_record.normal, ratio);
         *scattered = Ray::new(hit_record.point, refracted, r.time());
+        // refract picks reflection at random, the offset rays follow the branch of the ray
+        let reflected = refracted == reflect(unit_direction, hit_record.normal);
+        scattered.differential = specular_differential(r, hit_record, |d, n| {
+            if reflected { reflect(unit_vec(d), n) } else { snell_refract(unit_vec(d), n, ratio) }
+        });
         true
     }
 
@@ -141,8 +165,8 @@ impl Diffuselight {
 }
 
 impl MaterialTrait for Diffuselight {
-    fn emitted(&self, u: f64, v: f64, p: Vec3) -> Vec3 {
-        self.tex.value(u, v, p)
+    fn emitted(&self, hit_record: &HitRecord) -> Vec3 {
+        self.tex.sample(&TexCoord::from_hit(hit_record))
     }
 
     fn instancing(self) -> Arc<dyn MaterialTrait + Send + Sync> {
@@ -171,7 +195,7 @@ impl Isotropic {
 impl MaterialTrait for Isotropic {
     fn scatter(&self, r: &Ray, hit_record: &HitRecord, attenuation: &mut Vec3, scattered: &mut Ray) -> bool {
         *scattered = Ray::new(hit_record.point, unit_vec(random_in_unit_shpere()), r.time());
-        *attenuation = self.tex.value(hit_record.u, hit_record.v, hit_record.point);
+        *attenuation = hit_record.tint(self.tex.sample(&TexCoord::from_hit(hit_record)));
         true
     }
 
@@ -179,3 +203,188 @@ impl MaterialTrait for Isotropic {
         Arc::new(self)
     }
 }
+
+// Kajiya-Kay hair around the fiber direction hit_record.dpdu
+// light is either reflected on the cone of directions making the same angle with the fiber
+// (uncolored, spread by roughness), or scattered all around it weighted by the sine to the fiber
+pub struct KajiyaKay {
+    pub tex: Arc<dyn TextureTrait + Send + Sync>,
+    // probability of a specular bounce
+    pub specular: f64,
+    pub roughness: f64,
+}
+
+impl KajiyaKay {
+    pub fn new(tex: Arc<dyn TextureTrait + Send + Sync>, specular: f64, roughness: f64) -> Self {
+        Self {
+            tex,
+            specular,
+            roughness,
+        }
+    }
+
+    pub fn new_from_color(albedo: Vec3, specular: f64, roughness: f64) -> Self {
+        Self::new(SolidColor::new(albedo).instancing(), specular, roughness)
+    }
+}
+
+impl MaterialTrait for KajiyaKay {
+    fn scatter(&self, r: &Ray, hit_record: &HitRecord, attenuation: &mut Vec3, scattered: &mut Ray) -> bool {
+        let tangent = if hit_record.dpdu.near_zero() {
+            Onb::new(hit_record.normal).u
+        } else {
+            unit_vec(hit_record.dpdu)
+        };
+
+        let direction = if random_f64_0_1() < self.specular {
+            let d = unit_vec(r.direction());
+            let along = d * tangent;
+            let radial = (1.0 - along * along).max(0.0).sqrt();
+            let around = Onb::new(tangent);
+            let phi = 2.0 * PI * random_f64_0_1();
+            *attenuation = Vec3::ones();
+            tangent * along
+                + around.u * (radial * phi.cos())
+                + around.v * (radial * phi.sin())
+                + random_in_unit_shpere() * self.roughness
+        } else {
+            let direction = unit_vec(random_in_unit_shpere());
+            let cosine = direction * tangent;
+            // the sine averages to pi / 4 over the sphere
+            let sine = (1.0 - cosine * cosine).max(0.0).sqrt();
+            *attenuation = hit_record.tint(self.tex.sample(&TexCoord::from_hit(hit_record))) * (sine * 4.0 / PI);
+            direction
+        };
+
+        *scattered = Ray::new(hit_record.point, direction, r.time());
+        true
+    }
+
+    fn instancing(self) -> Arc<dyn MaterialTrait + Send + Sync> {
+        Arc::new(self)
+    }
+}
+
+// frame of the shading normal: w is the normal, u follows dpdu and v the side of dpdv; any frame
+// around the normal when the primitive has no tangents
+fn tangent_frame(hit_record: &HitRecord) -> Onb {
+    let n = hit_record.normal;
+    let t = hit_record.dpdu - n * (hit_record.dpdu * n);
+    if t.squared_length() <= 1e-12 * hit_record.dpdu.squared_length() {
+        return Onb::new(n);
+    }
+    let u = unit_vec(t);
+    let v = n.cross(u);
+    Onb {
+        u,
+        v: if v * hit_record.dpdv < 0.0 { v * -1.0 } else { v },
+        w: n,
+    }
+}
+
+// the hit with another shading normal, which is bent back to face the ray if it turned away from it
+fn with_shading_normal<'a>(r: &Ray, hit_record: &HitRecord<'a>, normal: Vec3) -> HitRecord<'a> {
+    let to_viewer = unit_vec(r.direction()) * -1.0;
+    let facing = normal * to_viewer;
+    let normal = if facing < 1e-3 { unit_vec(normal + to_viewer * (1e-3 - facing)) } else { normal };
+    HitRecord {
+        normal,
+        ..*hit_record
+    }
+}
+
+// a tangent space normal map over any material: the map stores n * 0.5 + 0.5 with z along the
+// normal, x along dpdu and y toward dpdv, and should be loaded with ColorSpace::Raw so that it is
+// used as stored; strength scales the tilt, 0 leaves the surface as it is
+// usage: NormalMapMaterial::new(inner, ImageTexture::new_with_color_space(&path, ColorSpace::Raw).instancing(), 1.0)
+pub struct NormalMapMaterial {
+    inner: Arc<dyn MaterialTrait + Send + Sync>,
+    map: Arc<dyn TextureTrait + Send + Sync>,
+    strength: f64,
+}
+
+impl NormalMapMaterial {
+    pub fn new(inner: Arc<dyn MaterialTrait + Send + Sync>, map: Arc<dyn TextureTrait + Send + Sync>, strength: f64) -> Self {
+        Self { inner, map, strength }
+    }
+}
+
+impl MaterialTrait for NormalMapMaterial {
+    fn scatter(&self, r: &Ray, hit_record: &HitRecord, attenuation: &mut Vec3, scattered: &mut Ray) -> bool {
+        let c = self.map.sample(&TexCoord::from_hit(hit_record));
+        let local = Vec3::new((2.0 * c.x - 1.0) * self.strength, (2.0 * c.y - 1.0) * self.strength, (2.0 * c.z - 1.0).max(1e-3));
+        let normal = unit_vec(tangent_frame(hit_record).local_vec(local));
+        self.inner.scatter(r, &with_shading_normal(r, hit_record, normal), attenuation, scattered)
+    }
+
+    fn emitted(&self, hit_record: &HitRecord) -> Vec3 {
+        self.inner.emitted(hit_record)
+    }
+
+    fn instancing(self) -> Arc<dyn MaterialTrait + Send + Sync> {
+        Arc::new(self)
+    }
+}
+
+// a height map over any material: shaded as if the surface were moved out along its normal by
+// scale times the luminance of height, like the bump shader of Games101
+pub struct BumpMapMaterial {
+    inner: Arc<dyn MaterialTrait + Send + Sync>,
+    height: Arc<dyn TextureTrait + Send + Sync>,
+    scale: f64,
+}
+
+impl BumpMapMaterial {
+    pub fn new(inner: Arc<dyn MaterialTrait + Send + Sync>, height: Arc<dyn TextureTrait + Send + Sync>, scale: f64) -> Self {
+        Self { inner, height, scale }
+    }
+}
+
+impl MaterialTrait for BumpMapMaterial {
+    fn scatter(&self, r: &Ray, hit_record: &HitRecord, attenuation: &mut Vec3, scattered: &mut Ray) -> bool {
+        let n = hit_record.normal;
+        // tangents in the plane of the shading normal
+        let frame = tangent_frame(hit_record);
+        let (mut dpdu, mut dpdv) = (hit_record.dpdu - n * (hit_record.dpdu * n), hit_record.dpdv - n * (hit_record.dpdv * n));
+        if dpdu.cross(dpdv).squared_length() <= 1e-12 * dpdu.squared_length() * dpdv.squared_length() {
+            (dpdu, dpdv) = (frame.u, frame.v);
+        }
+
+        // finite differences over the pixel footprint, or a small fixed step
+        let tc = TexCoord::from_hit(hit_record);
+        let du = match 0.5 * (fabs(tc.dudx) + fabs(tc.dudy)) {
+            du if du > 0.0 => du,
+            _ => 0.0005,
+        };
+        let dv = match 0.5 * (fabs(tc.dvdx) + fabs(tc.dvdy)) {
+            dv if dv > 0.0 => dv,
+            _ => 0.0005,
+        };
+        let height = |tc: &TexCoord| luminance(self.height.sample(tc)) * self.scale;
+        let h = height(&tc);
+        let mut shifted_u = tc;
+        shifted_u.u += du;
+        shifted_u.p = tc.p + dpdu * du;
+        let mut shifted_v = tc;
+        shifted_v.v += dv;
+        shifted_v.p = tc.p + dpdv * dv;
+        let (dhdu, dhdv) = ((height(&shifted_u) - h) / du, (height(&shifted_v) - h) / dv);
+
+        // n minus the gradient of the height along the surface; dpdu and dpdv are the same on both
+        // faces (see HitRecord::dpdu) and heights go outward, so the back face sees them reversed
+        let (e, f, g) = (dpdu * dpdu, dpdu * dpdv, dpdv * dpdv);
+        let det = e * g - f * f;
+        let gradient = (dpdu * (g * dhdu - f * dhdv) + dpdv * (e * dhdv - f * dhdu)) / det;
+        let side = if hit_record.front_face { 1.0 } else { -1.0 };
+        let normal = unit_vec(n - gradient * side);
+        self.inner.scatter(r, &with_shading_normal(r, hit_record, normal), attenuation, scattered)
+    }
+
+    fn emitted(&self, hit_record: &HitRecord) -> Vec3 {
+        self.inner.emitted(hit_record)
+    }
+
+    fn instancing(self) -> Arc<dyn MaterialTrait + Send + Sync> {
+        Arc::new(self)
+    }
+}
@@ -0,0 +1,363 @@
+use crate::aabb::*;
+use crate::bvh::*;
+use crate::displacement::*;
+use crate::hitable::*;
+use crate::interval::*;
+use crate::material::*;
+use crate::ray::*;
+use crate::utils::*;
+use crate::vec3::*;
+
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+
+// positions, indices, normals and uvs, as TriangleMesh::new takes them
+pub type MeshData = (Vec<Vec3>, Vec<[usize; 3]>, Vec<Vec3>, Vec<(f64, f64)>);
+
+// indexed triangle mesh with its own BVH, built once and shared by every Instance of it
+pub struct TriangleMesh {
+    pub positions: Vec<Vec3>,
+    // per vertex, empty if the mesh has none
+    pub normals: Vec<Vec3>,
+    pub uvs: Vec<(f64, f64)>,
+    // triangles, stored in BVH leaf order
+    pub indices: Vec<[usize; 3]>,
+    pub material: Arc<dyn MaterialTrait + Send + Sync>,
+    pub nodes: Vec<LinearBVHNode>,
+    pub stats: BVHStats,
+}
+
+impl TriangleMesh {
+    pub fn new(
+        positions: Vec<Vec3>,
+        indices: Vec<[usize; 3]>,
+        normals: Vec<Vec3>,
+        uvs: Vec<(f64, f64)>,
+        material: Arc<dyn MaterialTrait + Send + Sync>,
+    ) -> TriangleMesh {
+        let bboxes: Vec<Aabb> = indices
+            .iter()
+            .map(|tri| {
+                let bbox = Aabb::new_from_point(positions[tri[0]], positions[tri[1]]);
+                Aabb::new_from_bbox(bbox, Aabb::new_from_point(positions[tri[2]], positions[tri[2]]))
+            })
+            .collect();
+        let (nodes, ordered, stats) = build_sah(&bboxes, DEFAULT_MAX_LEAF_SIZE);
+        let indices = ordered.iter().map(|&i| indices[i]).collect();
+        TriangleMesh {
+            positions,
+            normals,
+            uvs,
+            indices,
+            material,
+            nodes,
+            stats,
+        }
+    }
+
+    // subdivided and displaced before the BVH is built, see Displacement
+    pub fn new_displaced(
+        positions: Vec<Vec3>,
+        indices: Vec<[usize; 3]>,
+        normals: Vec<Vec3>,
+        uvs: Vec<(f64, f64)>,
+        material: Arc<dyn MaterialTrait + Send + Sync>,
+        displacement: &Displacement,
+    ) -> TriangleMesh {
+        let (positions, indices, normals, uvs) = displacement.apply((positions, indices, normals, uvs));
+        Self::new(positions, indices, normals, uvs, material)
+    }
+
+    pub fn new_from_obj(path: &Path, material: Arc<dyn MaterialTrait + Send + Sync>) -> TriangleMesh {
+        let (positions, indices, normals, uvs) = Self::read_obj(path);
+        Self::new(positions, indices, normals, uvs, material)
+    }
+
+    pub fn new_from_obj_displaced(path: &Path, material: Arc<dyn MaterialTrait + Send + Sync>, displacement: &Displacement) -> TriangleMesh {
+        let (positions, indices, normals, uvs) = displacement.apply(Self::read_obj(path));
+        Self::new(positions, indices, normals, uvs, material)
+    }
+
+    fn read_obj(path: &Path) -> MeshData {
+        let text = fs::read_to_string(path).expect("File not found");
+        Self::parse_obj(&text)
+    }
+
+    // Wavefront OBJ with v / vt / vn and polygonal faces (fan triangulated)
+    // normals and uvs are only kept when every face corner has them
+    fn parse_obj(text: &str) -> MeshData {
+        let mut obj_positions = Vec::new();
+        let mut obj_uvs = Vec::new();
+        let mut obj_normals = Vec::new();
+        // each distinct (v, vt, vn) corner becomes one mesh vertex
+        let mut corners: Vec<(usize, Option<usize>, Option<usize>)> = Vec::new();
+        let mut corner_index = std::collections::HashMap::new();
+        let mut indices = Vec::new();
+
+        // 1-based, or negative to count back from the last one read; None when left empty as in v//vn
+        let resolve = |line: usize, index: &str, count: usize| -> Option<usize> {
+            if index.is_empty() {
+                return None;
+            }
+            let i: i64 = index.parse().unwrap_or(0);
+            let resolved = if i < 0 { count as i64 + i } else { i - 1 };
+            if !(0..count as i64).contains(&resolved) {
+                panic!("OBJ line {}: invalid face index {}", line, index);
+            }
+            Some(resolved as usize)
+        };
+
+        for (number, line) in text.lines().enumerate() {
+            let number = number + 1;
+            let mut tokens = line.split_whitespace();
+            let floats = |tokens: std::str::SplitWhitespace| -> Vec<f64> {
+                tokens.filter_map(|t| t.parse().ok()).collect()
+            };
+            match tokens.next() {
+                Some("v") => {
+                    let v = floats(tokens);
+                    if v.len() < 3 {
+                        panic!("OBJ line {}: a vertex needs x y z", number);
+                    }
+                    obj_positions.push(Vec3::new(v[0], v[1], v[2]));
+                }
+                Some("vt") => {
+                    let v = floats(tokens);
+                    if v.is_empty() {
+                        panic!("OBJ line {}: a texture coordinate needs u", number);
+                    }
+                    obj_uvs.push((v[0], *v.get(1).unwrap_or(&0.0)));
+                }
+                Some("vn") => {
+                    let v = floats(tokens);
+                    if v.len() < 3 {
+                        panic!("OBJ line {}: a normal needs x y z", number);
+                    }
+                    obj_normals.push(Vec3::new(v[0], v[1], v[2]));
+                }
+                Some("f") => {
+                    let mut face = Vec::new();
+                    for corner in tokens {
+                        let mut parts = corner.split('/');
+                        let v = resolve(number, parts.next().unwrap_or(""), obj_positions.len())
+                            .unwrap_or_else(|| panic!("OBJ line {}: a face corner needs a vertex", number));
+                        let vt = parts.next().and_then(|s| resolve(number, s, obj_uvs.len()));
+                        let vn = parts.next().and_then(|s| resolve(number, s, obj_normals.len()));
+                        let key = (v, vt, vn);
+                        let index = *corner_index.entry(key).or_insert_with(|| {
+                            corners.push(key);
+                            corners.len() - 1
+                        });
+                        face.push(index);
+                    }
+                    for k in 1..face.len().saturating_sub(1) {
+                        indices.push([face[0], face[k], face[k + 1]]);
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        let positions = corners.iter().map(|c| obj_positions[c.0]).collect();
+        let normals = if corners.iter().all(|c| c.2.is_some()) {
+            corners.iter().map(|c| unit_vec(obj_normals[c.2.unwrap()])).collect()
+        } else {
+            Vec::new()
+        };
+        let uvs = if corners.iter().all(|c| c.1.is_some()) {
+            corners.iter().map(|c| obj_uvs[c.1.unwrap()]).collect()
+        } else {
+            Vec::new()
+        };
+        (positions, indices, normals, uvs)
+    }
+
+    fn hit_triangle(&self, triangle: usize, r: &Ray, ray_t: Interval) -> Option<(f64, f64, f64)> {
+        let [i0, i1, i2] = self.indices[triangle];
+        intersect_triangle(r, ray_t, self.positions[i0], self.positions[i1], self.positions[i2])
+    }
+
+    fn hit_record(&self, triangle: usize, r: &Ray, t: f64, b1: f64, b2: f64) -> HitRecord<'_> {
+        let [i0, i1, i2] = self.indices[triangle];
+        let b0 = 1.0 - b1 - b2;
+        let p0 = self.positions[i0];
+        let geometric_normal = unit_vec((self.positions[i1] - p0).cross(self.positions[i2] - p0));
+
+        let mut rec = HitRecord::new(t, r.at(t), geometric_normal, self.material.as_ref(), true, 0.0, 0.0);
+        rec.set_face_normal(*r, geometric_normal);
+        if !self.normals.is_empty() {
+            // shading normal, flipped to the side of the geometric one
+            let shading = unit_vec(self.normals[i0] * b0 + self.normals[i1] * b1 + self.normals[i2] * b2);
+            rec.normal = if shading * rec.normal < 0.0 { shading * -1.0 } else { shading };
+        }
+        let (p1, p2) = (self.positions[i1], self.positions[i2]);
+        if self.uvs.is_empty() {
+            rec.u = b1;
+            rec.v = b2;
+            rec.dpdu = p1 - p0;
+            rec.dpdv = p2 - p0;
+        } else {
+            rec.u = self.uvs[i0].0 * b0 + self.uvs[i1].0 * b1 + self.uvs[i2].0 * b2;
+            rec.v = self.uvs[i0].1 * b0 + self.uvs[i1].1 * b1 + self.uvs[i2].1 * b2;
+            (rec.dpdu, rec.dpdv) = triangle_dpduv([p0, p1, p2], [self.uvs[i0], self.uvs[i1], self.uvs[i2]]);
+        }
+        rec
+    }
+}
+
+// derivatives of the point along u and v on a triangle with texture coordinates,
+// zero when the uvs of the corners are degenerate
+pub fn triangle_dpduv(p: [Vec3; 3], uv: [(f64, f64); 3]) -> (Vec3, Vec3) {
+    let (du02, dv02) = (uv[0].0 - uv[2].0, uv[0].1 - uv[2].1);
+    let (du12, dv12) = (uv[1].0 - uv[2].0, uv[1].1 - uv[2].1);
+    let (dp02, dp12) = (p[0] - p[2], p[1] - p[2]);
+    let det = du02 * dv12 - dv02 * du12;
+    if fabs(det) < 1e-12 {
+        return (Vec3::zero(), Vec3::zero());
+    }
+    let dpdu = (dp02 * dv12 - dp12 * dv02) / det;
+    let dpdv = (dp12 * du02 - dp02 * du12) / det;
+    (dpdu, dpdv)
+}
+
+// Moller-Trumbore, returns (t, b1, b2) with the barycentrics of the second and third vertex
+pub fn intersect_triangle(r: &Ray, ray_t: Interval, p0: Vec3, p1: Vec3, p2: Vec3) -> Option<(f64, f64, f64)> {
+    let e1 = p1 - p0;
+    let e2 = p2 - p0;
+
+    let pvec = r.direction().cross(e2);
+    let det = e1 * pvec;
+    if fabs(det) < 1e-12 {
+        return None;
+    }
+    let inv_det = 1.0 / det;
+    let tvec = r.origin() - p0;
+    let b1 = (tvec * pvec) * inv_det;
+    if !(0.0..=1.0).contains(&b1) {
+        return None;
+    }
+    let qvec = tvec.cross(e1);
+    let b2 = (r.direction() * qvec) * inv_det;
+    if b2 < 0.0 || b1 + b2 > 1.0 {
+        return None;
+    }
+    let t = (e2 * qvec) * inv_det;
+    if !ray_t.surrounds(t) {
+        return None;
+    }
+    Some((t, b1, b2))
+}
+
+impl Hittable for TriangleMesh {
+    fn hit(&self, r: &Ray, ray_t: Interval) -> Option<HitRecord<'_>> {
+        let mut closest: Option<(usize, f64, f64, f64)> = None;
+        traverse_flat_bvh(&self.nodes, r, ray_t, |i, interval| {
+            let (t, b1, b2) = self.hit_triangle(i, r, interval)?;
+            closest = Some((i, t, b1, b2));
+            Some(t)
+        });
+        let (i, t, b1, b2) = closest?;
+        Some(self.hit_record(i, r, t, b1, b2))
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        if self.nodes.is_empty() {
+            Aabb::default()
+        } else {
+            self.nodes[0].bbox
+        }
+    }
+
+    fn instancing(self) -> Arc<dyn Hittable + Send + Sync> {
+        Arc::new(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(a: Vec3, b: Vec3) {
+        assert!((a - b).length() < 1e-12, "{:?} instead of {:?}", a, b);
+    }
+
+    #[test]
+    fn polygons_are_fan_triangulated() {
+        let (positions, indices, normals, uvs) = TriangleMesh::parse_obj(
+            "# a unit square and a pentagon
+             v 0 0 0
+             v 1 0 0
+             v 1 1 0
+             v 0 1 0
+             v 0.5 1.5 0
+             f 1 2 3 4
+             f 1 2 3 5 4",
+        );
+        assert_eq!(positions.len(), 5);
+        assert_eq!(indices, vec![[0, 1, 2], [0, 2, 3], [0, 1, 2], [0, 2, 4], [0, 4, 3]]);
+        assert!(normals.is_empty());
+        assert!(uvs.is_empty());
+    }
+
+    #[test]
+    fn corners_are_shared_by_their_whole_index() {
+        // the corners at v 1 and 3 appear with two different uvs, so they are split
+        let (positions, indices, normals, uvs) = TriangleMesh::parse_obj(
+            "v 0 0 0
+             v 1 0 0
+             v 0 1 0
+             v 1 1 0
+             vt 0 0
+             vt 1 0
+             vt 0 1
+             vt 1 1
+             vn 0 0 2
+             f 1/1/1 2/2/1 3/3/1
+             f 2/2/1 4/4/1 3/3/1
+             f 1/4/1 3/1/1 2/2/1",
+        );
+        assert_eq!(positions.len(), 6);
+        assert_eq!(indices, vec![[0, 1, 2], [1, 3, 2], [4, 5, 1]]);
+        assert_close(positions[4], Vec3::new(0.0, 0.0, 0.0));
+        assert_eq!(uvs[4], (1.0, 1.0));
+        // normals are normalized
+        assert!(normals.iter().all(|&n| n == Vec3::new(0.0, 0.0, 1.0)));
+    }
+
+    #[test]
+    fn negative_indices_count_back_from_what_was_read() {
+        let (positions, indices, normals, _) = TriangleMesh::parse_obj(
+            "v 0 0 0
+             v 1 0 0
+             v 0 1 0
+             vn 1 0 0
+             f -3//-1 -2//-1 -1//-1
+             v 5 5 5
+             f -4 -3 -1",
+        );
+        assert_eq!(indices.len(), 2);
+        assert_close(positions[indices[1][2]], Vec3::new(5.0, 5.0, 5.0));
+        // the second face has no normals, so the mesh keeps none
+        assert!(normals.is_empty());
+    }
+
+    #[test]
+    #[should_panic(expected = "OBJ line 4: invalid face index 0")]
+    fn index_zero_names_its_line() {
+        TriangleMesh::parse_obj("v 0 0 0\nv 1 0 0\nv 0 1 0\nf 0 1 2");
+    }
+
+    #[test]
+    #[should_panic(expected = "OBJ line 2: invalid face index 2")]
+    fn index_past_the_vertices_read_so_far() {
+        // the vertices after the face do not count yet
+        TriangleMesh::parse_obj("v 0 0 0\nf 1 2 3\nv 1 0 0\nv 0 1 0");
+    }
+
+    #[test]
+    #[should_panic(expected = "OBJ line 2: a vertex needs x y z")]
+    fn short_vertex() {
+        TriangleMesh::parse_obj("v 0 0 0\nv 1 0");
+    }
+}
@@ -0,0 +1,38 @@
+use crate::utils::*;
+use crate::vec3::*;
+
+// orthonormal basis, w is the given direction
+#[derive(Copy, Clone, Debug)]
+pub struct Onb {
+    pub u: Vec3,
+    pub v: Vec3,
+    pub w: Vec3,
+}
+
+impl Onb {
+    pub fn new(n: Vec3) -> Onb {
+        let w = unit_vec(n);
+        let a = if fabs(w.x) > 0.9 {
+            Vec3::new(0.0, 1.0, 0.0)
+        } else {
+            Vec3::new(1.0, 0.0, 0.0)
+        };
+        let v = unit_vec(w.cross(a));
+        let u = w.cross(v);
+        Onb { u, v, w }
+    }
+
+    // from basis coordinates to world
+    pub fn local(&self, x: f64, y: f64, z: f64) -> Vec3 {
+        self.u * x + self.v * y + self.w * z
+    }
+
+    pub fn local_vec(&self, a: Vec3) -> Vec3 {
+        self.local(a.x, a.y, a.z)
+    }
+
+    // from world to basis coordinates
+    pub fn to_local(&self, a: Vec3) -> Vec3 {
+        Vec3::new(a * self.u, a * self.v, a * self.w)
+    }
+}
@@ -1,48 +1,104 @@
 use crate::utils::*;
 use crate::vec3::*;
 
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
 
 const PERLIN_POINT_COUNT: usize = 256;
 
+// how the octaves of fbm / ridged add up: every octave is lacunarity times finer and gain times
+// weaker than the one before
+#[derive(Copy, Clone, Debug)]
+pub struct Fractal {
+    pub octaves: usize,
+    pub lacunarity: f64,
+    pub gain: f64,
+}
+
+impl Fractal {
+    pub fn new(octaves: usize, lacunarity: f64, gain: f64) -> Fractal {
+        Fractal {
+            octaves,
+            lacunarity,
+            gain,
+        }
+    }
+}
+
+impl Default for Fractal {
+    // what turb(p, 7) sums
+    fn default() -> Self {
+        Self::new(7, 2.0, 0.5)
+    }
+}
+
 pub struct Perlin {
     randvec: Vec<Vec3>,
+    // gradients of the 4d noise
+    randvec4: Vec<[f64; 4]>,
+    // the worley point of every cell, in [0, 1)^3 inside it
+    randpoint: Vec<Vec3>,
     perm_x: Vec<i32>,
     perm_y: Vec<i32>,
     perm_z: Vec<i32>,
+    perm_w: Vec<i32>,
 }
 
 impl Perlin {
     pub fn new() -> Self {
+        Self::new_from_rng(&mut rand::thread_rng())
+    }
+
+    // the same seed always gives the same noise
+    pub fn new_with_seed(seed: u64) -> Self {
+        Self::new_from_rng(&mut StdRng::seed_from_u64(seed))
+    }
+
+    fn new_from_rng(rng: &mut impl Rng) -> Self {
         let mut randvec = vec![Vec3::zero(); PERLIN_POINT_COUNT];
         for i in 0..PERLIN_POINT_COUNT {
-            randvec[i] = unit_vec(random_vec3());
+            randvec[i] = unit_vec(Vec3::new(rng.gen_range(-1.0..1.0), rng.gen_range(-1.0..1.0), rng.gen_range(-1.0..1.0)));
         }
+        let randvec4 = (0..PERLIN_POINT_COUNT)
+            .map(|_| {
+                let g: [f64; 4] = [(); 4].map(|_| rng.gen_range(-1.0..1.0));
+                let length = g.iter().map(|c| c * c).sum::<f64>().sqrt();
+                g.map(|c| c / length)
+            })
+            .collect();
+        let randpoint = (0..PERLIN_POINT_COUNT).map(|_| Vec3::new(rng.gen(), rng.gen(), rng.gen())).collect();
         Self {
             randvec,
-            perm_x: Self::generate_perm(),
-            perm_y: Self::generate_perm(),
-            perm_z: Self::generate_perm(),
+            randvec4,
+            randpoint,
+            perm_x: Self::generate_perm(rng),
+            perm_y: Self::generate_perm(rng),
+            perm_z: Self::generate_perm(rng),
+            perm_w: Self::generate_perm(rng),
         }
     }
 
-    pub fn generate_perm() -> Vec<i32> {
+    pub fn generate_perm(rng: &mut impl Rng) -> Vec<i32> {
         let mut p: Vec<i32> = vec![0; PERLIN_POINT_COUNT];
         for i in 0..PERLIN_POINT_COUNT {
             p[i] = i as i32;
         }
-        Self::random_permutation(p, PERLIN_POINT_COUNT) 
+        Self::random_permutation(p, PERLIN_POINT_COUNT, rng)
     }
 
-    pub fn random_permutation(mut p: Vec<i32>, n: usize) -> Vec<i32> {
+    pub fn random_permutation(mut p: Vec<i32>, n: usize, rng: &mut impl Rng) -> Vec<i32> {
         for i in (1..=n-1).rev() {
-            let target = random_int_range(0, i as i32) as usize;
-            let tmp = p[i];
-            p[i] = p[target];
-            p[target] = tmp;
+            let target = rng.gen_range(0..=i);
+            p.swap(i, target);
         }
         p
     }
 
+    // index into the random tables for the lattice cell (i, j, k)
+    fn hash(&self, i: i32, j: i32, k: i32) -> usize {
+        (self.perm_x[(i & 255) as usize] ^ self.perm_y[(j & 255) as usize] ^ self.perm_z[(k & 255) as usize]) as usize
+    }
+
     pub fn noise(&self, p:Vec3) -> f64 {
         let (u, v, w) = (
             p.x - p.x.floor(),
@@ -61,11 +117,7 @@ impl Perlin {
         for di in 0..2 as i32 {
             for dj in 0..2 as i32 {
               for dk in 0..2 as i32 {
-                c[di as usize][dj as usize][dk as usize] = self.randvec[
-                  (self.perm_x[((i + di) & 255) as usize] ^
-                  self.perm_y[((j + dj) & 255) as usize] ^
-                  self.perm_z[((k + dk) & 255) as usize]) as usize
-                ];
+                c[di as usize][dj as usize][dk as usize] = self.randvec[self.hash(i + di, j + dj, k + dk)];
               }
             }
         }
@@ -93,16 +145,94 @@ impl Perlin {
         accum
     }
 
-    pub fn turb(&self, mut p: Vec3, depth: usize) -> f64 {
+    // gradient noise in 4d, w is usually the time of an animation so that the pattern changes smoothly
+    pub fn noise4(&self, p: Vec3, w: f64) -> f64 {
+        let cell = [p.x.floor(), p.y.floor(), p.z.floor(), w.floor()];
+        let f = [p.x - cell[0], p.y - cell[1], p.z - cell[2], w - cell[3]];
+        let i = cell.map(|c| c as i32);
+
+        let mut accum = 0.0;
+        for corner in 0..16 {
+            let d = [corner & 1, (corner >> 1) & 1, (corner >> 2) & 1, (corner >> 3) & 1];
+            let hash = self.hash(i[0] + d[0], i[1] + d[1], i[2] + d[2]) ^ self.perm_w[((i[3] + d[3]) & 255) as usize] as usize;
+            let gradient = self.randvec4[hash];
+            let mut dot = 0.0;
+            let mut weight = 1.0;
+            for axis in 0..4 {
+                dot += gradient[axis] * (f[axis] - d[axis] as f64);
+                let smooth = f[axis] * f[axis] * (3.0 - 2.0 * f[axis]);
+                weight *= if d[axis] == 1 { smooth } else { 1.0 - smooth };
+            }
+            accum += dot * weight;
+        }
+        accum
+    }
+
+    // 3d noise, or 4d when w is given
+    fn noise_at(&self, p: Vec3, w: Option<f64>) -> f64 {
+        match w {
+            Some(w) => self.noise4(p, w),
+            None => self.noise(p),
+        }
+    }
+
+    // fractional brownian motion: signed, around 0
+    pub fn fbm(&self, mut p: Vec3, mut w: Option<f64>, fractal: Fractal) -> f64 {
         let mut accum = 0.0;
         let mut weight = 1.0;
 
-        for i in 0..depth {
-            accum += weight * self.noise(p);
-            weight *= 0.5;
-            p = p * 2.0;
+        for _ in 0..fractal.octaves {
+            accum += weight * self.noise_at(p, w);
+            weight *= fractal.gain;
+            p = p * fractal.lacunarity;
+            w = w.map(|w| w * fractal.lacunarity);
         }
 
-        fabs(accum)
-    } 
-}
\ No newline at end of file
+        accum
+    }
+
+    pub fn turb(&self, p: Vec3, depth: usize) -> f64 {
+        fabs(self.fbm(p, None, Fractal::new(depth, 2.0, 0.5)))
+    }
+
+    // in [0, 1], sharp crests where the noise of every octave crosses zero
+    pub fn ridged(&self, mut p: Vec3, mut w: Option<f64>, fractal: Fractal) -> f64 {
+        let mut accum = 0.0;
+        let mut weight = 1.0;
+        let mut total = 0.0;
+
+        for _ in 0..fractal.octaves {
+            let ridge = 1.0 - fabs(self.noise_at(p, w)).min(1.0);
+            accum += weight * ridge * ridge;
+            total += weight;
+            weight *= fractal.gain;
+            p = p * fractal.lacunarity;
+            w = w.map(|w| w * fractal.lacunarity);
+        }
+
+        if total > 0.0 { accum / total } else { 0.0 }
+    }
+
+    // cellular noise: distances from p to the closest and the second closest of the points
+    // scattered one per unit cell
+    pub fn worley(&self, p: Vec3) -> (f64, f64) {
+        let (i, j, k) = (p.x.floor() as i32, p.y.floor() as i32, p.z.floor() as i32);
+        let (mut f1, mut f2) = (f64::INFINITY, f64::INFINITY);
+        for di in -1..=1 {
+            for dj in -1..=1 {
+                for dk in -1..=1 {
+                    let (ci, cj, ck) = (i + di, j + dj, k + dk);
+                    let point = Vec3::new(ci as f64, cj as f64, ck as f64) + self.randpoint[self.hash(ci, cj, ck)];
+                    let distance = (point - p).length();
+                    if distance < f1 {
+                        f2 = f1;
+                        f1 = distance;
+                    } else if distance < f2 {
+                        f2 = distance;
+                    }
+                }
+            }
+        }
+        (f1, f2)
+    }
+}
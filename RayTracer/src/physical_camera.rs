@@ -0,0 +1,289 @@
+use crate::camera::*;
+use crate::vec3::*;
+
+// film / sensor dimensions in millimeters
+#[derive(Copy, Clone, Debug)]
+pub struct SensorFormat {
+    pub width: f64,
+    pub height: f64,
+}
+
+impl SensorFormat {
+    pub fn new(width: f64, height: f64) -> Self {
+        Self { width, height }
+    }
+
+    pub fn full_frame() -> Self {
+        Self::new(36.0, 24.0)
+    }
+
+    pub fn aps_c() -> Self {
+        Self::new(23.6, 15.6)
+    }
+
+    pub fn micro_four_thirds() -> Self {
+        Self::new(17.3, 13.0)
+    }
+
+    pub fn super_35() -> Self {
+        Self::new(24.89, 18.66)
+    }
+
+    pub fn one_inch() -> Self {
+        Self::new(13.2, 8.8)
+    }
+}
+
+// the "sunny 16" settings (f/16, 1/100 s, ISO 100) give an exposure of 1.0,
+// i.e. the same brightness as a camera built with Camera::new
+const EXPOSURE_CALIBRATION: f64 = 16.0 * 16.0 * 100.0;
+
+// builds a Camera from photographic parameters
+// usage: PhysicalCameraBuilder::new(800, 450).look_from(..).look_at(..).focal_length(35.0).f_number(2.8).build()
+pub struct PhysicalCameraBuilder {
+    width: u32,
+    height: u32,
+    samples_per_pixel: u32,
+    max_depth: u32,
+    look_from: Vec3,
+    look_at: Vec3,
+    vup: Vec3,
+    background: Vec3,
+    focal_length: f64,
+    sensor: SensorFormat,
+    f_number: f64,
+    focus_dist: Option<f64>,
+    shutter_speed: f64,
+    shutter_offset: f64,
+    frame_rate: f64,
+    iso: f64,
+    units_per_meter: f64,
+}
+
+impl PhysicalCameraBuilder {
+    pub fn new(width: u32, height: u32) -> Self {
+        Self {
+            width,
+            height,
+            samples_per_pixel: 100,
+            max_depth: 50,
+            look_from: Vec3::new(0.0, 0.0, 1.0),
+            look_at: Vec3::zero(),
+            vup: Vec3::new(0.0, 1.0, 0.0),
+            background: Vec3::zero(),
+            focal_length: 50.0,
+            sensor: SensorFormat::full_frame(),
+            f_number: 16.0,
+            focus_dist: None,
+            shutter_speed: 1.0 / 100.0,
+            shutter_offset: 0.0,
+            frame_rate: 24.0,
+            iso: 100.0,
+            units_per_meter: 1.0,
+        }
+    }
+
+    pub fn samples_per_pixel(mut self, samples_per_pixel: u32) -> Self {
+        self.samples_per_pixel = samples_per_pixel;
+        self
+    }
+
+    pub fn max_depth(mut self, max_depth: u32) -> Self {
+        self.max_depth = max_depth;
+        self
+    }
+
+    pub fn look_from(mut self, look_from: Vec3) -> Self {
+        self.look_from = look_from;
+        self
+    }
+
+    pub fn look_at(mut self, look_at: Vec3) -> Self {
+        self.look_at = look_at;
+        self
+    }
+
+    pub fn vup(mut self, vup: Vec3) -> Self {
+        self.vup = vup;
+        self
+    }
+
+    pub fn background(mut self, background: Vec3) -> Self {
+        self.background = background;
+        self
+    }
+
+    // millimeters
+    pub fn focal_length(mut self, focal_length: f64) -> Self {
+        self.focal_length = focal_length;
+        self
+    }
+
+    pub fn sensor(mut self, sensor: SensorFormat) -> Self {
+        self.sensor = sensor;
+        self
+    }
+
+    pub fn f_number(mut self, f_number: f64) -> Self {
+        self.f_number = f_number;
+        self
+    }
+
+    // world units, defaults to the distance between look_from and look_at
+    pub fn focus_dist(mut self, focus_dist: f64) -> Self {
+        self.focus_dist = Some(focus_dist);
+        self
+    }
+
+    // exposure time in seconds
+    pub fn shutter_speed(mut self, shutter_speed: f64) -> Self {
+        self.shutter_speed = shutter_speed;
+        self
+    }
+
+    // delay between the start of the frame and the shutter opening, in seconds
+    pub fn shutter_offset(mut self, shutter_offset: f64) -> Self {
+        self.shutter_offset = shutter_offset;
+        self
+    }
+
+    // ray time 0..1 spans one frame, so the frame rate converts shutter seconds to ray times
+    pub fn frame_rate(mut self, frame_rate: f64) -> Self {
+        self.frame_rate = frame_rate;
+        self
+    }
+
+    pub fn iso(mut self, iso: f64) -> Self {
+        self.iso = iso;
+        self
+    }
+
+    // scale of the scene, used to size the aperture in world units
+    pub fn units_per_meter(mut self, units_per_meter: f64) -> Self {
+        self.units_per_meter = units_per_meter;
+        self
+    }
+
+    // the film height actually covered by the image once the sensor is fitted to its aspect ratio
+    pub fn film_height(&self) -> f64 {
+        let image_aspect = self.width as f64 / self.height as f64;
+        let sensor_aspect = self.sensor.width / self.sensor.height;
+        if image_aspect >= sensor_aspect {
+            self.sensor.width / image_aspect
+        } else {
+            self.sensor.height
+        }
+    }
+
+    pub fn vfov(&self) -> f64 {
+        (2.0 * (self.film_height() / (2.0 * self.focal_length)).atan()).to_degrees()
+    }
+
+    // radius of the entrance pupil in world units
+    pub fn aperture_radius(&self) -> f64 {
+        self.focal_length / (2.0 * self.f_number) * 0.001 * self.units_per_meter
+    }
+
+    pub fn exposure(&self) -> f64 {
+        EXPOSURE_CALIBRATION * self.shutter_speed * self.iso / (100.0 * self.f_number * self.f_number)
+    }
+
+    pub fn build(&self) -> Camera {
+        let focus_dist = self
+            .focus_dist
+            .unwrap_or_else(|| (self.look_from - self.look_at).length());
+        let defocus_angle = (2.0 * (self.aperture_radius() / focus_dist).atan()).to_degrees();
+
+        let mut camera = Camera::new(
+            self.width,
+            self.height,
+            self.samples_per_pixel,
+            self.max_depth,
+            self.vfov(),
+            self.look_from,
+            self.look_at,
+            self.vup,
+            defocus_angle,
+            focus_dist,
+            self.background,
+        );
+
+        let shutter_open = (self.shutter_offset * self.frame_rate).clamp(0.0, 1.0);
+        let shutter_close = (shutter_open + self.shutter_speed * self.frame_rate).clamp(0.0, 1.0);
+        camera.set_shutter(shutter_open, shutter_close);
+        camera.set_exposure(self.exposure());
+        camera
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(found: f64, expected: f64) {
+        assert!((found - expected).abs() <= 1e-9 * expected.abs().max(1.0), "{} instead of {}", found, expected);
+    }
+
+    #[test]
+    fn vfov_from_focal_length_and_sensor() {
+        // 3:2 like the sensor: the whole 24mm height, 2 atan(12 / 50) = 26.9915 degrees
+        let builder = PhysicalCameraBuilder::new(300, 200).focal_length(50.0);
+        assert_close(builder.film_height(), 24.0);
+        assert_close(builder.vfov(), 26.991466561591622);
+        // 16:9 crops the top and bottom: 36 / (16 / 9) = 20.25mm, 2 atan(10.125 / 50) = 22.8952 degrees
+        let builder = PhysicalCameraBuilder::new(1920, 1080).focal_length(50.0);
+        assert_close(builder.film_height(), 20.25);
+        assert_close(builder.vfov(), 22.89519252737121);
+        // narrower than the sensor keeps the height: 2 atan(7.8 / 35) = 25.1269 degrees on APS-C
+        let builder = PhysicalCameraBuilder::new(400, 400).sensor(SensorFormat::aps_c()).focal_length(35.0);
+        assert_close(builder.vfov(), 25.126938797036754);
+        assert_close(builder.build().vfov, 25.126938797036754);
+    }
+
+    #[test]
+    fn defocus_angle_from_the_f_number() {
+        // 50mm at f/2 is a 25mm pupil, 12.5mm = 0.0125m radius seen from 5m: 2 atan(0.0025) = 0.28648 degrees
+        let camera = PhysicalCameraBuilder::new(300, 200)
+            .look_from(Vec3::new(0.0, 0.0, 5.0))
+            .focal_length(50.0)
+            .f_number(2.0)
+            .build();
+        assert_close(camera.focus_dist, 5.0);
+        assert_close(camera.defocus_angle, 0.2864783007366131);
+        // the same in centimeters: a 1.25 unit radius seen from 500 units is the same angle
+        let camera = PhysicalCameraBuilder::new(300, 200)
+            .focal_length(50.0)
+            .f_number(2.0)
+            .units_per_meter(100.0)
+            .focus_dist(500.0)
+            .build();
+        assert_close(camera.defocus_angle, 0.2864783007366131);
+    }
+
+    #[test]
+    fn sunny_16_exposure() {
+        // f/16, 1/100 s, ISO 100 is the reference
+        assert_close(PhysicalCameraBuilder::new(300, 200).exposure(), 1.0);
+        // each stop doubles or halves it: f/8 lets in four times the light
+        assert_close(PhysicalCameraBuilder::new(300, 200).f_number(8.0).exposure(), 4.0);
+        // ISO 400 at 1/400 s is the same exposure
+        let builder = PhysicalCameraBuilder::new(300, 200).iso(400.0).shutter_speed(1.0 / 400.0);
+        assert_close(builder.exposure(), 1.0);
+        assert_close(builder.build().exposure, 1.0);
+    }
+
+    #[test]
+    fn shutter_in_frame_time() {
+        // a 180 degree shutter at 24 fps is open for the first half of the frame
+        let camera = PhysicalCameraBuilder::new(300, 200).shutter_speed(1.0 / 48.0).frame_rate(24.0).build();
+        assert_close(camera.shutter_open, 0.0);
+        assert_close(camera.shutter_close, 0.5);
+        let camera = PhysicalCameraBuilder::new(300, 200)
+            .shutter_speed(1.0 / 96.0)
+            .shutter_offset(1.0 / 96.0)
+            .frame_rate(24.0)
+            .build();
+        assert_close(camera.shutter_open, 0.25);
+        assert_close(camera.shutter_close, 0.5);
+    }
+}
@@ -0,0 +1,183 @@
+use crate::disk::*;
+
+use std::f64::consts::PI;
+use std::sync::Arc;
+
+// what part of a plane a Quad covers, in the planar coordinates (alpha, beta) of Q + alpha * u + beta * v
+pub trait PlanarShapeTrait {
+    // texture coordinates of the point if it is inside
+    fn contains(&self, alpha: f64, beta: f64) -> Option<(f64, f64)>;
+    // (alpha_min, alpha_max, beta_min, beta_max) of the shape, used for the bounding box
+    fn bounds(&self) -> (f64, f64, f64, f64);
+    // ((dalpha/du, dbeta/du), (dalpha/dv, dbeta/dv)) at a point of the shape, the default is for
+    // shapes whose texture coordinates are (alpha, beta)
+    fn uv_derivatives(&self, _alpha: f64, _beta: f64) -> ((f64, f64), (f64, f64)) {
+        ((1.0, 0.0), (0.0, 1.0))
+    }
+    fn instancing(self) -> Arc<dyn PlanarShapeTrait + Send + Sync>;
+}
+
+// the original quad: Q is a corner, u and v are the edges
+pub struct Parallelogram;
+
+impl PlanarShapeTrait for Parallelogram {
+    fn contains(&self, alpha: f64, beta: f64) -> Option<(f64, f64)> {
+        if (0.0..=1.0).contains(&alpha) && (0.0..=1.0).contains(&beta) {
+            Some((alpha, beta))
+        } else {
+            None
+        }
+    }
+
+    fn bounds(&self) -> (f64, f64, f64, f64) {
+        (0.0, 1.0, 0.0, 1.0)
+    }
+
+    fn instancing(self) -> Arc<dyn PlanarShapeTrait + Send + Sync> {
+        Arc::new(self)
+    }
+}
+
+// triangle Q, Q + u, Q + v, the texture coordinates are the barycentrics of the last two
+pub struct Triangle2D;
+
+impl PlanarShapeTrait for Triangle2D {
+    fn contains(&self, alpha: f64, beta: f64) -> Option<(f64, f64)> {
+        if alpha >= 0.0 && beta >= 0.0 && alpha + beta <= 1.0 {
+            Some((alpha, beta))
+        } else {
+            None
+        }
+    }
+
+    fn bounds(&self) -> (f64, f64, f64, f64) {
+        (0.0, 1.0, 0.0, 1.0)
+    }
+
+    fn instancing(self) -> Arc<dyn PlanarShapeTrait + Send + Sync> {
+        Arc::new(self)
+    }
+}
+
+// ellipse centered on Q with semi-axes u and v
+pub struct Ellipse;
+
+impl PlanarShapeTrait for Ellipse {
+    fn contains(&self, alpha: f64, beta: f64) -> Option<(f64, f64)> {
+        if alpha * alpha + beta * beta <= 1.0 {
+            Some(((alpha + 1.0) / 2.0, (beta + 1.0) / 2.0))
+        } else {
+            None
+        }
+    }
+
+    fn uv_derivatives(&self, _alpha: f64, _beta: f64) -> ((f64, f64), (f64, f64)) {
+        ((2.0, 0.0), (0.0, 2.0))
+    }
+
+    fn bounds(&self) -> (f64, f64, f64, f64) {
+        (-1.0, 1.0, -1.0, 1.0)
+    }
+
+    fn instancing(self) -> Arc<dyn PlanarShapeTrait + Send + Sync> {
+        Arc::new(self)
+    }
+}
+
+// elliptic ring centered on Q, inner is the hole size relative to the outer semi-axes u and v
+// u is the angle, v goes from the inner to the outer edge
+pub struct Annulus2D {
+    pub inner: f64,
+}
+
+impl Annulus2D {
+    pub fn new(inner: f64) -> Self {
+        Self { inner }
+    }
+}
+
+impl PlanarShapeTrait for Annulus2D {
+    fn contains(&self, alpha: f64, beta: f64) -> Option<(f64, f64)> {
+        let rho2 = alpha * alpha + beta * beta;
+        if rho2 > 1.0 || rho2 < self.inner * self.inner {
+            return None;
+        }
+        Some((azimuth_uv(alpha, beta), (rho2.sqrt() - self.inner) / (1.0 - self.inner)))
+    }
+
+    fn uv_derivatives(&self, alpha: f64, beta: f64) -> ((f64, f64), (f64, f64)) {
+        let rho = (alpha * alpha + beta * beta).sqrt().max(1e-8);
+        let radial = (1.0 - self.inner) / rho;
+        ((-beta * 2.0 * PI, alpha * 2.0 * PI), (alpha * radial, beta * radial))
+    }
+
+    fn bounds(&self) -> (f64, f64, f64, f64) {
+        (-1.0, 1.0, -1.0, 1.0)
+    }
+
+    fn instancing(self) -> Arc<dyn PlanarShapeTrait + Send + Sync> {
+        Arc::new(self)
+    }
+}
+
+// polygon given by its outline and any number of holes, all in (alpha, beta) coordinates
+// inside is decided with the even-odd rule, so the winding of the rings does not matter
+// the texture coordinates map the bounds of the outline to [0, 1]^2
+pub struct Polygon2D {
+    pub outline: Vec<(f64, f64)>,
+    pub holes: Vec<Vec<(f64, f64)>>,
+    bounds: (f64, f64, f64, f64),
+}
+
+impl Polygon2D {
+    pub fn new(outline: Vec<(f64, f64)>, holes: Vec<Vec<(f64, f64)>>) -> Self {
+        let mut bounds = (f64::INFINITY, f64::NEG_INFINITY, f64::INFINITY, f64::NEG_INFINITY);
+        for &(a, b) in &outline {
+            bounds = (bounds.0.min(a), bounds.1.max(a), bounds.2.min(b), bounds.3.max(b));
+        }
+        Self { outline, holes, bounds }
+    }
+
+    // whether the ray going from (a, b) towards +alpha crosses the ring an odd number of times
+    fn odd_crossings(ring: &[(f64, f64)], a: f64, b: f64) -> bool {
+        let mut odd = false;
+        for i in 0..ring.len() {
+            let (a0, b0) = ring[i];
+            let (a1, b1) = ring[(i + 1) % ring.len()];
+            if (b0 > b) != (b1 > b) && a < a0 + (b - b0) / (b1 - b0) * (a1 - a0) {
+                odd = !odd;
+            }
+        }
+        odd
+    }
+}
+
+impl PlanarShapeTrait for Polygon2D {
+    fn contains(&self, alpha: f64, beta: f64) -> Option<(f64, f64)> {
+        let (a_min, a_max, b_min, b_max) = self.bounds;
+        if alpha < a_min || alpha > a_max || beta < b_min || beta > b_max {
+            return None;
+        }
+        let mut inside = Self::odd_crossings(&self.outline, alpha, beta);
+        for hole in &self.holes {
+            inside ^= Self::odd_crossings(hole, alpha, beta);
+        }
+        if !inside {
+            return None;
+        }
+        Some(((alpha - a_min) / (a_max - a_min), (beta - b_min) / (b_max - b_min)))
+    }
+
+    fn uv_derivatives(&self, _alpha: f64, _beta: f64) -> ((f64, f64), (f64, f64)) {
+        let (a_min, a_max, b_min, b_max) = self.bounds;
+        ((a_max - a_min, 0.0), (0.0, b_max - b_min))
+    }
+
+    fn bounds(&self) -> (f64, f64, f64, f64) {
+        self.bounds
+    }
+
+    fn instancing(self) -> Arc<dyn PlanarShapeTrait + Send + Sync> {
+        Arc::new(self)
+    }
+}
@@ -0,0 +1,86 @@
+use crate::aabb::*;
+use crate::hitable::*;
+use crate::interval::*;
+use crate::material::*;
+use crate::onb::*;
+use crate::ray::*;
+use crate::utils::*;
+use crate::vec3::*;
+
+use std::sync::Arc;
+
+// infinite plane through point, e.g. a ground that never ends
+// (u, v) are the plane coordinates divided by uv_scale, wrapped to [0, 1)
+pub struct InfinitePlane {
+    pub point: Vec3,
+    pub normal: Vec3,
+    pub uv_scale: f64,
+    pub material: Arc<dyn MaterialTrait + Send + Sync>,
+    onb: Onb,
+    bbox: Aabb,
+}
+
+impl InfinitePlane {
+    pub fn new(point: Vec3, normal: Vec3, material: Arc<dyn MaterialTrait + Send + Sync>) -> InfinitePlane {
+        Self::new_with_uv_scale(point, normal, 1.0, material)
+    }
+
+    pub fn new_with_uv_scale(point: Vec3, normal: Vec3, uv_scale: f64, material: Arc<dyn MaterialTrait + Send + Sync>) -> InfinitePlane {
+        let onb = Onb::new(normal);
+        let n = onb.w;
+        // an axis aligned plane stays thin along its normal, any other one spans the whole space
+        let bound = |axis_normal: f64, p: f64| {
+            if fabs(axis_normal) > 1.0 - 1e-12 {
+                Interval::new(p, p)
+            } else {
+                Interval::new(f64::NEG_INFINITY, f64::INFINITY)
+            }
+        };
+        let bbox = Aabb::new(bound(n.x, point.x), bound(n.y, point.y), bound(n.z, point.z));
+        InfinitePlane {
+            point,
+            normal: n,
+            uv_scale,
+            material,
+            onb,
+            bbox,
+        }
+    }
+}
+
+impl Hittable for InfinitePlane {
+    fn hit(&self, r: &Ray, ray_t: Interval) -> Option<HitRecord<'_>> {
+        let denom = self.normal * r.direction();
+        if fabs(denom) < 1e-12 {
+            return None;
+        }
+        let t = (self.normal * (self.point - r.origin())) / denom;
+        if !ray_t.surrounds(t) {
+            return None;
+        }
+        let point = r.at(t);
+        let local = self.onb.to_local(point - self.point) / self.uv_scale;
+
+        let mut rec = HitRecord::new(
+            t,
+            point,
+            self.normal,
+            self.material.as_ref(),
+            true,
+            local.x.rem_euclid(1.0),
+            local.y.rem_euclid(1.0),
+        );
+        rec.dpdu = self.onb.u * self.uv_scale;
+        rec.dpdv = self.onb.v * self.uv_scale;
+        rec.set_face_normal(*r, self.normal);
+        Some(rec)
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.bbox
+    }
+
+    fn instancing(self) -> Arc<dyn Hittable + Send + Sync> {
+        Arc::new(self)
+    }
+}
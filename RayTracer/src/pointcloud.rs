@@ -0,0 +1,552 @@
+use crate::aabb::*;
+use crate::bvh::*;
+use crate::disk::*;
+use crate::hitable::*;
+use crate::interval::*;
+use crate::material::*;
+use crate::onb::*;
+use crate::ray::*;
+use crate::sphere::*;
+use crate::utils::*;
+use crate::vec3::*;
+
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+
+#[derive(Copy, Clone, PartialEq)]
+pub enum PointShape {
+    Sphere,
+    // oriented by the point normal, or facing the ray when the cloud has no normals
+    Disk,
+}
+
+// (t, outward normal, u, v, dpdu, dpdv) of a hit point
+type PointHit = (f64, Vec3, f64, f64, Vec3, Vec3);
+
+// points with a radius each and optional per point color and normal, in their own BVH
+// the color reaches the material through HitRecord::color
+pub struct PointCloud {
+    pub positions: Vec<Vec3>,
+    pub radii: Vec<f64>,
+    // per point, empty if the cloud has none
+    pub colors: Vec<Vec3>,
+    pub normals: Vec<Vec3>,
+    pub shape: PointShape,
+    pub material: Arc<dyn MaterialTrait + Send + Sync>,
+    pub nodes: Vec<LinearBVHNode>,
+    pub stats: BVHStats,
+}
+
+impl PointCloud {
+    pub fn new(
+        positions: Vec<Vec3>,
+        radii: Vec<f64>,
+        colors: Vec<Vec3>,
+        normals: Vec<Vec3>,
+        shape: PointShape,
+        material: Arc<dyn MaterialTrait + Send + Sync>,
+    ) -> PointCloud {
+        assert!(radii.len() == positions.len(), "a point cloud needs one radius per point");
+        let bboxes: Vec<Aabb> = (0..positions.len())
+            .map(|i| {
+                if shape == PointShape::Disk && !normals.is_empty() {
+                    disk_bbox(positions[i], normals[i], radii[i])
+                } else {
+                    let r = Vec3::ones() * radii[i];
+                    Aabb::new_from_point(positions[i] - r, positions[i] + r)
+                }
+            })
+            .collect();
+        let (nodes, ordered, stats) = build_sah(&bboxes, DEFAULT_MAX_LEAF_SIZE);
+        // attributes are stored in BVH leaf order
+        let reorder = |values: Vec<Vec3>| {
+            if values.is_empty() {
+                values
+            } else {
+                ordered.iter().map(|&i| values[i]).collect()
+            }
+        };
+        let radii = ordered.iter().map(|&i| radii[i]).collect();
+        PointCloud {
+            positions: reorder(positions),
+            radii,
+            colors: reorder(colors),
+            normals: reorder(normals),
+            shape,
+            material,
+            nodes,
+            stats,
+        }
+    }
+
+    // one point per line: "x y z", "x y z r g b" or "x y z r g b nx ny nz"
+    // colors are 0-255 if any of them is above 1, 0-1 otherwise
+    pub fn new_from_xyz(path: &Path, radius: f64, shape: PointShape, material: Arc<dyn MaterialTrait + Send + Sync>) -> Result<PointCloud, String> {
+        let text = fs::read_to_string(path).map_err(|err| format!("point cloud {}: {}", path.display(), err))?;
+        let mut positions = Vec::new();
+        let mut colors = Vec::new();
+        let mut normals = Vec::new();
+        for (number, line) in text.lines().enumerate().map(|(i, line)| (i + 1, line.trim())) {
+            if line.is_empty() || line.starts_with('#') || line.starts_with("//") {
+                continue;
+            }
+            let values = line
+                .split(|c: char| c.is_whitespace() || c == ',' || c == ';')
+                .filter(|s| !s.is_empty())
+                .map(|s| s.parse().map_err(|_| format!("point cloud {} line {}: invalid number {}", path.display(), number, s)))
+                .collect::<Result<Vec<f64>, String>>()?;
+            if values.len() < 3 {
+                return Err(format!("point cloud {} line {}: a point needs at least x y z", path.display(), number));
+            }
+            positions.push(Vec3::new(values[0], values[1], values[2]));
+            if values.len() >= 6 {
+                colors.push(Vec3::new(values[3], values[4], values[5]));
+            }
+            if values.len() >= 9 {
+                normals.push(unit_vec(Vec3::new(values[6], values[7], values[8])));
+            }
+        }
+        // attributes only count when every point has them
+        if colors.len() != positions.len() {
+            colors.clear();
+        }
+        if normals.len() != positions.len() {
+            normals.clear();
+        }
+        if colors.iter().any(|c| c.x > 1.0 || c.y > 1.0 || c.z > 1.0) {
+            colors = colors.iter().map(|&c| c / 255.0).collect();
+        }
+        let radii = vec![radius; positions.len()];
+        Ok(Self::new(positions, radii, colors, normals, shape, material))
+    }
+
+    // vertices of an ascii or binary PLY file: x, y, z and optionally nx, ny, nz, red, green, blue
+    // (integers are 0-255) and radius, which replaces the given one
+    pub fn new_from_ply(path: &Path, radius: f64, shape: PointShape, material: Arc<dyn MaterialTrait + Send + Sync>) -> Result<PointCloud, String> {
+        let error = |err: String| format!("point cloud {}: {}", path.display(), err);
+        let data = fs::read(path).map_err(|err| error(err.to_string()))?;
+        let vertices = read_ply_vertices(&data).map_err(error)?;
+        let column = |name: &str| vertices.names.iter().position(|n| n == name).ok_or_else(|| error(format!("PLY vertices without {}", name)));
+        let (x, y, z) = (column("x")?, column("y")?, column("z")?);
+        let column = |name: &str| column(name).ok();
+        let normal = match (column("nx"), column("ny"), column("nz")) {
+            (Some(nx), Some(ny), Some(nz)) => Some((nx, ny, nz)),
+            _ => None,
+        };
+        let color = match (column("red").or(column("r")), column("green").or(column("g")), column("blue").or(column("b"))) {
+            (Some(r), Some(g), Some(b)) => Some((r, g, b)),
+            _ => None,
+        };
+        let radius_column = column("radius");
+
+        let mut positions = Vec::with_capacity(vertices.rows.len());
+        let mut radii = Vec::with_capacity(vertices.rows.len());
+        let mut colors = Vec::new();
+        let mut normals = Vec::new();
+        for row in &vertices.rows {
+            positions.push(Vec3::new(row[x], row[y], row[z]));
+            radii.push(radius_column.map_or(radius, |i| row[i]));
+            if let Some((nx, ny, nz)) = normal {
+                normals.push(unit_vec(Vec3::new(row[nx], row[ny], row[nz])));
+            }
+            if let Some((r, g, b)) = color {
+                let scale = if vertices.integer[r] { 1.0 / 255.0 } else { 1.0 };
+                colors.push(Vec3::new(row[r], row[g], row[b]) * scale);
+            }
+        }
+        Ok(Self::new(positions, radii, colors, normals, shape, material))
+    }
+
+    fn hit_point(&self, i: usize, r: &Ray, ray_t: Interval) -> Option<PointHit> {
+        let center = self.positions[i];
+        let radius = self.radii[i];
+        match self.shape {
+            PointShape::Sphere => {
+                let oc = center - r.origin();
+                let a = r.direction().squared_length();
+                let h = r.direction() * oc;
+                let c = oc.squared_length() - radius * radius;
+                let delta = h * h - a * c;
+                if delta < 0.0 {
+                    return None;
+                }
+                let sqrt_delta = delta.sqrt();
+                let mut t = (h - sqrt_delta) / a;
+                if !ray_t.surrounds(t) {
+                    t = (h + sqrt_delta) / a;
+                    if !ray_t.surrounds(t) {
+                        return None;
+                    }
+                }
+                let outward_normal = (r.at(t) - center) / radius;
+                let (mut u, mut v) = (0.0, 0.0);
+                Sphere::get_sphere_uv(outward_normal, &mut u, &mut v);
+                let (dpdu, dpdv) = Sphere::get_sphere_dpduv(outward_normal * radius);
+                Some((t, outward_normal, u, v, dpdu, dpdv))
+            }
+            PointShape::Disk => {
+                let normal = if self.normals.is_empty() {
+                    unit_vec(r.direction()) * -1.0
+                } else {
+                    self.normals[i]
+                };
+                let denom = normal * r.direction();
+                if fabs(denom) < 1e-12 {
+                    return None;
+                }
+                let t = (normal * (center - r.origin())) / denom;
+                if !ray_t.surrounds(t) {
+                    return None;
+                }
+                let onb = Onb::new(normal);
+                let local = onb.to_local(r.at(t) - center);
+                if local.x * local.x + local.y * local.y > radius * radius {
+                    return None;
+                }
+                Some((t, normal, 0.5 + local.x / (2.0 * radius), 0.5 + local.y / (2.0 * radius), onb.u * (2.0 * radius), onb.v * (2.0 * radius)))
+            }
+        }
+    }
+}
+
+impl Hittable for PointCloud {
+    fn hit(&self, r: &Ray, ray_t: Interval) -> Option<HitRecord<'_>> {
+        let mut closest: Option<(usize, PointHit)> = None;
+        traverse_flat_bvh(&self.nodes, r, ray_t, |i, interval| {
+            let hit = self.hit_point(i, r, interval)?;
+            closest = Some((i, hit));
+            Some(hit.0)
+        });
+        let (i, (t, normal, u, v, dpdu, dpdv)) = closest?;
+        let mut rec = HitRecord::new(t, r.at(t), normal, self.material.as_ref(), true, u, v);
+        rec.color = self.colors.get(i).copied();
+        rec.dpdu = dpdu;
+        rec.dpdv = dpdv;
+        rec.set_face_normal(*r, normal);
+        Some(rec)
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        if self.nodes.is_empty() {
+            Aabb::default()
+        } else {
+            self.nodes[0].bbox
+        }
+    }
+
+    fn instancing(self) -> Arc<dyn Hittable + Send + Sync> {
+        Arc::new(self)
+    }
+}
+
+// the vertex element of a PLY file as rows of numbers
+struct PlyVertices {
+    names: Vec<String>,
+    // whether each property is an integer type
+    integer: Vec<bool>,
+    rows: Vec<Vec<f64>>,
+}
+
+#[derive(Copy, Clone, PartialEq)]
+enum PlyFormat {
+    Ascii,
+    LittleEndian,
+    BigEndian,
+}
+
+enum PlyProperty {
+    Scalar(String, String),
+    // count type, item type
+    List(String, String),
+}
+
+struct PlyElement {
+    name: String,
+    count: usize,
+    properties: Vec<PlyProperty>,
+}
+
+fn ply_type_size(ty: &str) -> Result<usize, String> {
+    match ty {
+        "char" | "uchar" | "int8" | "uint8" => Ok(1),
+        "short" | "ushort" | "int16" | "uint16" => Ok(2),
+        "int" | "uint" | "float" | "int32" | "uint32" | "float32" => Ok(4),
+        "double" | "float64" => Ok(8),
+        _ => Err(format!("unknown PLY type {}", ty)),
+    }
+}
+
+fn read_ply_binary(bytes: &[u8], ty: &str, big_endian: bool) -> f64 {
+    macro_rules! read {
+        ($t:ty, $n:expr) => {{
+            let raw: [u8; $n] = bytes[..$n].try_into().unwrap();
+            (if big_endian { <$t>::from_be_bytes(raw) } else { <$t>::from_le_bytes(raw) }) as f64
+        }};
+    }
+    match ty {
+        "char" | "int8" => read!(i8, 1),
+        "uchar" | "uint8" => read!(u8, 1),
+        "short" | "int16" => read!(i16, 2),
+        "ushort" | "uint16" => read!(u16, 2),
+        "int" | "int32" => read!(i32, 4),
+        "uint" | "uint32" => read!(u32, 4),
+        "float" | "float32" => read!(f32, 4),
+        "double" | "float64" => read!(f64, 8),
+        // ply_type_size rejected the other types
+        _ => unreachable!(),
+    }
+}
+
+fn read_ply_vertices(data: &[u8]) -> Result<PlyVertices, String> {
+    let header_end = data
+        .windows(b"end_header".len())
+        .position(|w| w == b"end_header")
+        .ok_or("not a PLY file")?;
+    let header = String::from_utf8_lossy(&data[..header_end]);
+    // the body starts on the line after end_header
+    let mut body_start = header_end + b"end_header".len();
+    while body_start < data.len() && data[body_start] != b'\n' {
+        body_start += 1;
+    }
+    body_start += 1;
+
+    let mut format = PlyFormat::Ascii;
+    let mut elements: Vec<PlyElement> = Vec::new();
+    for line in header.lines() {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        match words.as_slice() {
+            ["format", "ascii", ..] => format = PlyFormat::Ascii,
+            ["format", "binary_little_endian", ..] => format = PlyFormat::LittleEndian,
+            ["format", "binary_big_endian", ..] => format = PlyFormat::BigEndian,
+            ["element", name, count] => elements.push(PlyElement {
+                name: name.to_string(),
+                count: count.parse().map_err(|_| format!("invalid PLY element count {}", count))?,
+                properties: Vec::new(),
+            }),
+            ["property", "list", count_type, item_type, _] => {
+                ply_type_size(count_type)?;
+                ply_type_size(item_type)?;
+                elements
+                    .last_mut()
+                    .ok_or("PLY property outside of an element")?
+                    .properties
+                    .push(PlyProperty::List(count_type.to_string(), item_type.to_string()))
+            }
+            ["property", ty, name] => {
+                ply_type_size(ty)?;
+                elements
+                    .last_mut()
+                    .ok_or("PLY property outside of an element")?
+                    .properties
+                    .push(PlyProperty::Scalar(ty.to_string(), name.to_string()))
+            }
+            _ => {}
+        }
+    }
+
+    let body = &data[body_start.min(data.len())..];
+    let text = if format == PlyFormat::Ascii { String::from_utf8_lossy(body).into_owned() } else { String::new() };
+    let mut words = text.split_whitespace();
+    let mut offset = 0;
+    let mut next_value = |ty: &str| -> Result<f64, String> {
+        if format == PlyFormat::Ascii {
+            let word = words.next().ok_or("PLY file ends early")?;
+            word.parse().map_err(|_| format!("invalid number {} in PLY file", word))
+        } else {
+            let size = ply_type_size(ty)?;
+            if offset + size > body.len() {
+                return Err("PLY file ends early".to_string());
+            }
+            let value = read_ply_binary(&body[offset..], ty, format == PlyFormat::BigEndian);
+            offset += size;
+            Ok(value)
+        }
+    };
+
+    for element in &elements {
+        let mut rows = Vec::with_capacity(if element.name == "vertex" { element.count } else { 0 });
+        for _ in 0..element.count {
+            let mut row = Vec::new();
+            for property in &element.properties {
+                match property {
+                    PlyProperty::Scalar(ty, _) => row.push(next_value(ty)?),
+                    PlyProperty::List(count_type, item_type) => {
+                        let count = next_value(count_type)? as usize;
+                        for _ in 0..count {
+                            next_value(item_type)?;
+                        }
+                    }
+                }
+            }
+            if element.name == "vertex" {
+                rows.push(row);
+            }
+        }
+        if element.name == "vertex" {
+            let scalars = element.properties.iter().filter_map(|p| match p {
+                PlyProperty::Scalar(ty, name) => Some((ty, name)),
+                PlyProperty::List(..) => None,
+            });
+            let (names, integer) = scalars
+                .map(|(ty, name)| (name.clone(), !matches!(ty.as_str(), "float" | "float32" | "double" | "float64")))
+                .unzip();
+            return Ok(PlyVertices { names, integer, rows });
+        }
+    }
+    Err("PLY file without vertices".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    // deleted when dropped, also when the test panics
+    struct TempFile(PathBuf);
+
+    impl TempFile {
+        fn new(name: &str, data: &[u8]) -> TempFile {
+            let path = std::env::temp_dir().join(format!("ray_tracer_{}_{}", std::process::id(), name));
+            fs::write(&path, data).unwrap();
+            TempFile(path)
+        }
+    }
+
+    impl Drop for TempFile {
+        fn drop(&mut self) {
+            let _ = fs::remove_file(&self.0);
+        }
+    }
+
+    fn white() -> Arc<dyn MaterialTrait + Send + Sync> {
+        Lambertian::new_from_color(Vec3::ones()).instancing()
+    }
+
+    // the cloud keeps its points in BVH order
+    fn point_index(cloud: &PointCloud, position: Vec3) -> usize {
+        cloud.positions.iter().position(|&p| (p - position).length() < 1e-12).expect("point not found")
+    }
+
+    fn assert_close(a: Vec3, b: Vec3) {
+        assert!((a - b).length() < 1e-9, "{:?} instead of {:?}", a, b);
+    }
+
+    const HEADER_END: &str = "end_header\n";
+
+    fn binary_ply(format: &str, big_endian: bool) -> Vec<u8> {
+        let mut data = format!(
+            "ply\nformat {} 1.0\nelement vertex 2\nproperty float x\nproperty float y\nproperty float z\n\
+             property uchar red\nproperty uchar green\nproperty uchar blue\nelement face 0\n\
+             property list uchar int vertex_indices\n{}",
+            format, HEADER_END
+        )
+        .into_bytes();
+        for (position, color) in [([1.0f32, 2.0, 3.0], [255u8, 0, 51]), ([-1.0, 0.5, 0.0], [0, 255, 0])] {
+            for x in position {
+                data.extend(if big_endian { x.to_be_bytes() } else { x.to_le_bytes() });
+            }
+            data.extend(color);
+        }
+        data
+    }
+
+    #[test]
+    fn xyz_columns_separators_and_comments() {
+        let file = TempFile::new(
+            "columns.xyz",
+            b"# comment\n// another one\n\n0 0 0 255 0 51 0 0 2\n1,2,3,0,255,0,3,0,0\n4;5;6;0;0;255;0;1;0\n",
+        );
+        let cloud = PointCloud::new_from_xyz(&file.0, 0.1, PointShape::Sphere, white()).unwrap();
+        assert_eq!(cloud.positions.len(), 3);
+        assert_eq!(cloud.radii, vec![0.1; 3]);
+        let i = point_index(&cloud, Vec3::new(0.0, 0.0, 0.0));
+        // one component above 1 means all colors are 0-255
+        assert_close(cloud.colors[i], Vec3::new(1.0, 0.0, 0.2));
+        assert_close(cloud.normals[i], Vec3::new(0.0, 0.0, 1.0));
+        let i = point_index(&cloud, Vec3::new(1.0, 2.0, 3.0));
+        assert_close(cloud.normals[i], Vec3::new(1.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn xyz_attributes_only_count_when_every_point_has_them() {
+        let file = TempFile::new("partial.xyz", b"0 0 0 0.5 0.5 0.5\n1 1 1\n");
+        let cloud = PointCloud::new_from_xyz(&file.0, 0.1, PointShape::Disk, white()).unwrap();
+        assert_eq!(cloud.positions.len(), 2);
+        assert!(cloud.colors.is_empty());
+        assert!(cloud.normals.is_empty());
+    }
+
+    #[test]
+    fn xyz_errors_name_the_file_and_line() {
+        let file = TempFile::new("short.xyz", b"0 0 0\n1 1\n");
+        let err = PointCloud::new_from_xyz(&file.0, 0.1, PointShape::Sphere, white()).err().unwrap();
+        assert_eq!(err, format!("point cloud {} line 2: a point needs at least x y z", file.0.display()));
+        let file = TempFile::new("number.xyz", b"# x y z\n0 0 zero\n");
+        let err = PointCloud::new_from_xyz(&file.0, 0.1, PointShape::Sphere, white()).err().unwrap();
+        assert_eq!(err, format!("point cloud {} line 2: invalid number zero", file.0.display()));
+    }
+
+    #[test]
+    fn missing_files_are_errors() {
+        let path = std::env::temp_dir().join(format!("ray_tracer_{}_missing.xyz", std::process::id()));
+        let err = PointCloud::new_from_xyz(&path, 0.1, PointShape::Sphere, white()).err().unwrap();
+        assert!(err.starts_with(&format!("point cloud {}: ", path.display())), "{}", err);
+        let err = PointCloud::new_from_ply(&path.with_extension("ply"), 0.1, PointShape::Sphere, white()).err().unwrap();
+        assert!(err.starts_with(&format!("point cloud {}: ", path.with_extension("ply").display())), "{}", err);
+    }
+
+    #[test]
+    fn ply_ascii_skips_other_elements() {
+        let data = format!(
+            "ply\nformat ascii 1.0\ncomment faces first\nelement face 1\nproperty list uchar int vertex_indices\n\
+             element vertex 2\nproperty float x\nproperty float y\nproperty float z\nproperty float radius\n\
+             property uchar red\nproperty uchar green\nproperty uchar blue\n{}\
+             3 0 1 1\n0 0 0 0.5 255 0 0\n1 2 3 0.25 0 255 0\n",
+            HEADER_END
+        );
+        let vertices = read_ply_vertices(data.as_bytes()).unwrap();
+        assert_eq!(vertices.names, ["x", "y", "z", "radius", "red", "green", "blue"]);
+        assert_eq!(vertices.integer, [false, false, false, false, true, true, true]);
+        assert_eq!(vertices.rows, vec![vec![0.0, 0.0, 0.0, 0.5, 255.0, 0.0, 0.0], vec![1.0, 2.0, 3.0, 0.25, 0.0, 255.0, 0.0]]);
+
+        let file = TempFile::new("ascii.ply", data.as_bytes());
+        let cloud = PointCloud::new_from_ply(&file.0, 0.1, PointShape::Sphere, white()).unwrap();
+        // the radius column replaces the given radius, integer colors are 0-255
+        let i = point_index(&cloud, Vec3::new(1.0, 2.0, 3.0));
+        assert_eq!(cloud.radii[i], 0.25);
+        assert_close(cloud.colors[i], Vec3::new(0.0, 1.0, 0.0));
+    }
+
+    #[test]
+    fn ply_binary_both_endians() {
+        for (format, big_endian) in [("binary_little_endian", false), ("binary_big_endian", true)] {
+            let vertices = read_ply_vertices(&binary_ply(format, big_endian)).unwrap();
+            assert_eq!(vertices.rows, vec![vec![1.0, 2.0, 3.0, 255.0, 0.0, 51.0], vec![-1.0, 0.5, 0.0, 0.0, 255.0, 0.0]], "{}", format);
+        }
+    }
+
+    #[test]
+    fn ply_binary_truncated() {
+        let data = binary_ply("binary_little_endian", false);
+        assert_eq!(read_ply_vertices(&data[..data.len() - 2]).err().unwrap(), "PLY file ends early");
+    }
+
+    #[test]
+    fn ply_without_vertices() {
+        let data = format!("ply\nformat ascii 1.0\nelement face 0\nproperty list uchar int vertex_indices\n{}", HEADER_END);
+        assert_eq!(read_ply_vertices(data.as_bytes()).err().unwrap(), "PLY file without vertices");
+    }
+
+    #[test]
+    fn ply_errors_name_the_file() {
+        let data = format!("ply\nformat ascii 1.0\nelement vertex 1\nproperty float x\nproperty float y\n{}0 0\n", HEADER_END);
+        let file = TempFile::new("no_z.ply", data.as_bytes());
+        let err = PointCloud::new_from_ply(&file.0, 0.1, PointShape::Sphere, white()).err().unwrap();
+        assert_eq!(err, format!("point cloud {}: PLY vertices without z", file.0.display()));
+        let data = format!("ply\nformat binary_little_endian 1.0\nelement vertex 1\nproperty half x\n{}", HEADER_END);
+        let file = TempFile::new("half.ply", data.as_bytes());
+        let err = PointCloud::new_from_ply(&file.0, 0.1, PointShape::Sphere, white()).err().unwrap();
+        assert_eq!(err, format!("point cloud {}: unknown PLY type half", file.0.display()));
+    }
+}
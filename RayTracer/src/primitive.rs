@@ -0,0 +1,341 @@
+use crate::aabb::*;
+use crate::bvh::*;
+use crate::hitable::*;
+use crate::interval::*;
+use crate::material::*;
+use crate::quad::*;
+use crate::ray::*;
+use crate::sphere::*;
+use crate::utils::*;
+use crate::vec3::*;
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+// what an intersection test returns, the material is an index into the material table
+#[derive(Copy, Clone)]
+pub struct SurfaceHit {
+    pub t: f64,
+    pub point: Vec3,
+    pub normal: Vec3,
+    pub front_face: bool,
+    pub u: f64,
+    pub v: f64,
+    pub dpdu: Vec3,
+    pub dpdv: Vec3,
+    pub dndu: Vec3,
+    pub dndv: Vec3,
+    pub material: u32,
+}
+
+// statically dispatched primitives, anything else goes through Object
+pub enum Primitive {
+    Sphere {
+        center: Vec3,
+        // zero for a static sphere
+        center_vec: Vec3,
+        radius: f64,
+        material: u32,
+    },
+    Quad {
+        q: Vec3,
+        u: Vec3,
+        v: Vec3,
+        normal: Vec3,
+        d: f64,
+        w: Vec3,
+        material: u32,
+    },
+    Object(Arc<dyn Hittable + Send + Sync>),
+}
+
+impl Primitive {
+    // None for Object, which is hit through Hittable
+    pub fn intersect(&self, r: &Ray, ray_t: Interval) -> Option<SurfaceHit> {
+        match *self {
+            Primitive::Sphere {
+                center,
+                center_vec,
+                radius,
+                material,
+            } => {
+                let center = center + center_vec * r.time();
+                let oc = center - r.origin();
+                let a = r.direction().squared_length();
+                let h = r.direction() * oc;
+                let c = oc.squared_length() - radius * radius;
+                let delta = h * h - a * c;
+                if delta < 0.0 {
+                    return None;
+                }
+
+                let sqrt_delta = delta.sqrt();
+                let mut t = (h - sqrt_delta) / a;
+                if !ray_t.surrounds(t) {
+                    t = (h + sqrt_delta) / a;
+                    if !ray_t.surrounds(t) {
+                        return None;
+                    }
+                }
+
+                let point = r.at(t);
+                let outward_normal = (point - center) * (1.0 / radius);
+                let front_face = outward_normal * r.direction() < 0.0;
+                let normal = if front_face { outward_normal } else { outward_normal * -1.0 };
+                let (mut u, mut v) = (0.0, 0.0);
+                Sphere::get_sphere_uv(normal, &mut u, &mut v);
+                // inside, the uv are those of the opposite point, as in Sphere::hit
+                let (dpdu, dpdv) = Sphere::get_sphere_dpduv(normal * radius);
+                let side = if front_face { 1.0 } else { -1.0 };
+                Some(SurfaceHit {
+                    t,
+                    point,
+                    normal,
+                    front_face,
+                    u,
+                    v,
+                    dpdu: dpdu * side,
+                    dpdv: dpdv * side,
+                    dndu: dpdu / radius,
+                    dndv: dpdv / radius,
+                    material,
+                })
+            }
+            Primitive::Quad {
+                q,
+                u,
+                v,
+                normal,
+                d,
+                w,
+                material,
+            } => {
+                let denom = normal * r.direction();
+                if fabs(denom) < 1e-8 {
+                    return None;
+                }
+                let t = (d - normal * r.origin()) / denom;
+                if !ray_t.contains(t) {
+                    return None;
+                }
+                let point = r.at(t);
+                let planar = point - q;
+                let alpha = w * planar.cross(v);
+                let beta = w * u.cross(planar);
+                if !(0.0..=1.0).contains(&alpha) || !(0.0..=1.0).contains(&beta) {
+                    return None;
+                }
+
+                let front_face = r.direction() * normal < 0.0;
+                Some(SurfaceHit {
+                    t,
+                    point,
+                    normal: if front_face { normal } else { normal * -1.0 },
+                    front_face,
+                    u: alpha,
+                    v: beta,
+                    dpdu: u,
+                    dpdv: v,
+                    dndu: Vec3::zero(),
+                    dndv: Vec3::zero(),
+                    material,
+                })
+            }
+            Primitive::Object(_) => None,
+        }
+    }
+}
+
+// collects primitives and deduplicates their materials (by pointer), then builds a PrimitiveBVH
+pub struct PrimitiveList {
+    pub primitives: Vec<Primitive>,
+    pub bboxes: Vec<Aabb>,
+    pub materials: Vec<Arc<dyn MaterialTrait + Send + Sync>>,
+    material_index: HashMap<usize, u32>,
+}
+
+impl PrimitiveList {
+    pub fn new() -> PrimitiveList {
+        PrimitiveList {
+            primitives: Vec::new(),
+            bboxes: Vec::new(),
+            materials: Vec::new(),
+            material_index: HashMap::new(),
+        }
+    }
+
+    fn add_material(&mut self, material: Arc<dyn MaterialTrait + Send + Sync>) -> u32 {
+        let key = Arc::as_ptr(&material) as *const () as usize;
+        let materials = &mut self.materials;
+        *self.material_index.entry(key).or_insert_with(|| {
+            materials.push(material);
+            (materials.len() - 1) as u32
+        })
+    }
+
+    pub fn add_sphere(&mut self, center: Vec3, radius: f64, material: Arc<dyn MaterialTrait + Send + Sync>) {
+        self.add_moving_sphere(center, center, radius, material);
+    }
+
+    pub fn add_moving_sphere(&mut self, center1: Vec3, center2: Vec3, radius: f64, material: Arc<dyn MaterialTrait + Send + Sync>) {
+        let bbox = if center1 == center2 {
+            Sphere::new(center1, radius, material.clone()).bounding_box()
+        } else {
+            Sphere::new_moving(center1, center2, radius, material.clone()).bounding_box()
+        };
+        let material = self.add_material(material);
+        self.primitives.push(Primitive::Sphere {
+            center: center1,
+            center_vec: center2 - center1,
+            radius,
+            material,
+        });
+        self.bboxes.push(bbox);
+    }
+
+    // same parameters as Quad::new
+    pub fn add_quad(&mut self, q: Vec3, u: Vec3, v: Vec3, material: Arc<dyn MaterialTrait + Send + Sync>) {
+        let bbox = Quad::new(q, u, v, material.clone()).bounding_box();
+        let n = u.cross(v);
+        let normal = unit_vec(n);
+        let material = self.add_material(material);
+        self.primitives.push(Primitive::Quad {
+            q,
+            u,
+            v,
+            normal,
+            d: normal * q,
+            w: n / (n * n),
+            material,
+        });
+        self.bboxes.push(bbox);
+    }
+
+    // same box as create_box
+    pub fn add_box(&mut self, a: Vec3, b: Vec3, material: Arc<dyn MaterialTrait + Send + Sync>) {
+        let min = Vec3::new(a.x.min(b.x), a.y.min(b.y), a.z.min(b.z));
+        let max = Vec3::new(a.x.max(b.x), a.y.max(b.y), a.z.max(b.z));
+        let dx = Vec3::new(max.x - min.x, 0.0, 0.0);
+        let dy = Vec3::new(0.0, max.y - min.y, 0.0);
+        let dz = Vec3::new(0.0, 0.0, max.z - min.z);
+
+        self.add_quad(Vec3::new(min.x, min.y, max.z), dx, dy, material.clone());
+        self.add_quad(Vec3::new(max.x, min.y, max.z), dz * -1.0, dy, material.clone());
+        self.add_quad(Vec3::new(max.x, min.y, min.z), dx * -1.0, dy, material.clone());
+        self.add_quad(Vec3::new(min.x, min.y, min.z), dz, dy, material.clone());
+        self.add_quad(Vec3::new(min.x, max.y, max.z), dx, dz * -1.0, material.clone());
+        self.add_quad(Vec3::new(min.x, min.y, min.z), dx, dz, material);
+    }
+
+    // fallback for everything without a static path (media, transforms, meshes...)
+    pub fn add_object(&mut self, object: Arc<dyn Hittable + Send + Sync>) {
+        self.bboxes.push(object.bounding_box());
+        self.primitives.push(Primitive::Object(object));
+    }
+
+    // the same scene made of the dynamically dispatched Sphere / Quad objects
+    pub fn to_hittable_list(&self) -> Hittable_list {
+        let mut list = Hittable_list::default();
+        for primitive in &self.primitives {
+            match *primitive {
+                Primitive::Sphere {
+                    center,
+                    center_vec,
+                    radius,
+                    material,
+                } => {
+                    let material = self.materials[material as usize].clone();
+                    if center_vec == Vec3::zero() {
+                        list.add(Sphere::new(center, radius, material).instancing());
+                    } else {
+                        list.add(Sphere::new_moving(center, center + center_vec, radius, material).instancing());
+                    }
+                }
+                Primitive::Quad { q, u, v, material, .. } => {
+                    list.add(Quad::new(q, u, v, self.materials[material as usize].clone()).instancing());
+                }
+                Primitive::Object(ref object) => list.add(object.clone()),
+            }
+        }
+        list
+    }
+
+    pub fn build(self) -> PrimitiveBVH {
+        let (nodes, ordered, stats) = build_sah(&self.bboxes, DEFAULT_MAX_LEAF_SIZE);
+        let mut primitives: Vec<Option<Primitive>> = self.primitives.into_iter().map(Some).collect();
+        let primitives = ordered.iter().map(|&i| primitives[i].take().unwrap()).collect();
+        PrimitiveBVH {
+            nodes,
+            primitives,
+            materials: self.materials,
+            stats,
+        }
+    }
+}
+
+impl Default for PrimitiveList {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// flat BVH over an enum array: no virtual call, and the material of the closest hit is only
+// looked up once it is known
+pub struct PrimitiveBVH {
+    pub nodes: Vec<LinearBVHNode>,
+    pub primitives: Vec<Primitive>,
+    pub materials: Vec<Arc<dyn MaterialTrait + Send + Sync>>,
+    pub stats: BVHStats,
+}
+
+impl Hittable for PrimitiveBVH {
+    fn hit(&self, r: &Ray, ray_t: Interval) -> Option<HitRecord<'_>> {
+        // at most one of them is set, the one of the closest hit so far
+        let mut closest_surface: Option<SurfaceHit> = None;
+        let mut closest_record: Option<HitRecord> = None;
+        traverse_flat_bvh(&self.nodes, r, ray_t, |i, interval| match &self.primitives[i] {
+            Primitive::Object(object) => {
+                let rec = object.hit(r, interval)?;
+                closest_surface = None;
+                closest_record = Some(rec);
+                Some(rec.t)
+            }
+            primitive => {
+                let hit = primitive.intersect(r, interval)?;
+                closest_surface = Some(hit);
+                closest_record = None;
+                Some(hit.t)
+            }
+        });
+
+        let Some(hit) = closest_surface else {
+            return closest_record;
+        };
+        let mut rec = HitRecord::new(
+            hit.t,
+            hit.point,
+            hit.normal,
+            self.materials[hit.material as usize].as_ref(),
+            hit.front_face,
+            hit.u,
+            hit.v,
+        );
+        rec.dpdu = hit.dpdu;
+        rec.dpdv = hit.dpdv;
+        rec.dndu = hit.dndu;
+        rec.dndv = hit.dndv;
+        Some(rec)
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        if self.nodes.is_empty() {
+            Aabb::default()
+        } else {
+            self.nodes[0].bbox
+        }
+    }
+
+    fn instancing(self) -> Arc<dyn Hittable + Send + Sync> {
+        Arc::new(self)
+    }
+}
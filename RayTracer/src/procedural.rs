@@ -0,0 +1,256 @@
+use crate::color::*;
+use crate::perlin::*;
+use crate::texture::*;
+use crate::utils::*;
+use crate::vec3::*;
+
+use std::sync::Arc;
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum NoiseKind {
+    // soft clouds
+    Fbm,
+    // |fbm|, creases where it crosses zero
+    Turbulence,
+    // sharp crests, like mountain ridges
+    Ridged,
+}
+
+// fractal noise through a color ramp
+// usage: FbmTexture::new(4.0).kind(NoiseKind::Ridged).fractal(Fractal::new(6, 2.0, 0.5)).ramp(ramp)
+pub struct FbmTexture {
+    noise: Perlin,
+    scale: f64,
+    kind: NoiseKind,
+    fractal: Fractal,
+    // 4th noise coordinate, animations change it from frame to frame
+    time: Option<f64>,
+    ramp: ColorRamp,
+}
+
+impl FbmTexture {
+    pub fn new(scale: f64) -> Self {
+        Self {
+            noise: Perlin::new(),
+            scale,
+            kind: NoiseKind::Fbm,
+            fractal: Fractal::default(),
+            time: None,
+            ramp: ColorRamp::grayscale(),
+        }
+    }
+
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.noise = Perlin::new_with_seed(seed);
+        self
+    }
+
+    pub fn kind(mut self, kind: NoiseKind) -> Self {
+        self.kind = kind;
+        self
+    }
+
+    pub fn fractal(mut self, fractal: Fractal) -> Self {
+        self.fractal = fractal;
+        self
+    }
+
+    pub fn time(mut self, time: f64) -> Self {
+        self.time = Some(time);
+        self
+    }
+
+    pub fn ramp(mut self, ramp: ColorRamp) -> Self {
+        self.ramp = ramp;
+        self
+    }
+}
+
+impl TextureTrait for FbmTexture {
+    fn value(&self, _u: f64, _v: f64, p: Vec3) -> Vec3 {
+        let p = p * self.scale;
+        let t = match self.kind {
+            NoiseKind::Fbm => 0.5 + 0.5 * self.noise.fbm(p, self.time, self.fractal),
+            NoiseKind::Turbulence => fabs(self.noise.fbm(p, self.time, self.fractal)),
+            NoiseKind::Ridged => self.noise.ridged(p, self.time, self.fractal),
+        };
+        self.ramp.eval(t)
+    }
+
+    fn instancing(self) -> Arc<dyn TextureTrait + Send + Sync> {
+        Arc::new(self)
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum WorleyFeature {
+    // distance to the closest point: round cells, dark at their centers
+    F1,
+    // distance to the second closest point
+    F2,
+    // zero on the borders between cells: cracks, scales, cobblestones
+    F2MinusF1,
+}
+
+// cellular noise through a color ramp, scale is the number of cells per unit
+pub struct WorleyTexture {
+    noise: Perlin,
+    scale: f64,
+    feature: WorleyFeature,
+    ramp: ColorRamp,
+}
+
+impl WorleyTexture {
+    pub fn new(scale: f64) -> Self {
+        Self {
+            noise: Perlin::new(),
+            scale,
+            feature: WorleyFeature::F1,
+            ramp: ColorRamp::grayscale(),
+        }
+    }
+
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.noise = Perlin::new_with_seed(seed);
+        self
+    }
+
+    pub fn feature(mut self, feature: WorleyFeature) -> Self {
+        self.feature = feature;
+        self
+    }
+
+    pub fn ramp(mut self, ramp: ColorRamp) -> Self {
+        self.ramp = ramp;
+        self
+    }
+}
+
+impl TextureTrait for WorleyTexture {
+    fn value(&self, _u: f64, _v: f64, p: Vec3) -> Vec3 {
+        let (f1, f2) = self.noise.worley(p * self.scale);
+        let t = match self.feature {
+            WorleyFeature::F1 => f1,
+            WorleyFeature::F2 => f2,
+            WorleyFeature::F2MinusF1 => f2 - f1,
+        };
+        self.ramp.eval(t)
+    }
+
+    fn instancing(self) -> Arc<dyn TextureTrait + Send + Sync> {
+        Arc::new(self)
+    }
+}
+
+// growth rings around the line through center along axis, rings per unit of distance from it;
+// the ramp colors one ring, from 0 at its inner edge to 1 at its outer edge
+// usage: WoodTexture::new(Vec3::zero(), Vec3::new(0.0, 1.0, 0.0), 6.0).turbulence(0.4, 2.0)
+pub struct WoodTexture {
+    noise: Perlin,
+    center: Vec3,
+    axis: Vec3,
+    rings: f64,
+    // how far (in rings) the noise moves the rings, and its frequency
+    turbulence: f64,
+    noise_scale: f64,
+    fractal: Fractal,
+    ramp: ColorRamp,
+}
+
+impl WoodTexture {
+    pub fn new(center: Vec3, axis: Vec3, rings: f64) -> Self {
+        let light = Vec3::new(0.76, 0.55, 0.33);
+        let dark = Vec3::new(0.45, 0.27, 0.12);
+        Self {
+            noise: Perlin::new(),
+            center,
+            axis: unit_vec(axis),
+            rings,
+            turbulence: 0.3,
+            noise_scale: 1.5,
+            fractal: Fractal::new(4, 2.0, 0.5),
+            ramp: ColorRamp::new(vec![(0.0, light), (0.65, light * 0.9), (0.85, dark), (1.0, light)]),
+        }
+    }
+
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.noise = Perlin::new_with_seed(seed);
+        self
+    }
+
+    pub fn turbulence(mut self, turbulence: f64, noise_scale: f64) -> Self {
+        self.turbulence = turbulence;
+        self.noise_scale = noise_scale;
+        self
+    }
+
+    pub fn fractal(mut self, fractal: Fractal) -> Self {
+        self.fractal = fractal;
+        self
+    }
+
+    pub fn ramp(mut self, ramp: ColorRamp) -> Self {
+        self.ramp = ramp;
+        self
+    }
+}
+
+impl TextureTrait for WoodTexture {
+    fn value(&self, _u: f64, _v: f64, p: Vec3) -> Vec3 {
+        let d = p - self.center;
+        let radius = (d - self.axis * (d * self.axis)).length();
+        let ring = radius * self.rings + self.turbulence * self.noise.fbm(p * self.noise_scale, None, self.fractal);
+        self.ramp.eval(ring - ring.floor())
+    }
+
+    fn instancing(self) -> Arc<dyn TextureTrait + Send + Sync> {
+        Arc::new(self)
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+pub enum GradientShape {
+    // 0 on the plane through from, 1 on the parallel plane through to
+    Linear { from: Vec3, to: Vec3 },
+    // 0 at center, 1 at radius from it
+    Radial { center: Vec3, radius: f64 },
+}
+
+// a color ramp laid out in space
+pub struct GradientTexture {
+    shape: GradientShape,
+    ramp: ColorRamp,
+}
+
+impl GradientTexture {
+    pub fn new_linear(from: Vec3, to: Vec3, ramp: ColorRamp) -> Self {
+        Self {
+            shape: GradientShape::Linear { from, to },
+            ramp,
+        }
+    }
+
+    pub fn new_radial(center: Vec3, radius: f64, ramp: ColorRamp) -> Self {
+        Self {
+            shape: GradientShape::Radial { center, radius },
+            ramp,
+        }
+    }
+}
+
+impl TextureTrait for GradientTexture {
+    fn value(&self, _u: f64, _v: f64, p: Vec3) -> Vec3 {
+        let t = match self.shape {
+            GradientShape::Linear { from, to } => {
+                let d = to - from;
+                (p - from) * d / (d * d)
+            }
+            GradientShape::Radial { center, radius } => (p - center).length() / radius,
+        };
+        self.ramp.eval(t)
+    }
+
+    fn instancing(self) -> Arc<dyn TextureTrait + Send + Sync> {
+        Arc::new(self)
+    }
+}
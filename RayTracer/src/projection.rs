@@ -0,0 +1,268 @@
+use crate::camera::*;
+use crate::ray::*;
+
+use std::f64::consts::PI;
+use std::sync::Arc;
+
+// maps a film position to a primary ray
+// (s, t) in [0, 1]^2 is the position on the film, s grows to the right and t grows downward
+// None means the film position is not covered by the projection (e.g. outside the fisheye circle)
+pub trait ProjectionTrait {
+    fn generate_ray(&self, camera: &Camera, s: f64, t: f64, time: f64) -> Option<Ray>;
+    // offset rays of the ray generated for (s, t), one pixel to the right and one pixel down
+    fn generate_differential(&self, camera: &Camera, ray: &Ray, s: f64, t: f64) -> Option<RayDifferential> {
+        let rx = self.generate_ray(camera, s + 1.0 / camera.width as f64, t, ray.time())?;
+        let ry = self.generate_ray(camera, s, t + 1.0 / camera.height as f64, ray.time())?;
+        Some(RayDifferential {
+            rx_origin: rx.origin(),
+            rx_direction: rx.direction(),
+            ry_origin: ry.origin(),
+            ry_direction: ry.direction(),
+        })
+    }
+    fn instancing(self) -> Arc<dyn ProjectionTrait + Send + Sync>;
+}
+
+// the pinhole / thin lens model set up by Camera::new
+pub struct Perspective {}
+
+impl Perspective {
+    pub fn new() -> Self {
+        Self {}
+    }
+}
+
+impl Default for Perspective {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ProjectionTrait for Perspective {
+    fn generate_ray(&self, camera: &Camera, s: f64, t: f64, time: f64) -> Option<Ray> {
+        let pixel_center = camera.viewport_upper_left
+            + camera.pixel_delta_u * (s * camera.width as f64)
+            + camera.pixel_delta_v * (t * camera.height as f64);
+        let ray_origin = if camera.defocus_angle <= 0.0 {
+            camera.camera_center
+        } else {
+            camera.lens_sample(s, t)?
+        };
+        Some(Ray::new(ray_origin, pixel_center - ray_origin, time))
+    }
+
+    // the offset rays go through the same lens point, toward the neighbouring pixels on the focus plane
+    fn generate_differential(&self, camera: &Camera, ray: &Ray, s: f64, t: f64) -> Option<RayDifferential> {
+        let pixel_center = camera.viewport_upper_left
+            + camera.pixel_delta_u * (s * camera.width as f64)
+            + camera.pixel_delta_v * (t * camera.height as f64);
+        Some(RayDifferential {
+            rx_origin: ray.origin(),
+            rx_direction: pixel_center + camera.pixel_delta_u - ray.origin(),
+            ry_origin: ray.origin(),
+            ry_direction: pixel_center + camera.pixel_delta_v - ray.origin(),
+        })
+    }
+
+    fn instancing(self) -> Arc<dyn ProjectionTrait + Send + Sync> {
+        Arc::new(self)
+    }
+}
+
+// parallel rays along the view direction, view_height is the world space height of the film
+pub struct Orthographic {
+    pub view_height: f64,
+}
+
+impl Orthographic {
+    pub fn new(view_height: f64) -> Self {
+        Self { view_height }
+    }
+}
+
+impl ProjectionTrait for Orthographic {
+    fn generate_ray(&self, camera: &Camera, s: f64, t: f64, time: f64) -> Option<Ray> {
+        let view_width = self.view_height * camera.aspect_ratio;
+        let origin = camera.camera_center
+            + camera.u * ((s - 0.5) * view_width)
+            + camera.v * ((0.5 - t) * self.view_height);
+        Some(Ray::new(origin, camera.w * -1.0, time))
+    }
+
+    fn instancing(self) -> Arc<dyn ProjectionTrait + Send + Sync> {
+        Arc::new(self)
+    }
+}
+
+// equidistant fisheye: the angle to the view direction grows linearly with the distance to the
+// film center, fov (degrees) is the angle covered by the circle inscribed in the film
+pub struct Fisheye {
+    pub fov: f64,
+}
+
+impl Fisheye {
+    pub fn new(fov: f64) -> Self {
+        Self { fov }
+    }
+}
+
+impl ProjectionTrait for Fisheye {
+    fn generate_ray(&self, camera: &Camera, s: f64, t: f64, time: f64) -> Option<Ray> {
+        // normalize so that the shorter film side spans [-1, 1]
+        let scale = camera.width.min(camera.height) as f64;
+        let x = (s - 0.5) * 2.0 * camera.width as f64 / scale;
+        let y = (0.5 - t) * 2.0 * camera.height as f64 / scale;
+        let r = (x * x + y * y).sqrt();
+        if r > 1.0 {
+            return None;
+        }
+
+        let theta = r * self.fov.to_radians() / 2.0;
+        let phi = y.atan2(x);
+        let direction = camera.u * (theta.sin() * phi.cos())
+            + camera.v * (theta.sin() * phi.sin())
+            - camera.w * theta.cos();
+        Some(Ray::new(camera.camera_center, direction, time))
+    }
+
+    fn instancing(self) -> Arc<dyn ProjectionTrait + Send + Sync> {
+        Arc::new(self)
+    }
+}
+
+// full 360 x 180 degrees latitude-longitude panorama, the film center looks at look_at
+pub struct Equirectangular {}
+
+impl Equirectangular {
+    pub fn new() -> Self {
+        Self {}
+    }
+}
+
+impl Default for Equirectangular {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ProjectionTrait for Equirectangular {
+    fn generate_ray(&self, camera: &Camera, s: f64, t: f64, time: f64) -> Option<Ray> {
+        let phi = (s - 0.5) * 2.0 * PI;
+        let theta = (0.5 - t) * PI;
+        let direction = (camera.u * phi.sin() - camera.w * phi.cos()) * theta.cos() + camera.v * theta.sin();
+        Some(Ray::new(camera.camera_center, direction, time))
+    }
+
+    fn instancing(self) -> Arc<dyn ProjectionTrait + Send + Sync> {
+        Arc::new(self)
+    }
+}
+
+// cylindrical panorama: angular horizontally over hfov (degrees), perspective vertically over
+// the camera's vfov
+pub struct Cylindrical {
+    pub hfov: f64,
+}
+
+impl Cylindrical {
+    pub fn new(hfov: f64) -> Self {
+        Self { hfov }
+    }
+}
+
+impl ProjectionTrait for Cylindrical {
+    fn generate_ray(&self, camera: &Camera, s: f64, t: f64, time: f64) -> Option<Ray> {
+        let phi = (s - 0.5) * self.hfov.to_radians();
+        let h = (camera.vfov.to_radians() / 2.0).tan();
+        let direction = camera.u * phi.sin() - camera.w * phi.cos() + camera.v * ((0.5 - t) * 2.0 * h);
+        Some(Ray::new(camera.camera_center, direction, time))
+    }
+
+    fn instancing(self) -> Arc<dyn ProjectionTrait + Send + Sync> {
+        Arc::new(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::*;
+    use crate::vec3::*;
+
+    // 200 x 100 film at the origin looking down -z, y up
+    fn camera(projection: Arc<dyn ProjectionTrait + Send + Sync>) -> Camera {
+        let mut camera = Camera::new(
+            200,
+            100,
+            1,
+            1,
+            90.0,
+            Vec3::zero(),
+            Vec3::new(0.0, 0.0, -1.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            0.0,
+            1.0,
+            Vec3::zero(),
+        );
+        camera.set_projection(projection);
+        camera
+    }
+
+    fn ray(camera: &Camera, s: f64, t: f64) -> Option<Ray> {
+        camera.projection.generate_ray(camera, s, t, 0.0)
+    }
+
+    fn assert_direction(ray: Option<Ray>, expected: Vec3) {
+        let d = unit_vec(ray.expect("the film position should have a ray").direction());
+        assert!((d - unit_vec(expected)).length() < 1e-9, "{:?} instead of {:?}", d, unit_vec(expected));
+    }
+
+    #[test]
+    fn perspective_spans_the_vertical_fov() {
+        let camera = camera(Perspective::new().instancing());
+        assert_direction(ray(&camera, 0.5, 0.5), Vec3::new(0.0, 0.0, -1.0));
+        // 90 degrees vertically, twice as wide as high
+        assert_direction(ray(&camera, 0.5, 0.0), Vec3::new(0.0, 1.0, -1.0));
+        assert_direction(ray(&camera, 1.0, 1.0), Vec3::new(2.0, -1.0, -1.0));
+    }
+
+    #[test]
+    fn orthographic_rays_are_parallel() {
+        let camera = camera(Orthographic::new(4.0).instancing());
+        for (s, t) in [(0.5, 0.5), (0.0, 0.0), (1.0, 0.25)] {
+            assert_direction(ray(&camera, s, t), Vec3::new(0.0, 0.0, -1.0));
+        }
+        let corner = ray(&camera, 0.0, 0.0).unwrap().origin();
+        assert!((corner - Vec3::new(-4.0, 2.0, 0.0)).length() < 1e-9, "{:?}", corner);
+    }
+
+    #[test]
+    fn fisheye_angle_grows_with_the_radius() {
+        let camera = camera(Fisheye::new(180.0).instancing());
+        assert_direction(ray(&camera, 0.5, 0.5), Vec3::new(0.0, 0.0, -1.0));
+        // the inscribed circle touches the top of the film at 90 degrees
+        assert_direction(ray(&camera, 0.5, 0.0), Vec3::new(0.0, 1.0, 0.0));
+        // halfway to the right edge of the circle is 45 degrees
+        assert_direction(ray(&camera, 0.625, 0.5), Vec3::new(1.0, 0.0, -1.0));
+        assert!(ray(&camera, 0.0, 0.0).is_none());
+    }
+
+    #[test]
+    fn equirectangular_covers_the_sphere() {
+        let camera = camera(Equirectangular::new().instancing());
+        assert_direction(ray(&camera, 0.5, 0.5), Vec3::new(0.0, 0.0, -1.0));
+        assert_direction(ray(&camera, 0.75, 0.5), Vec3::new(1.0, 0.0, 0.0));
+        assert_direction(ray(&camera, 0.0, 0.5), Vec3::new(0.0, 0.0, 1.0));
+        assert_direction(ray(&camera, 0.5, 0.25), Vec3::new(0.0, 1.0, -1.0));
+    }
+
+    #[test]
+    fn cylindrical_is_angular_across_and_perspective_up() {
+        let camera = camera(Cylindrical::new(180.0).instancing());
+        assert_direction(ray(&camera, 0.5, 0.5), Vec3::new(0.0, 0.0, -1.0));
+        assert_direction(ray(&camera, 1.0, 0.5), Vec3::new(1.0, 0.0, 0.0));
+        // the top of the film is tan(45 degrees) above the horizon on every column
+        assert_direction(ray(&camera, 0.5, 0.0), Vec3::new(0.0, 1.0, -1.0));
+        assert_direction(ray(&camera, 1.0, 0.0), Vec3::new(1.0, 1.0, 0.0));
+    }
+}
@@ -5,6 +5,7 @@ use crate::aabb::*;
 use crate::hitable::*;
 use crate::interval::*;
 use crate::ray::*;
+use crate::planar::*;
 
 use std::sync::Arc;
 
@@ -13,6 +14,7 @@ pub struct Quad {
     u: Vec3,
     v: Vec3,
     material: Arc<dyn MaterialTrait + Send + Sync>,
+    shape: Arc<dyn PlanarShapeTrait + Send + Sync>,
     bbox: Aabb,
     normal: Vec3,
     D: f64,
@@ -21,18 +23,25 @@ pub struct Quad {
 
 impl Quad {
     pub fn new(Q: Vec3, u: Vec3, v: Vec3, material: Arc<dyn MaterialTrait + Send + Sync>) -> Self {
-        let bbox_diagonal1 = Aabb::new_from_point(Q, Q + u + v);
-        let bbox_diagonal2 = Aabb::new_from_point(Q + u, Q + v);
+        Self::new_with_shape(Q, u, v, Parallelogram.instancing(), material)
+    }
+
+    // any shape of the plane, the points are Q + alpha * u + beta * v for the (alpha, beta) the shape contains
+    pub fn new_with_shape(q: Vec3, u: Vec3, v: Vec3, shape: Arc<dyn PlanarShapeTrait + Send + Sync>, material: Arc<dyn MaterialTrait + Send + Sync>) -> Self {
+        let (a_min, a_max, b_min, b_max) = shape.bounds();
+        let bbox_diagonal1 = Aabb::new_from_point(q + u * a_min + v * b_min, q + u * a_max + v * b_max);
+        let bbox_diagonal2 = Aabb::new_from_point(q + u * a_max + v * b_min, q + u * a_min + v * b_max);
         let bbox = Aabb::new_from_bbox(bbox_diagonal1, bbox_diagonal2);
         let n = u.cross(v);
         let normal = unit_vec(n);
-        let D = normal * Q;
+        let D = normal * q;
         let w = n / (n * n);
         Self {
-            Q,
+            Q: q,
             u,
             v,
             material,
+            shape,
             bbox,
             normal,
             D,
@@ -40,17 +49,29 @@ impl Quad {
         }
     }
 
-    pub fn is_interier(&self, a: f64, b: f64, rec: &mut HitRecord) -> bool {
-        let unit_interval = Interval::new(0.0, 1.0);
-    
-        if !unit_interval.contains(a) || !unit_interval.contains(b) {
-          false
-        } 
-        else {
-          rec.u = a;
-          rec.v = b;
-          true
-        }
+    // triangle q, q + u, q + v
+    pub fn new_triangle(q: Vec3, u: Vec3, v: Vec3, material: Arc<dyn MaterialTrait + Send + Sync>) -> Self {
+        Self::new_with_shape(q, u, v, Triangle2D.instancing(), material)
+    }
+
+    // ellipse of center Q and semi-axes u and v
+    pub fn new_ellipse(center: Vec3, u: Vec3, v: Vec3, material: Arc<dyn MaterialTrait + Send + Sync>) -> Self {
+        Self::new_with_shape(center, u, v, Ellipse.instancing(), material)
+    }
+
+    // elliptic ring, inner is the hole size relative to u and v
+    pub fn new_annulus(center: Vec3, u: Vec3, v: Vec3, inner: f64, material: Arc<dyn MaterialTrait + Send + Sync>) -> Self {
+        Self::new_with_shape(center, u, v, Annulus2D::new(inner).instancing(), material)
+    }
+
+    // polygon with holes, the vertices are (alpha, beta) coordinates along u and v
+    pub fn new_polygon(q: Vec3, u: Vec3, v: Vec3, outline: Vec<(f64, f64)>, holes: Vec<Vec<(f64, f64)>>, material: Arc<dyn MaterialTrait + Send + Sync>) -> Self {
+        Self::new_with_shape(q, u, v, Polygon2D::new(outline, holes).instancing(), material)
+    }
+
+    // the uv of the hit when (a, b) is inside the shape
+    pub fn is_interier(&self, a: f64, b: f64) -> Option<(f64, f64)> {
+        self.shape.contains(a, b)
     }
 }
 
@@ -58,20 +79,21 @@ impl Clone for Quad {
     fn clone(&self) -> Self {
       Self {
         material: self.material.clone(),
+        shape: self.shape.clone(),
         ..*self
       }
     }
 }
 
 impl Hittable for Quad {
-    fn hit(&self, ray: &Ray, ray_t: Interval, rec: &mut HitRecord) -> bool {
+    fn hit(&self, ray: &Ray, ray_t: Interval) -> Option<HitRecord<'_>> {
        let denom = self.normal * ray.direction();
        if fabs(denom) < 1e-8 {
-        return false;
+        return None;
        }
        let t = (self.D - self.normal * ray.origin()) / denom;
        if !ray_t.contains(t) {
-        return false;
+        return None;
        }
        let intersection = ray.at(t);
        let planar_hitpt_vector = intersection - self.Q;
@@ -79,22 +101,23 @@ impl Hittable for Quad {
        let beta = self.w * self.u.cross(planar_hitpt_vector);
 
         
-       if !self.is_interier(alpha, beta, rec) {
-            return false;
-        }
+       let (u, v) = self.is_interier(alpha, beta)?;
 
-        *rec = HitRecord::new(
+        let mut rec = HitRecord::new(
             t,
             intersection,
             self.normal,
-            self.material.clone(),
+            self.material.as_ref(),
             true,
-            rec.u, 
-            rec.v
+            u,
+            v
         );
         rec.set_face_normal(*ray, self.normal);
+        let ((da_du, db_du), (da_dv, db_dv)) = self.shape.uv_derivatives(alpha, beta);
+        rec.dpdu = self.u * da_du + self.v * db_du;
+        rec.dpdv = self.u * da_dv + self.v * db_dv;
 
-        true
+        Some(rec)
     }
 
     fn bounding_box(&self) -> Aabb {
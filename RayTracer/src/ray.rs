@@ -1,10 +1,22 @@
 pub use crate::vec3::Vec3;
 
+// rays through the film one pixel to the right (x) and one pixel down (y) of a camera ray,
+// they tell how large a pixel is where the ray hits and so how much a texture has to be filtered
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct RayDifferential {
+    pub rx_origin: Vec3,
+    pub rx_direction: Vec3,
+    pub ry_origin: Vec3,
+    pub ry_direction: Vec3,
+}
+
 #[derive(Copy, Clone, Debug, PartialEq)]
 pub struct Ray {
     pub a_origin: Vec3,
     pub b_direction: Vec3,
     pub time: f64,
+    // only camera rays and their specular bounces carry one
+    pub differential: Option<RayDifferential>,
 }
 
 impl Ray {
@@ -13,6 +25,16 @@ impl Ray {
             a_origin,
             b_direction,
             time,
+            differential: None,
+        }
+    }
+
+    pub fn new_with_differential(a_origin: Vec3, b_direction: Vec3, time: f64, differential: RayDifferential) -> Self {
+        Self {
+            a_origin,
+            b_direction,
+            time,
+            differential: Some(differential),
         }
     }
 
@@ -21,6 +43,7 @@ impl Ray {
             a_origin: Vec3::zero(),
             b_direction: Vec3::zero(),
             time: 0.0,
+            differential: None,
         }
     }
 
@@ -39,6 +62,16 @@ impl Ray {
     pub fn at(&self, t: f64) -> Vec3 {
         self.a_origin + self.b_direction * t
     }
+
+    // moves the offset rays toward the ray, scale 0.5 makes them half a pixel away
+    pub fn scale_differential(&mut self, scale: f64) {
+        if let Some(d) = self.differential.as_mut() {
+            d.rx_origin = self.a_origin + (d.rx_origin - self.a_origin) * scale;
+            d.ry_origin = self.a_origin + (d.ry_origin - self.a_origin) * scale;
+            d.rx_direction = self.b_direction + (d.rx_direction - self.b_direction) * scale;
+            d.ry_direction = self.b_direction + (d.ry_direction - self.b_direction) * scale;
+        }
+    }
     pub fn info(&self){
         println!("ori");
         self.a_origin.info();
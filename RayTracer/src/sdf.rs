@@ -0,0 +1,444 @@
+use crate::aabb::*;
+use crate::hitable::*;
+use crate::interval::*;
+use crate::material::*;
+use crate::ray::*;
+use crate::sphere::*;
+use crate::utils::*;
+use crate::vec3::*;
+
+use std::sync::Arc;
+
+// signed distance to a surface, negative inside
+// non exact fields (twist, displacement, fractals) may overestimate, SdfObject::step_scale makes up for it
+pub trait SdfTrait {
+    fn distance(&self, p: Vec3) -> f64;
+    fn instancing(self) -> Arc<dyn SdfTrait + Send + Sync>;
+}
+
+fn vec_abs(p: Vec3) -> Vec3 {
+    Vec3::new(fabs(p.x), fabs(p.y), fabs(p.z))
+}
+
+fn vec_max(p: Vec3, m: f64) -> Vec3 {
+    Vec3::new(p.x.max(m), p.y.max(m), p.z.max(m))
+}
+
+pub struct SdfSphere {
+    pub center: Vec3,
+    pub radius: f64,
+}
+
+impl SdfSphere {
+    pub fn new(center: Vec3, radius: f64) -> SdfSphere {
+        SdfSphere { center, radius }
+    }
+}
+
+impl SdfTrait for SdfSphere {
+    fn distance(&self, p: Vec3) -> f64 {
+        (p - self.center).length() - self.radius
+    }
+
+    fn instancing(self) -> Arc<dyn SdfTrait + Send + Sync> {
+        Arc::new(self)
+    }
+}
+
+// axis aligned box, the rounding radius is taken from the half size
+pub struct SdfBox {
+    pub center: Vec3,
+    pub half_size: Vec3,
+    pub rounding: f64,
+}
+
+impl SdfBox {
+    pub fn new(center: Vec3, half_size: Vec3) -> SdfBox {
+        Self::new_rounded(center, half_size, 0.0)
+    }
+
+    pub fn new_rounded(center: Vec3, half_size: Vec3, rounding: f64) -> SdfBox {
+        SdfBox {
+            center,
+            half_size,
+            rounding,
+        }
+    }
+}
+
+impl SdfTrait for SdfBox {
+    fn distance(&self, p: Vec3) -> f64 {
+        let q = vec_abs(p - self.center) - self.half_size + self.rounding;
+        let outside = vec_max(q, 0.0).length();
+        let inside = q.x.max(q.y).max(q.z).min(0.0);
+        outside + inside - self.rounding
+    }
+
+    fn instancing(self) -> Arc<dyn SdfTrait + Send + Sync> {
+        Arc::new(self)
+    }
+}
+
+// torus around the y axis
+pub struct SdfTorus {
+    pub center: Vec3,
+    pub major_radius: f64,
+    pub minor_radius: f64,
+}
+
+impl SdfTorus {
+    pub fn new(center: Vec3, major_radius: f64, minor_radius: f64) -> SdfTorus {
+        SdfTorus {
+            center,
+            major_radius,
+            minor_radius,
+        }
+    }
+}
+
+impl SdfTrait for SdfTorus {
+    fn distance(&self, p: Vec3) -> f64 {
+        let p = p - self.center;
+        let ring = (p.x * p.x + p.z * p.z).sqrt() - self.major_radius;
+        (ring * ring + p.y * p.y).sqrt() - self.minor_radius
+    }
+
+    fn instancing(self) -> Arc<dyn SdfTrait + Send + Sync> {
+        Arc::new(self)
+    }
+}
+
+// segment from a to b grown by radius
+pub struct SdfCapsule {
+    pub a: Vec3,
+    pub b: Vec3,
+    pub radius: f64,
+}
+
+impl SdfCapsule {
+    pub fn new(a: Vec3, b: Vec3, radius: f64) -> SdfCapsule {
+        SdfCapsule { a, b, radius }
+    }
+}
+
+impl SdfTrait for SdfCapsule {
+    fn distance(&self, p: Vec3) -> f64 {
+        let pa = p - self.a;
+        let ba = self.b - self.a;
+        let h = ((pa * ba) / (ba * ba)).clamp(0.0, 1.0);
+        (pa - ba * h).length() - self.radius
+    }
+
+    fn instancing(self) -> Arc<dyn SdfTrait + Send + Sync> {
+        Arc::new(self)
+    }
+}
+
+// with a smoothness above zero the two shapes are blended over about that distance
+pub struct SdfUnion {
+    pub a: Arc<dyn SdfTrait + Send + Sync>,
+    pub b: Arc<dyn SdfTrait + Send + Sync>,
+    pub smoothness: f64,
+}
+
+impl SdfUnion {
+    pub fn new(a: Arc<dyn SdfTrait + Send + Sync>, b: Arc<dyn SdfTrait + Send + Sync>) -> SdfUnion {
+        Self::new_smooth(a, b, 0.0)
+    }
+
+    pub fn new_smooth(a: Arc<dyn SdfTrait + Send + Sync>, b: Arc<dyn SdfTrait + Send + Sync>, smoothness: f64) -> SdfUnion {
+        SdfUnion { a, b, smoothness }
+    }
+}
+
+impl SdfTrait for SdfUnion {
+    fn distance(&self, p: Vec3) -> f64 {
+        let (d1, d2) = (self.a.distance(p), self.b.distance(p));
+        if self.smoothness <= 0.0 {
+            return d1.min(d2);
+        }
+        // polynomial smooth minimum
+        let h = (0.5 + 0.5 * (d2 - d1) / self.smoothness).clamp(0.0, 1.0);
+        d2 + (d1 - d2) * h - self.smoothness * h * (1.0 - h)
+    }
+
+    fn instancing(self) -> Arc<dyn SdfTrait + Send + Sync> {
+        Arc::new(self)
+    }
+}
+
+pub struct SdfIntersection {
+    pub a: Arc<dyn SdfTrait + Send + Sync>,
+    pub b: Arc<dyn SdfTrait + Send + Sync>,
+}
+
+impl SdfIntersection {
+    pub fn new(a: Arc<dyn SdfTrait + Send + Sync>, b: Arc<dyn SdfTrait + Send + Sync>) -> SdfIntersection {
+        SdfIntersection { a, b }
+    }
+}
+
+impl SdfTrait for SdfIntersection {
+    fn distance(&self, p: Vec3) -> f64 {
+        self.a.distance(p).max(self.b.distance(p))
+    }
+
+    fn instancing(self) -> Arc<dyn SdfTrait + Send + Sync> {
+        Arc::new(self)
+    }
+}
+
+// a minus b
+pub struct SdfDifference {
+    pub a: Arc<dyn SdfTrait + Send + Sync>,
+    pub b: Arc<dyn SdfTrait + Send + Sync>,
+}
+
+impl SdfDifference {
+    pub fn new(a: Arc<dyn SdfTrait + Send + Sync>, b: Arc<dyn SdfTrait + Send + Sync>) -> SdfDifference {
+        SdfDifference { a, b }
+    }
+}
+
+impl SdfTrait for SdfDifference {
+    fn distance(&self, p: Vec3) -> f64 {
+        self.a.distance(p).max(-self.b.distance(p))
+    }
+
+    fn instancing(self) -> Arc<dyn SdfTrait + Send + Sync> {
+        Arc::new(self)
+    }
+}
+
+pub struct SdfTranslate {
+    pub child: Arc<dyn SdfTrait + Send + Sync>,
+    pub offset: Vec3,
+}
+
+impl SdfTranslate {
+    pub fn new(child: Arc<dyn SdfTrait + Send + Sync>, offset: Vec3) -> SdfTranslate {
+        SdfTranslate { child, offset }
+    }
+}
+
+impl SdfTrait for SdfTranslate {
+    fn distance(&self, p: Vec3) -> f64 {
+        self.child.distance(p - self.offset)
+    }
+
+    fn instancing(self) -> Arc<dyn SdfTrait + Send + Sync> {
+        Arc::new(self)
+    }
+}
+
+// uniform scale around the origin
+pub struct SdfScale {
+    pub child: Arc<dyn SdfTrait + Send + Sync>,
+    pub scale: f64,
+}
+
+impl SdfScale {
+    pub fn new(child: Arc<dyn SdfTrait + Send + Sync>, scale: f64) -> SdfScale {
+        SdfScale { child, scale }
+    }
+}
+
+impl SdfTrait for SdfScale {
+    fn distance(&self, p: Vec3) -> f64 {
+        self.child.distance(p / self.scale) * self.scale
+    }
+
+    fn instancing(self) -> Arc<dyn SdfTrait + Send + Sync> {
+        Arc::new(self)
+    }
+}
+
+// rotates every horizontal slice by rate radians per unit of height, not exact
+pub struct SdfTwist {
+    pub child: Arc<dyn SdfTrait + Send + Sync>,
+    pub rate: f64,
+}
+
+impl SdfTwist {
+    pub fn new(child: Arc<dyn SdfTrait + Send + Sync>, rate: f64) -> SdfTwist {
+        SdfTwist { child, rate }
+    }
+}
+
+impl SdfTrait for SdfTwist {
+    fn distance(&self, p: Vec3) -> f64 {
+        let (sin, cos) = (self.rate * p.y).sin_cos();
+        self.child.distance(Vec3::new(cos * p.x - sin * p.z, p.y, sin * p.x + cos * p.z))
+    }
+
+    fn instancing(self) -> Arc<dyn SdfTrait + Send + Sync> {
+        Arc::new(self)
+    }
+}
+
+// sinusoidal bumps added to the surface, not exact
+pub struct SdfDisplace {
+    pub child: Arc<dyn SdfTrait + Send + Sync>,
+    pub amplitude: f64,
+    pub frequency: f64,
+}
+
+impl SdfDisplace {
+    pub fn new(child: Arc<dyn SdfTrait + Send + Sync>, amplitude: f64, frequency: f64) -> SdfDisplace {
+        SdfDisplace {
+            child,
+            amplitude,
+            frequency,
+        }
+    }
+}
+
+impl SdfTrait for SdfDisplace {
+    fn distance(&self, p: Vec3) -> f64 {
+        let q = p * self.frequency;
+        self.child.distance(p) + self.amplitude * q.x.sin() * q.y.sin() * q.z.sin()
+    }
+
+    fn instancing(self) -> Arc<dyn SdfTrait + Send + Sync> {
+        Arc::new(self)
+    }
+}
+
+// distance estimate of the mandelbulb fractal, it fits in a sphere of radius 1.2 around the origin
+pub struct SdfMandelbulb {
+    pub power: f64,
+    pub iterations: usize,
+}
+
+impl SdfMandelbulb {
+    pub fn new(power: f64, iterations: usize) -> SdfMandelbulb {
+        SdfMandelbulb { power, iterations }
+    }
+}
+
+impl SdfTrait for SdfMandelbulb {
+    fn distance(&self, p: Vec3) -> f64 {
+        let mut z = p;
+        let mut dr = 1.0;
+        let mut r = z.length();
+        for _ in 0..self.iterations {
+            if r > 2.0 {
+                break;
+            }
+            let theta = (z.z / r).acos() * self.power;
+            let phi = z.y.atan2(z.x) * self.power;
+            dr = r.powf(self.power - 1.0) * self.power * dr + 1.0;
+            let zr = r.powf(self.power);
+            z = Vec3::new(theta.sin() * phi.cos(), theta.sin() * phi.sin(), theta.cos()) * zr + p;
+            r = z.length();
+        }
+        0.5 * r.ln() * r / dr
+    }
+
+    fn instancing(self) -> Arc<dyn SdfTrait + Send + Sync> {
+        Arc::new(self)
+    }
+}
+
+// sphere traces the field inside bbox, which has to contain the whole surface
+// u, v are the spherical coordinates of the normal
+pub struct SdfObject {
+    pub sdf: Arc<dyn SdfTrait + Send + Sync>,
+    pub material: Arc<dyn MaterialTrait + Send + Sync>,
+    // a surface is reached closer than epsilon (in world units)
+    pub epsilon: f64,
+    pub max_steps: usize,
+    // below 1 for fields that overestimate the distance
+    pub step_scale: f64,
+    bbox: Aabb,
+}
+
+impl SdfObject {
+    pub fn new(sdf: Arc<dyn SdfTrait + Send + Sync>, bbox: Aabb, material: Arc<dyn MaterialTrait + Send + Sync>) -> SdfObject {
+        SdfObject {
+            sdf,
+            material,
+            epsilon: 1e-4,
+            max_steps: 256,
+            step_scale: 1.0,
+            bbox,
+        }
+    }
+
+    pub fn new_with_step_scale(sdf: Arc<dyn SdfTrait + Send + Sync>, bbox: Aabb, step_scale: f64, material: Arc<dyn MaterialTrait + Send + Sync>) -> SdfObject {
+        SdfObject {
+            step_scale,
+            ..Self::new(sdf, bbox, material)
+        }
+    }
+
+    // central differences of the field
+    pub fn gradient(&self, p: Vec3) -> Vec3 {
+        let h = self.epsilon;
+        let dx = Vec3::new(h, 0.0, 0.0);
+        let dy = Vec3::new(0.0, h, 0.0);
+        let dz = Vec3::new(0.0, 0.0, h);
+        Vec3::new(
+            self.sdf.distance(p + dx) - self.sdf.distance(p - dx),
+            self.sdf.distance(p + dy) - self.sdf.distance(p - dy),
+            self.sdf.distance(p + dz) - self.sdf.distance(p - dz),
+        )
+    }
+}
+
+impl Hittable for SdfObject {
+    fn hit(&self, r: &Ray, ray_t: Interval) -> Option<HitRecord<'_>> {
+        let clipped = self.bbox.clip(r, ray_t)?;
+        let direction_length = r.direction().length();
+
+        // rays leaving a surface start right on it, so a hit needs either a change of side
+        // or a distance that is small and still shrinking
+        let mut t = clipped.tmin;
+        let mut d = self.sdf.distance(r.at(t));
+        let outside = d >= 0.0;
+        let mut found = None;
+        for _ in 0..self.max_steps {
+            let t_next = t + (fabs(d) * self.step_scale).max(self.epsilon) / direction_length;
+            if t_next > clipped.tmax {
+                break;
+            }
+            let d_next = self.sdf.distance(r.at(t_next));
+            if (d_next >= 0.0) != outside {
+                // crossed the surface, bisect back to it
+                let (mut t0, mut t1) = (t, t_next);
+                for _ in 0..16 {
+                    let mid = 0.5 * (t0 + t1);
+                    if (self.sdf.distance(r.at(mid)) >= 0.0) == outside {
+                        t0 = mid;
+                    } else {
+                        t1 = mid;
+                    }
+                }
+                found = Some(t1);
+                break;
+            }
+            if fabs(d_next) < self.epsilon && fabs(d_next) < fabs(d) {
+                found = Some(t_next);
+                break;
+            }
+            t = t_next;
+            d = d_next;
+        }
+
+        let t = found?;
+        let point = r.at(t);
+        let outward_normal = unit_vec(self.gradient(point));
+        let mut rec = HitRecord::new(t, point, outward_normal, self.material.as_ref(), true, 0.0, 0.0);
+        rec.set_face_normal(*r, outward_normal);
+        Sphere::get_sphere_uv(outward_normal, &mut rec.u, &mut rec.v);
+        Some(rec)
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.bbox
+    }
+
+    fn instancing(self) -> Arc<dyn Hittable + Send + Sync> {
+        Arc::new(self)
+    }
+}
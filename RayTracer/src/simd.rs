@@ -0,0 +1,392 @@
+use crate::aabb::*;
+use crate::bvh::*;
+use crate::hitable::*;
+use crate::interval::*;
+use crate::ray::*;
+use crate::vec3::*;
+
+use std::ops::{Add, Mul, Sub};
+use std::sync::Arc;
+
+// f32 counterparts of Vec3 / Ray / Aabb, only used to cull boxes
+// the primitives are still intersected in f64, so the image does not change
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Vec3f {
+    pub x: f32,
+    pub y: f32,
+    pub z: f32,
+}
+
+impl Vec3f {
+    pub fn new(x: f32, y: f32, z: f32) -> Self {
+        Self { x, y, z }
+    }
+
+    pub fn lp(&self, i: usize) -> f32 {
+        match i {
+            0 => self.x,
+            1 => self.y,
+            2 => self.z,
+            _ => panic!("invalid indexing"),
+        }
+    }
+
+    pub fn cross(&self, other: Vec3f) -> Self {
+        Self::new(
+            self.y * other.z - self.z * other.y,
+            self.z * other.x - self.x * other.z,
+            self.x * other.y - self.y * other.x,
+        )
+    }
+}
+
+impl From<Vec3> for Vec3f {
+    fn from(v: Vec3) -> Self {
+        Self::new(v.x as f32, v.y as f32, v.z as f32)
+    }
+}
+
+impl Add for Vec3f {
+    type Output = Self;
+
+    fn add(self, other: Self) -> Self {
+        Self::new(self.x + other.x, self.y + other.y, self.z + other.z)
+    }
+}
+
+impl Sub for Vec3f {
+    type Output = Self;
+
+    fn sub(self, other: Self) -> Self {
+        Self::new(self.x - other.x, self.y - other.y, self.z - other.z)
+    }
+}
+
+// dot product, like Vec3
+impl Mul for Vec3f {
+    type Output = f32;
+
+    fn mul(self, other: Self) -> f32 {
+        self.x * other.x + self.y * other.y + self.z * other.z
+    }
+}
+
+impl Mul<f32> for Vec3f {
+    type Output = Self;
+
+    fn mul(self, other: f32) -> Self {
+        Self::new(self.x * other, self.y * other, self.z * other)
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct Rayf {
+    pub origin: Vec3f,
+    pub direction: Vec3f,
+    pub inv_direction: Vec3f,
+    pub time: f32,
+}
+
+impl From<&Ray> for Rayf {
+    fn from(r: &Ray) -> Self {
+        let direction = Vec3f::from(r.direction());
+        Self {
+            origin: Vec3f::from(r.origin()),
+            direction,
+            inv_direction: Vec3f::new(1.0 / direction.x, 1.0 / direction.y, 1.0 / direction.z),
+            time: r.time() as f32,
+        }
+    }
+}
+
+// relative padding applied when rounding f64 bounds to f32, so that a box never shrinks
+const F32_PADDING: f32 = 4.0 * f32::EPSILON;
+
+fn round_down(x: f64) -> f32 {
+    let y = x as f32;
+    y - y.abs() * F32_PADDING - f32::MIN_POSITIVE
+}
+
+fn round_up(x: f64) -> f32 {
+    let y = x as f32;
+    y + y.abs() * F32_PADDING + f32::MIN_POSITIVE
+}
+
+// the slab distances are inflated by this factor, it covers the f32 rounding of the ray
+const SLAB_PADDING: f32 = 1.0 + 8.0 * f32::EPSILON;
+
+#[derive(Copy, Clone, Debug)]
+pub struct Aabbf {
+    pub min: Vec3f,
+    pub max: Vec3f,
+}
+
+impl From<&Aabb> for Aabbf {
+    fn from(bbox: &Aabb) -> Self {
+        Self {
+            min: Vec3f::new(round_down(bbox.x.tmin), round_down(bbox.y.tmin), round_down(bbox.z.tmin)),
+            max: Vec3f::new(round_up(bbox.x.tmax), round_up(bbox.y.tmax), round_up(bbox.z.tmax)),
+        }
+    }
+}
+
+impl Aabbf {
+    // entry distance if the ray overlaps the box inside [tmin, tmax]
+    pub fn hit(&self, r: &Rayf, tmin: f32, tmax: f32) -> Option<f32> {
+        let mut t_near = tmin;
+        let mut t_far = tmax;
+        for i in 0..3 {
+            let t0 = (self.min.lp(i) - r.origin.lp(i)) * r.inv_direction.lp(i);
+            let t1 = (self.max.lp(i) - r.origin.lp(i)) * r.inv_direction.lp(i);
+            // min / max drop the NaN of 0 * inf, the slab then does not clip
+            t_near = t_near.max(t0.min(t1));
+            t_far = t_far.min(t0.max(t1) * SLAB_PADDING);
+        }
+        if t_near <= t_far {
+            Some(t_near)
+        } else {
+            None
+        }
+    }
+}
+
+// four boxes in structure of arrays layout, tested together lane by lane
+#[derive(Copy, Clone, Debug)]
+pub struct Aabb4 {
+    pub min: [[f32; 4]; 3],
+    pub max: [[f32; 4]; 3],
+}
+
+impl Aabb4 {
+    // unused lanes are left inverted, their children are BVH4Child::Empty
+    pub fn new(boxes: &[Aabbf]) -> Self {
+        let mut min = [[f32::INFINITY; 4]; 3];
+        let mut max = [[f32::NEG_INFINITY; 4]; 3];
+        for (lane, bbox) in boxes.iter().enumerate().take(4) {
+            for axis in 0..3 {
+                min[axis][lane] = bbox.min.lp(axis);
+                max[axis][lane] = bbox.max.lp(axis);
+            }
+        }
+        Self { min, max }
+    }
+
+    // entry distance of each lane, infinity for the lanes that are missed
+    // gives the same result as Aabbf::hit on each lane, NaNs included
+    pub fn hit(&self, r: &Rayf, tmin: f32, tmax: f32) -> [f32; 4] {
+        #[cfg(target_arch = "x86_64")]
+        return self.hit_sse(r, tmin, tmax);
+        #[cfg(not(target_arch = "x86_64"))]
+        return self.hit_lanes(r, tmin, tmax);
+    }
+
+    // SSE2 is part of every x86_64 cpu, the four lanes of an axis are one register
+    #[cfg(target_arch = "x86_64")]
+    fn hit_sse(&self, r: &Rayf, tmin: f32, tmax: f32) -> [f32; 4] {
+        use std::arch::x86_64::*;
+
+        let origin = [r.origin.x, r.origin.y, r.origin.z];
+        let inv_direction = [r.inv_direction.x, r.inv_direction.y, r.inv_direction.z];
+        let mut entry = [0.0f32; 4];
+        // SAFETY: the loads and the store read and write whole [f32; 4] arrays
+        unsafe {
+            let padding = _mm_set1_ps(SLAB_PADDING);
+            let mut t_near = _mm_set1_ps(tmin);
+            let mut t_far = _mm_set1_ps(tmax);
+            for axis in 0..3 {
+                let o = _mm_set1_ps(origin[axis]);
+                let inv = _mm_set1_ps(inv_direction[axis]);
+                let t0 = _mm_mul_ps(_mm_sub_ps(_mm_loadu_ps(self.min[axis].as_ptr()), o), inv);
+                let t1 = _mm_mul_ps(_mm_sub_ps(_mm_loadu_ps(self.max[axis].as_ptr()), o), inv);
+                // _mm_min_ps / _mm_max_ps return their second operand when one is NaN, f32::min / max
+                // return the other one: t0 is put back where t1 is NaN
+                let t1_nan = _mm_cmpunord_ps(t1, t1);
+                let near = _mm_or_ps(_mm_and_ps(t1_nan, t0), _mm_andnot_ps(t1_nan, _mm_min_ps(t0, t1)));
+                let far = _mm_or_ps(_mm_and_ps(t1_nan, t0), _mm_andnot_ps(t1_nan, _mm_max_ps(t0, t1)));
+                // the accumulators are never NaN, a NaN slab keeps them as they are
+                t_near = _mm_max_ps(near, t_near);
+                t_far = _mm_min_ps(_mm_mul_ps(far, padding), t_far);
+            }
+            let hit = _mm_cmple_ps(t_near, t_far);
+            let missed = _mm_andnot_ps(hit, _mm_set1_ps(f32::INFINITY));
+            _mm_storeu_ps(entry.as_mut_ptr(), _mm_or_ps(_mm_and_ps(hit, t_near), missed));
+        }
+        entry
+    }
+
+    #[cfg_attr(target_arch = "x86_64", allow(dead_code))]
+    fn hit_lanes(&self, r: &Rayf, tmin: f32, tmax: f32) -> [f32; 4] {
+        std::array::from_fn(|lane| {
+            let bbox = Aabbf {
+                min: Vec3f::new(self.min[0][lane], self.min[1][lane], self.min[2][lane]),
+                max: Vec3f::new(self.max[0][lane], self.max[1][lane], self.max[2][lane]),
+            };
+            bbox.hit(r, tmin, tmax).unwrap_or(f32::INFINITY)
+        })
+    }
+}
+
+// child reference of a BVH4 node
+#[derive(Copy, Clone, Debug)]
+pub enum BVH4Child {
+    Empty,
+    Node(usize),
+    // primitives[offset..offset + count]
+    Leaf(usize, usize),
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct BVH4Node {
+    pub bounds: Aabb4,
+    pub children: [BVH4Child; 4],
+}
+
+// 4-wide BVH collapsed from the binary SAH tree: each node keeps up to four grandchildren
+// and tests all of their boxes at once
+pub struct BVH4 {
+    pub nodes: Vec<BVH4Node>,
+    pub primitives: Vec<Arc<dyn Hittable + Send + Sync>>,
+    pub bbox: Aabb,
+    // of the binary tree it was collapsed from
+    pub stats: BVHStats,
+}
+
+// a visited node pushes at most three more than it pops, the tree depth is at most 64
+const BVH4_STACK_SIZE: usize = 3 * 64 + 1;
+// marks a leaf lane on the traversal stack, node indices stay far below it
+const LEAF_FLAG: u32 = 1 << 31;
+
+impl BVH4 {
+    pub fn new(list: &Hittable_list) -> Self {
+        Self::new_from_flat(FlatBVH::new(list))
+    }
+
+    pub fn new_from_flat(flat: FlatBVH) -> Self {
+        let bbox = flat.bounding_box();
+        let mut nodes = Vec::new();
+        if !flat.nodes.is_empty() {
+            Self::collapse(&flat.nodes, 0, &mut nodes);
+        }
+        Self {
+            nodes,
+            primitives: flat.primitives,
+            bbox,
+            stats: flat.stats,
+        }
+    }
+
+    // returns the index of the BVH4 node made for the binary node `index`
+    fn collapse(binary: &[LinearBVHNode], index: usize, nodes: &mut Vec<BVH4Node>) -> usize {
+        let root = &binary[index];
+        let mut children = if root.primitive_count > 0 {
+            vec![index]
+        } else {
+            vec![index + 1, root.offset]
+        };
+        // open the interior child with the largest area until there are four
+        while children.len() < 4 {
+            let largest = children
+                .iter()
+                .enumerate()
+                .filter(|(_, &child)| binary[child].primitive_count == 0)
+                .max_by(|(_, &a), (_, &b)| binary[a].bbox.surface_area().total_cmp(&binary[b].bbox.surface_area()))
+                .map(|(slot, _)| slot);
+            let Some(slot) = largest else {
+                break;
+            };
+            let child = children[slot];
+            children[slot] = child + 1;
+            children.push(binary[child].offset);
+        }
+
+        let boxes: Vec<Aabbf> = children.iter().map(|&child| Aabbf::from(&binary[child].bbox)).collect();
+        let node_index = nodes.len();
+        nodes.push(BVH4Node {
+            bounds: Aabb4::new(&boxes),
+            children: [BVH4Child::Empty; 4],
+        });
+        for (slot, &child) in children.iter().enumerate() {
+            let node = &binary[child];
+            nodes[node_index].children[slot] = if node.primitive_count > 0 {
+                BVH4Child::Leaf(node.offset, node.primitive_count)
+            } else {
+                BVH4Child::Node(Self::collapse(binary, child, nodes))
+            };
+        }
+        node_index
+    }
+}
+
+// lanes sorted by decreasing entry distance, a sorting network is much cheaper than a sort call for four items
+fn far_to_near(entry: &[f32; 4]) -> [usize; 4] {
+    let mut order = [0, 1, 2, 3];
+    for (a, b) in [(0, 1), (2, 3), (0, 2), (1, 3), (1, 2)] {
+        if entry[order[a]] < entry[order[b]] {
+            order.swap(a, b);
+        }
+    }
+    order
+}
+
+impl Hittable for BVH4 {
+    fn hit(&self, r: &Ray, ray_t: Interval) -> Option<HitRecord<'_>> {
+        if self.nodes.is_empty() {
+            return None;
+        }
+        let ray = Rayf::from(r);
+        let tmin = round_down(ray_t.tmin);
+        let mut closest_so_far = ray_t.tmax;
+        let mut closest = None;
+        // (node index, or LEAF_FLAG | node index * 4 + lane for a leaf, entry distance of the box)
+        let mut stack = [(0u32, f32::NEG_INFINITY); BVH4_STACK_SIZE];
+        let mut stack_size = 1;
+
+        while stack_size > 0 {
+            stack_size -= 1;
+            let (item, item_entry) = stack[stack_size];
+            let tmax = closest_so_far as f32 * SLAB_PADDING;
+            if item_entry > tmax {
+                continue;
+            }
+
+            if item & LEAF_FLAG != 0 {
+                let leaf = (item & !LEAF_FLAG) as usize;
+                if let BVH4Child::Leaf(offset, count) = self.nodes[leaf / 4].children[leaf % 4] {
+                    for primitive in &self.primitives[offset..offset + count] {
+                        if let Some(rec) = primitive.hit(r, Interval::new(ray_t.tmin, closest_so_far)) {
+                            closest_so_far = rec.t;
+                            closest = Some(rec);
+                        }
+                    }
+                }
+                continue;
+            }
+
+            let node = &self.nodes[item as usize];
+            let entry = node.bounds.hit(&ray, tmin, tmax);
+            // push the far lanes first so that the nearest one is visited next
+            let order = far_to_near(&entry);
+            for &lane in &order {
+                if entry[lane] == f32::INFINITY {
+                    continue;
+                }
+                stack[stack_size] = match node.children[lane] {
+                    BVH4Child::Empty => continue,
+                    BVH4Child::Node(child) => (child as u32, entry[lane]),
+                    BVH4Child::Leaf(..) => (LEAF_FLAG | (item * 4 + lane as u32), entry[lane]),
+                };
+                stack_size += 1;
+            }
+        }
+        closest
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.bbox
+    }
+
+    fn instancing(self) -> Arc<dyn Hittable + Send + Sync> {
+        Arc::new(self)
+    }
+}
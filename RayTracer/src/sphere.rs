@@ -61,10 +61,18 @@ impl Sphere {
         *v = theta / PI;
     }
 
+    // derivatives of the point along the u and v of get_sphere_uv, p is relative to the center
+    pub fn get_sphere_dpduv(p: Vec3) -> (Vec3, Vec3) {
+        let rho = (p.x * p.x + p.z * p.z).sqrt().max(1e-8);
+        let dpdu = Vec3::new(p.z, 0.0, -p.x) * (2.0 * PI);
+        let dpdv = Vec3::new(-p.x * p.y / rho, rho, -p.z * p.y / rho) * PI;
+        (dpdu, dpdv)
+    }
+
 }
 
 impl Hittable for Sphere {
-    fn hit(&self, r: &Ray, ray_t: Interval, rec: &mut HitRecord, ) -> bool {
+    fn hit(&self, r: &Ray, ray_t: Interval) -> Option<HitRecord<'_>> {
         let center = if self.is_moving {
             self.sphere_center(r.time())
         }
@@ -78,30 +86,38 @@ impl Hittable for Sphere {
         let delta = h * h - a * c;
 
         if delta < 0.0 {
-            return false;
+            return None;
         }
 
         let mut temp = (h-delta.sqrt()) / a;
         if (!ray_t.surrounds(temp)) {
             temp = (h + delta.sqrt()) / a;
             if (!ray_t.surrounds(temp)) {
-                return false;
+                return None;
             }
         }
 
-        rec.t = temp;
-        rec.point = r.at(temp);
-        rec.front_face = (rec.point - center) * r.direction() < 0.0;
-        rec.normal = if rec.front_face {
-                        (rec.point - center) * (1.0 / self.radius)
+        let point = r.at(temp);
+        let front_face = (point - center) * r.direction() < 0.0;
+        let normal = if front_face {
+                        (point - center) * (1.0 / self.radius)
                     } 
                     else {
-                        (center - rec.point) * (1.0 / self.radius)
+                        (center - point) * (1.0 / self.radius)
                     };
-        rec.material = Arc::clone(&self.material);
+        let mut rec = HitRecord::new(temp, point, normal, self.material.as_ref(), front_face, 0.0, 0.0);
         Self::get_sphere_uv(rec.normal, &mut rec.u, &mut rec.v);
-
-        true
+        // the uv come from the normal facing the ray, so inside they are the uv of the opposite
+        // point, and the hit point moves against the tangents there; this is not a back face flip
+        let (dpdu, dpdv) = Self::get_sphere_dpduv(rec.normal * self.radius);
+        let side = if rec.front_face { 1.0 } else { -1.0 };
+        rec.dpdu = dpdu * side;
+        rec.dpdv = dpdv * side;
+        // the facing normal is the facing point over the radius
+        rec.dndu = dpdu / self.radius;
+        rec.dndv = dpdv / self.radius;
+
+        Some(rec)
     }
 
     fn bounding_box(&self) -> Aabb {
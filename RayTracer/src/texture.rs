@@ -1,15 +1,71 @@
 use crate::utils::*;
 use crate::vec3::*;
 use crate::color::*;
-use crate::interval::*;
 use crate::perlin::*;
+use crate::hitable::*;
 
 use std::sync::Arc;
 use std::path::Path;
-use image::{DynamicImage, GenericImageView};
+use std::fs::File;
+use std::io::BufReader;
+use image::codecs::hdr::HdrDecoder;
+use image::error::{ImageError, ImageResult, ParameterError, ParameterErrorKind};
+use image::{DynamicImage, ImageFormat, Rgb32FImage};
+
+// where a texture is looked up, with the size of the pixel footprint in uv when it is known
+#[derive(Copy, Clone, Debug)]
+pub struct TexCoord {
+    pub u: f64,
+    pub v: f64,
+    pub p: Vec3,
+    // zero when the lookup does not come from a surface
+    pub n: Vec3,
+    pub dpdx: Vec3,
+    pub dpdy: Vec3,
+    pub dudx: f64,
+    pub dvdx: f64,
+    pub dudy: f64,
+    pub dvdy: f64,
+}
+
+impl TexCoord {
+    // a point lookup, without footprint
+    pub fn new(u: f64, v: f64, p: Vec3) -> TexCoord {
+        TexCoord {
+            u,
+            v,
+            p,
+            n: Vec3::zero(),
+            dpdx: Vec3::zero(),
+            dpdy: Vec3::zero(),
+            dudx: 0.0,
+            dvdx: 0.0,
+            dudy: 0.0,
+            dvdy: 0.0,
+        }
+    }
+
+    pub fn from_hit(rec: &HitRecord) -> TexCoord {
+        let mut tc = Self::new(rec.u, rec.v, rec.point);
+        tc.n = rec.normal;
+        if let Some(d) = rec.differentials {
+            tc.dpdx = d.dpdx;
+            tc.dpdy = d.dpdy;
+            tc.dudx = d.dudx;
+            tc.dvdx = d.dvdx;
+            tc.dudy = d.dudy;
+            tc.dvdy = d.dvdy;
+        }
+        tc
+    }
+}
 
 pub trait TextureTrait {
     fn value(&self, u: f64, v: f64, p: Vec3) -> Vec3;
+    // filtered over the footprint of tc, textures that do not filter just look up the center
+    fn sample(&self, tc: &TexCoord) -> Vec3 {
+        self.value(tc.u, tc.v, tc.p)
+    }
     fn instancing(self) -> Arc<dyn TextureTrait + Send + Sync>;
 }
 
@@ -57,15 +113,22 @@ impl CheckerTexture {
             odd: SolidColor::new(odd).instancing(),
         }
     }
-}
 
-impl TextureTrait for CheckerTexture {
-    fn value(&self, u: f64, v: f64, p: Vec3) -> Vec3 {
+    fn is_even(&self, p: Vec3) -> bool {
         let xInteger = (self.inv_scale * p.x).floor() as i32;
         let yInteger = (self.inv_scale * p.y).floor() as i32;
         let zInteger = (self.inv_scale * p.z).floor() as i32;
-        let is_even = (xInteger + yInteger + zInteger) % 2 == 0;
-        if is_even { self.even.value(u, v, p) } else {self.odd.value(u, v, p) }
+        (xInteger + yInteger + zInteger) % 2 == 0
+    }
+}
+
+impl TextureTrait for CheckerTexture {
+    fn value(&self, u: f64, v: f64, p: Vec3) -> Vec3 {
+        if self.is_even(p) { self.even.value(u, v, p) } else {self.odd.value(u, v, p) }
+    }
+
+    fn sample(&self, tc: &TexCoord) -> Vec3 {
+        if self.is_even(tc.p) { self.even.sample(tc) } else { self.odd.sample(tc) }
     }
 
     fn instancing(self) -> Arc<dyn TextureTrait + Send + Sync> {
@@ -73,39 +136,352 @@ impl TextureTrait for CheckerTexture {
     }
 }
 
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum TextureFilter {
+    // the texel under the point
+    Nearest,
+    Bilinear,
+    // bilinear lookups in the two mipmap levels closest to the footprint size
+    Trilinear,
+    // gaussian weighted average over the elliptic footprint, stays sharp along its short axis at grazing angles
+    Ewa,
+}
+
+// what an image shows outside [0, 1]^2
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum WrapMode {
+    Repeat,
+    // repeats with every other copy flipped, so that the edges always match
+    Mirror,
+    // the border texels are stretched outward
+    Clamp,
+    // a constant color around the image
+    Border(Vec3),
+}
+
+impl WrapMode {
+    // index in 0..size of texel i, None when it falls on the border color
+    fn wrap(&self, i: i64, size: usize) -> Option<usize> {
+        let n = size as i64;
+        match self {
+            WrapMode::Repeat => Some(i.rem_euclid(n) as usize),
+            WrapMode::Mirror => {
+                let i = i.rem_euclid(2 * n);
+                Some(if i < n { i } else { 2 * n - 1 - i } as usize)
+            }
+            WrapMode::Clamp => Some(i.clamp(0, n - 1) as usize),
+            WrapMode::Border(_) => (0..n).contains(&i).then_some(i as usize),
+        }
+    }
+}
+
+// longest / shortest axis of an EWA footprint, longer ones are widened and use a coarser level
+const MAX_ANISOTROPY: f64 = 8.0;
+// falloff of the EWA gaussian
+const EWA_ALPHA: f64 = 2.0;
+
+// one level of a mipmap, kept in memory by ImageTexture and paged in tiles by TextureManager
+pub trait MipLevelTrait {
+    fn size(&self) -> (usize, usize);
+    // x and y are inside the level
+    fn fetch(&self, x: usize, y: usize) -> Vec3;
+
+    // the texels (x[0], y[0]), (x[1], y[0]), (x[0], y[1]) and (x[1], y[1])
+    fn fetch_quad(&self, x: [usize; 2], y: [usize; 2]) -> [Vec3; 4] {
+        [self.fetch(x[0], y[0]), self.fetch(x[1], y[0]), self.fetch(x[0], y[1]), self.fetch(x[1], y[1])]
+    }
+
+    fn texel(&self, x: i64, y: i64, wrap: WrapMode) -> Vec3 {
+        let (width, height) = self.size();
+        match (wrap.wrap(x, width), wrap.wrap(y, height)) {
+            (Some(x), Some(y)) => self.fetch(x, y),
+            // only Border leaves the image
+            _ => match wrap {
+                WrapMode::Border(color) => color,
+                _ => Vec3::zero(),
+            },
+        }
+    }
+
+    // (s, t) in [0, 1]^2 with t growing downward like the rows, texel centers are at half texels
+    fn bilinear(&self, s: f64, t: f64, wrap: WrapMode) -> Vec3 {
+        let (width, height) = self.size();
+        let x = s * width as f64 - 0.5;
+        let y = t * height as f64 - 0.5;
+        let (x0, y0) = (x.floor(), y.floor());
+        let (fx, fy) = (x - x0, y - y0);
+        let (x0, y0) = (x0 as i64, y0 as i64);
+        let texels = match (wrap.wrap(x0, width), wrap.wrap(x0 + 1, width), wrap.wrap(y0, height), wrap.wrap(y0 + 1, height)) {
+            (Some(x0), Some(x1), Some(y0), Some(y1)) => self.fetch_quad([x0, x1], [y0, y1]),
+            _ => [
+                self.texel(x0, y0, wrap),
+                self.texel(x0 + 1, y0, wrap),
+                self.texel(x0, y0 + 1, wrap),
+                self.texel(x0 + 1, y0 + 1, wrap),
+            ],
+        };
+        texels[0] * ((1.0 - fx) * (1.0 - fy)) + texels[1] * (fx * (1.0 - fy)) + texels[2] * ((1.0 - fx) * fy) + texels[3] * (fx * fy)
+    }
+
+    // average over the ellipse with axes axis0 and axis1 (in [0, 1]^2 units) around (s, t)
+    fn ewa(&self, s: f64, t: f64, axis0: (f64, f64), axis1: (f64, f64), wrap: WrapMode) -> Vec3 {
+        let (width, height) = self.size();
+        let (w, h) = (width as f64, height as f64);
+        let (s, t) = (s * w - 0.5, t * h - 0.5);
+        let (ds0, dt0) = (axis0.0 * w, axis0.1 * h);
+        let (ds1, dt1) = (axis1.0 * w, axis1.1 * h);
+
+        // implicit ellipse a x^2 + b x y + c y^2 < 1, the + 1 keeps it at least a texel wide
+        let mut a = dt0 * dt0 + dt1 * dt1 + 1.0;
+        let mut b = -2.0 * (ds0 * dt0 + ds1 * dt1);
+        let mut c = ds0 * ds0 + ds1 * ds1 + 1.0;
+        let inv_f = 1.0 / (a * c - b * b * 0.25);
+        a *= inv_f;
+        b *= inv_f;
+        c *= inv_f;
+
+        // texels inside its bounding box
+        let det = 4.0 * a * c - b * b;
+        let s_extent = 2.0 * (det * c).sqrt() / det;
+        let t_extent = 2.0 * (det * a).sqrt() / det;
+        let (s0, s1) = ((s - s_extent).ceil() as i64, (s + s_extent).floor() as i64);
+        let (t0, t1) = ((t - t_extent).ceil() as i64, (t + t_extent).floor() as i64);
+
+        let mut sum = Vec3::zero();
+        let mut weight_sum = 0.0;
+        for y in t0..=t1 {
+            let dy = y as f64 - t;
+            for x in s0..=s1 {
+                let dx = x as f64 - s;
+                let r2 = a * dx * dx + b * dx * dy + c * dy * dy;
+                if r2 < 1.0 {
+                    let weight = (-EWA_ALPHA * r2).exp() - (-EWA_ALPHA).exp();
+                    sum += self.texel(x, y, wrap) * weight;
+                    weight_sum += weight;
+                }
+            }
+        }
+        if weight_sum > 0.0 {
+            sum / weight_sum
+        } else {
+            self.bilinear((s + 0.5) / w, (t + 0.5) / h, wrap)
+        }
+    }
+
+    // texel (x, y) of the next level, the average of a 2 x 2 block
+    // only odd sizes read past the edge, where the last row or column is repeated
+    fn downsampled_texel(&self, x: usize, y: usize) -> Vec3 {
+        let (width, height) = self.size();
+        let [a, b, c, d] = self.fetch_quad([2 * x, (2 * x + 1).min(width - 1)], [2 * y, (2 * y + 1).min(height - 1)]);
+        (a + b + c + d) * 0.25
+    }
+}
+
+impl<L: MipLevelTrait> MipLevelTrait for Arc<L> {
+    fn size(&self) -> (usize, usize) {
+        self.as_ref().size()
+    }
+
+    fn fetch(&self, x: usize, y: usize) -> Vec3 {
+        self.as_ref().fetch(x, y)
+    }
+
+    fn fetch_quad(&self, x: [usize; 2], y: [usize; 2]) -> [Vec3; 4] {
+        self.as_ref().fetch_quad(x, y)
+    }
+}
+
+struct MipLevel {
+    width: usize,
+    height: usize,
+    texels: Vec<[f32; 3]>,
+}
+
+impl MipLevelTrait for MipLevel {
+    fn size(&self) -> (usize, usize) {
+        (self.width, self.height)
+    }
+
+    fn fetch(&self, x: usize, y: usize) -> Vec3 {
+        let [r, g, b] = self.texels[y * self.width + x];
+        Vec3::new(r as f64, g as f64, b as f64)
+    }
+}
+
+impl MipLevel {
+    // half the size, rounded up
+    fn downsample(&self) -> MipLevel {
+        let (width, height) = (self.width.div_ceil(2), self.height.div_ceil(2));
+        let mut texels = Vec::with_capacity(width * height);
+        for y in 0..height {
+            for x in 0..width {
+                let average = self.downsampled_texel(x, y);
+                texels.push([average.x as f32, average.y as f32, average.z as f32]);
+            }
+        }
+        MipLevel {
+            width,
+            height,
+            texels,
+        }
+    }
+}
+
+// fractional level whose texels are about width wide, in [0, 1]^2 units
+fn level_of<L: MipLevelTrait>(levels: &[L], width: f64) -> f64 {
+    let (w, h) = levels[0].size();
+    (width * w.max(h) as f64).max(1e-8).log2().clamp(0.0, (levels.len() - 1) as f64)
+}
+
+// blends the lookups in the two levels around level
+fn between_levels<L: MipLevelTrait>(levels: &[L], level: f64, lookup: impl Fn(&L) -> Vec3) -> Vec3 {
+    let l0 = level.floor() as usize;
+    let l1 = (l0 + 1).min(levels.len() - 1);
+    let f = level - l0 as f64;
+    if l0 == l1 || f == 0.0 {
+        return lookup(&levels[l0]);
+    }
+    lookup(&levels[l0]) * (1.0 - f) + lookup(&levels[l1]) * f
+}
+
+fn ewa_lookup<L: MipLevelTrait>(levels: &[L], s: f64, t: f64, axis_x: (f64, f64), axis_y: (f64, f64), wrap: WrapMode) -> Vec3 {
+    let length = |a: (f64, f64)| (a.0 * a.0 + a.1 * a.1).sqrt();
+    let (major, mut minor) = if length(axis_x) >= length(axis_y) { (axis_x, axis_y) } else { (axis_y, axis_x) };
+    let major_length = length(major);
+    let mut minor_length = length(minor);
+    if minor_length > 0.0 && minor_length * MAX_ANISOTROPY < major_length {
+        let scale = major_length / (minor_length * MAX_ANISOTROPY);
+        minor = (minor.0 * scale, minor.1 * scale);
+        minor_length *= scale;
+    }
+    if minor_length == 0.0 {
+        return levels[0].bilinear(s, t, wrap);
+    }
+    between_levels(levels, level_of(levels, minor_length), |level| level.ewa(s, t, major, minor, wrap))
+}
+
+// filtered lookup in a mipmap, levels[0] is the image and every level is half the previous one
+pub fn sample_mipmap<L: MipLevelTrait>(levels: &[L], filter: TextureFilter, wrap: WrapMode, tc: &TexCoord) -> Vec3 {
+    if levels.is_empty() {
+        return Vec3::new(0.0, 1.0, 1.0);
+    }
+
+    // rows go down the image
+    let (s, t) = (tc.u, 1.0 - tc.v);
+
+    match filter {
+        TextureFilter::Nearest => {
+            let (width, height) = levels[0].size();
+            let i = (s * width as f64).floor() as i64;
+            let j = (t * height as f64).floor() as i64;
+            levels[0].texel(i, j, wrap)
+        }
+        TextureFilter::Bilinear => levels[0].bilinear(s, t, wrap),
+        TextureFilter::Trilinear => {
+            let width = 2.0 * fmax(fmax(fabs(tc.dudx), fabs(tc.dvdx)), fmax(fabs(tc.dudy), fabs(tc.dvdy)));
+            between_levels(levels, level_of(levels, width), |level| level.bilinear(s, t, wrap))
+        }
+        // t grows the other way than v
+        TextureFilter::Ewa => ewa_lookup(levels, s, t, (tc.dudx, -tc.dvdx), (tc.dudy, -tc.dvdy), wrap),
+    }
+}
+
+// image::open tone maps Radiance HDR files down to 8 bits, so those are decoded here as floats
+pub fn open_image(path: &Path) -> ImageResult<DynamicImage> {
+    if ImageFormat::from_path(path).ok() != Some(ImageFormat::Hdr) {
+        return image::open(path);
+    }
+    let decoder = HdrDecoder::new(BufReader::new(File::open(path)?))?;
+    let meta = decoder.metadata();
+    let pixels = decoder.read_image_hdr()?;
+    let buffer = Rgb32FImage::from_raw(meta.width, meta.height, pixels.iter().flat_map(|p| p.0).collect())
+        .ok_or(ImageError::Parameter(ParameterError::from_kind(ParameterErrorKind::DimensionMismatch)))?;
+    Ok(DynamicImage::ImageRgb32F(buffer))
+}
+
+// the image and its mipmap, level 0 is the image and every level is half the previous one
 pub struct ImageTexture {
-    img: DynamicImage,
+    levels: Vec<MipLevel>,
+    pub filter: TextureFilter,
+    pub wrap: WrapMode,
 }
 
 impl ImageTexture {
     pub fn new(path: &Path) -> Self {
-        let img = image::open(path).expect("File not found");
-        ImageTexture { img }
+        Self::new_with_options(path, TextureFilter::Trilinear, ColorSpace::Srgb)
     }
-}
 
-impl TextureTrait for ImageTexture {
-    fn value(&self, u: f64, v: f64, p: Vec3) -> Vec3 {
-        let (width, height) = (self.img.width(), self.img.height());
-        if height == 0 || width == 0 {
-            return Vec3::new(0.0, 1.0, 1.0);
+    pub fn new_with_filter(path: &Path, filter: TextureFilter) -> Self {
+        Self::new_with_options(path, filter, ColorSpace::Srgb)
+    }
+
+    // data maps like roughness, height or normal maps should be ColorSpace::Raw
+    pub fn new_with_color_space(path: &Path, color_space: ColorSpace) -> Self {
+        Self::new_with_options(path, TextureFilter::Trilinear, color_space)
+    }
+
+    pub fn new_with_options(path: &Path, filter: TextureFilter, color_space: ColorSpace) -> Self {
+        Self::new_from_image(&open_image(path).expect("File not found"), filter, color_space)
+    }
+
+    // 16-bit and float images keep their precision; float images (HDR, OpenEXR) are linear by
+    // definition, so color_space only decodes integer ones
+    pub fn new_from_image(img: &DynamicImage, filter: TextureFilter, color_space: ColorSpace) -> Self {
+        let is_float = matches!(img, DynamicImage::ImageRgb32F(_) | DynamicImage::ImageRgba32F(_));
+        let decode = |value: f32| if is_float { value } else { color_space.decode(value) };
+        let img = img.to_rgb32f();
+        let (width, height) = (img.width() as usize, img.height() as usize);
+        let mut levels = Vec::new();
+        if width > 0 && height > 0 {
+            let texels = img.pixels().map(|p| [decode(p[0]), decode(p[1]), decode(p[2])]).collect();
+            levels.push(MipLevel {
+                width,
+                height,
+                texels,
+            });
+            // only the filters that read coarser levels need them
+            if matches!(filter, TextureFilter::Trilinear | TextureFilter::Ewa) {
+                while let Some(last) = levels.last().filter(|l| l.width > 1 || l.height > 1) {
+                    let next = last.downsample();
+                    levels.push(next);
+                }
+            }
+        }
+        ImageTexture {
+            levels,
+            filter,
+            wrap: WrapMode::Clamp,
         }
+    }
+
+    // the alpha channel as a gray texture, 1 where the image has no alpha; for Cutout
+    pub fn new_alpha(path: &Path) -> Self {
+        Self::new_alpha_from_image(&open_image(path).expect("File not found"), TextureFilter::Bilinear)
+    }
+
+    // alpha is linear coverage, it is never decoded
+    pub fn new_alpha_from_image(img: &DynamicImage, filter: TextureFilter) -> Self {
+        let rgba = img.to_rgba32f();
+        let alpha = Rgb32FImage::from_fn(rgba.width(), rgba.height(), |x, y| {
+            let a = rgba.get_pixel(x, y)[3];
+            image::Rgb([a, a, a])
+        });
+        Self::new_from_image(&DynamicImage::ImageRgb32F(alpha), filter, ColorSpace::Raw)
+    }
 
-        let u = Interval::new(0.0,1.0).clamp(u);
-        let v = 1.0 - Interval::new(0.0,1.0).clamp(v);
+    // Clamp by default, tiling with UvTransformTexture needs Repeat or Mirror
+    pub fn set_wrap(&mut self, wrap: WrapMode) {
+        self.wrap = wrap;
+    }
+}
 
-        
-        let i = (u * (width - 1) as f64) as u32;   
-        let j = (v * (height - 1) as f64) as u32;
-            
-        let color_scale = 1.0 / 255.0;
-        let pixel = self.img.get_pixel(i, j);
+impl TextureTrait for ImageTexture {
+    fn value(&self, u: f64, v: f64, p: Vec3) -> Vec3 {
+        self.sample(&TexCoord::new(u, v, p))
+    }
 
-        Vec3::new(
-            color_scale * pixel[0] as f64,
-            color_scale * pixel[1] as f64,
-            color_scale * pixel[2] as f64,
-        )
+    fn sample(&self, tc: &TexCoord) -> Vec3 {
+        sample_mipmap(&self.levels, self.filter, self.wrap, tc)
     }
 
     fn instancing(self) -> Arc<dyn TextureTrait + Send + Sync> {
@@ -113,9 +489,15 @@ impl TextureTrait for ImageTexture {
     }
 }
 
+// marble: a sine wave along axis whose stripes are bent by turbulence
+// usage: NoiseTexture::new(4.0).axis(Vec3::new(1.0, 0.0, 0.0)).turbulence(6.0, 5).ramp(ramp)
 pub struct NoiseTexture {
     noise: Perlin,
     scale: f64,
+    axis: Vec3,
+    turbulence: f64,
+    depth: usize,
+    ramp: ColorRamp,
 }
 
 impl NoiseTexture {
@@ -123,13 +505,118 @@ impl NoiseTexture {
         Self{
             noise: Perlin::new(),
             scale,
+            axis: Vec3::new(0.0, 0.0, 1.0),
+            turbulence: 10.0,
+            depth: 7,
+            ramp: ColorRamp::grayscale(),
         }
     }
+
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.noise = Perlin::new_with_seed(seed);
+        self
+    }
+
+    // the stripes run across it
+    pub fn axis(mut self, axis: Vec3) -> Self {
+        self.axis = unit_vec(axis);
+        self
+    }
+
+    pub fn turbulence(mut self, turbulence: f64, depth: usize) -> Self {
+        self.turbulence = turbulence;
+        self.depth = depth;
+        self
+    }
+
+    pub fn ramp(mut self, ramp: ColorRamp) -> Self {
+        self.ramp = ramp;
+        self
+    }
 }
 
 impl TextureTrait for NoiseTexture {
     fn value(&self, u: f64, v: f64, p: Vec3) -> Vec3 {
-       Vec3::new(0.5, 0.5, 0.5) * (1.0 + ( self.scale * p.z + 10.0 * self.noise.turb(p, 7 as usize)).sin())
+        let wave = (self.scale * (p * self.axis) + self.turbulence * self.noise.turb(p, self.depth)).sin();
+        self.ramp.eval(0.5 * (1.0 + wave))
+    }
+
+    fn instancing(self) -> Arc<dyn TextureTrait + Send + Sync> {
+        Arc::new(self)
+    }
+}
+
+
+// moves the texture coordinates before looking up tex: mirror, then rotate around the center of
+// the texture, then tile and offset; tiles only repeat if tex does, e.g. an ImageTexture set to
+// WrapMode::Repeat or WrapMode::Mirror
+// usage: UvTransformTexture::new(tex).tile(4.0, 2.0).rotate(30.0).offset(0.25, 0.0)
+pub struct UvTransformTexture {
+    tex: Arc<dyn TextureTrait + Send + Sync>,
+    tiles: (f64, f64),
+    offset: (f64, f64),
+    // degrees, counterclockwise
+    rotation: f64,
+    mirror: (bool, bool),
+}
+
+impl UvTransformTexture {
+    pub fn new(tex: Arc<dyn TextureTrait + Send + Sync>) -> Self {
+        Self {
+            tex,
+            tiles: (1.0, 1.0),
+            offset: (0.0, 0.0),
+            rotation: 0.0,
+            mirror: (false, false),
+        }
+    }
+
+    pub fn tile(mut self, u: f64, v: f64) -> Self {
+        self.tiles = (u, v);
+        self
+    }
+
+    pub fn offset(mut self, u: f64, v: f64) -> Self {
+        self.offset = (u, v);
+        self
+    }
+
+    pub fn rotate(mut self, degrees: f64) -> Self {
+        self.rotation = degrees;
+        self
+    }
+
+    pub fn mirror(mut self, u: bool, v: bool) -> Self {
+        self.mirror = (u, v);
+        self
+    }
+
+    // the linear part, which also carries the footprint over
+    fn transform_vector(&self, du: f64, dv: f64) -> (f64, f64) {
+        let du = if self.mirror.0 { -du } else { du };
+        let dv = if self.mirror.1 { -dv } else { dv };
+        let (sin, cos) = self.rotation.to_radians().sin_cos();
+        ((cos * du - sin * dv) * self.tiles.0, (sin * du + cos * dv) * self.tiles.1)
+    }
+
+    fn transform(&self, u: f64, v: f64) -> (f64, f64) {
+        let (u, v) = self.transform_vector(u - 0.5, v - 0.5);
+        (u + 0.5 * self.tiles.0 + self.offset.0, v + 0.5 * self.tiles.1 + self.offset.1)
+    }
+}
+
+impl TextureTrait for UvTransformTexture {
+    fn value(&self, u: f64, v: f64, p: Vec3) -> Vec3 {
+        let (u, v) = self.transform(u, v);
+        self.tex.value(u, v, p)
+    }
+
+    fn sample(&self, tc: &TexCoord) -> Vec3 {
+        let mut transformed = *tc;
+        (transformed.u, transformed.v) = self.transform(tc.u, tc.v);
+        (transformed.dudx, transformed.dvdx) = self.transform_vector(tc.dudx, tc.dvdx);
+        (transformed.dudy, transformed.dvdy) = self.transform_vector(tc.dudy, tc.dvdy);
+        self.tex.sample(&transformed)
     }
 
     fn instancing(self) -> Arc<dyn TextureTrait + Send + Sync> {
@@ -137,3 +624,73 @@ impl TextureTrait for NoiseTexture {
     }
 }
 
+// projects textures along the x, y and z axes and blends them by the normal, for surfaces without
+// usable uv like the boxes of create_box; the projections use the world space point with scale
+// repeats per unit, so the textures should repeat
+pub struct TriplanarTexture {
+    x: Arc<dyn TextureTrait + Send + Sync>,
+    y: Arc<dyn TextureTrait + Send + Sync>,
+    z: Arc<dyn TextureTrait + Send + Sync>,
+    scale: f64,
+    // higher values narrow the blend where two projections meet
+    sharpness: f64,
+}
+
+impl TriplanarTexture {
+    pub fn new(tex: Arc<dyn TextureTrait + Send + Sync>, scale: f64, sharpness: f64) -> Self {
+        Self::new_per_axis(tex.clone(), tex.clone(), tex, scale, sharpness)
+    }
+
+    pub fn new_per_axis(x: Arc<dyn TextureTrait + Send + Sync>, y: Arc<dyn TextureTrait + Send + Sync>, z: Arc<dyn TextureTrait + Send + Sync>, scale: f64, sharpness: f64) -> Self {
+        Self {
+            x,
+            y,
+            z,
+            scale,
+            sharpness,
+        }
+    }
+
+    // tex with u along u_axis and v along v_axis of the point
+    fn project(&self, tex: &Arc<dyn TextureTrait + Send + Sync>, tc: &TexCoord, u_axis: u8, v_axis: u8) -> Vec3 {
+        let mut projected = *tc;
+        projected.u = tc.p.lp(u_axis) * self.scale;
+        projected.v = tc.p.lp(v_axis) * self.scale;
+        projected.dudx = tc.dpdx.lp(u_axis) * self.scale;
+        projected.dvdx = tc.dpdx.lp(v_axis) * self.scale;
+        projected.dudy = tc.dpdy.lp(u_axis) * self.scale;
+        projected.dvdy = tc.dpdy.lp(v_axis) * self.scale;
+        tex.sample(&projected)
+    }
+}
+
+impl TextureTrait for TriplanarTexture {
+    fn value(&self, u: f64, v: f64, p: Vec3) -> Vec3 {
+        self.sample(&TexCoord::new(u, v, p))
+    }
+
+    fn sample(&self, tc: &TexCoord) -> Vec3 {
+        let mut weights = [
+            fabs(tc.n.x).powf(self.sharpness),
+            fabs(tc.n.y).powf(self.sharpness),
+            fabs(tc.n.z).powf(self.sharpness),
+        ];
+        let total: f64 = weights.iter().sum();
+        // without a normal all three are blended equally
+        for w in weights.iter_mut() {
+            *w = if total > 0.0 { *w / total } else { 1.0 / 3.0 };
+        }
+
+        let mut color = Vec3::zero();
+        for (weight, tex, u_axis, v_axis) in [(weights[0], &self.x, 2, 1), (weights[1], &self.y, 0, 2), (weights[2], &self.z, 0, 1)] {
+            if weight > 1e-3 {
+                color += self.project(tex, tc, u_axis, v_axis) * weight;
+            }
+        }
+        color
+    }
+
+    fn instancing(self) -> Arc<dyn TextureTrait + Send + Sync> {
+        Arc::new(self)
+    }
+}
@@ -0,0 +1,618 @@
+use crate::color::*;
+use crate::perlin::*;
+use crate::procedural::*;
+use crate::texture::*;
+use crate::texture_manager::*;
+use crate::utils::*;
+use crate::vec3::*;
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+
+// textures that combine other textures; a scalar is a gray color, and where a color is used as a
+// scalar (a mix factor, a ramp input) its luminance is taken
+
+// a where factor is 0, b where it is 1
+pub struct MixTexture {
+    a: Arc<dyn TextureTrait + Send + Sync>,
+    b: Arc<dyn TextureTrait + Send + Sync>,
+    factor: Arc<dyn TextureTrait + Send + Sync>,
+}
+
+impl MixTexture {
+    pub fn new(a: Arc<dyn TextureTrait + Send + Sync>, b: Arc<dyn TextureTrait + Send + Sync>, factor: f64) -> Self {
+        Self::new_with_mask(a, b, SolidColor::new(Vec3::ones() * factor).instancing())
+    }
+
+    pub fn new_with_mask(a: Arc<dyn TextureTrait + Send + Sync>, b: Arc<dyn TextureTrait + Send + Sync>, mask: Arc<dyn TextureTrait + Send + Sync>) -> Self {
+        Self { a, b, factor: mask }
+    }
+}
+
+impl TextureTrait for MixTexture {
+    fn value(&self, u: f64, v: f64, p: Vec3) -> Vec3 {
+        self.sample(&TexCoord::new(u, v, p))
+    }
+
+    fn sample(&self, tc: &TexCoord) -> Vec3 {
+        let factor = luminance(self.factor.sample(tc));
+        // only look up the side that shows
+        if factor <= 0.0 {
+            return self.a.sample(tc);
+        }
+        if factor >= 1.0 {
+            return self.b.sample(tc);
+        }
+        self.a.sample(tc) * (1.0 - factor) + self.b.sample(tc) * factor
+    }
+
+    fn instancing(self) -> Arc<dyn TextureTrait + Send + Sync> {
+        Arc::new(self)
+    }
+}
+
+// per channel
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum MathOp {
+    Add,
+    Subtract,
+    Multiply,
+    Min,
+    Max,
+}
+
+pub struct MathTexture {
+    op: MathOp,
+    a: Arc<dyn TextureTrait + Send + Sync>,
+    b: Arc<dyn TextureTrait + Send + Sync>,
+}
+
+impl MathTexture {
+    pub fn new(op: MathOp, a: Arc<dyn TextureTrait + Send + Sync>, b: Arc<dyn TextureTrait + Send + Sync>) -> Self {
+        Self { op, a, b }
+    }
+}
+
+impl TextureTrait for MathTexture {
+    fn value(&self, u: f64, v: f64, p: Vec3) -> Vec3 {
+        self.sample(&TexCoord::new(u, v, p))
+    }
+
+    fn sample(&self, tc: &TexCoord) -> Vec3 {
+        let (a, b) = (self.a.sample(tc), self.b.sample(tc));
+        match self.op {
+            MathOp::Add => a + b,
+            MathOp::Subtract => a - b,
+            MathOp::Multiply => dot(a, b),
+            MathOp::Min => Vec3::merge_min(&a, &b),
+            MathOp::Max => Vec3::merge_max(&a, &b),
+        }
+    }
+
+    fn instancing(self) -> Arc<dyn TextureTrait + Send + Sync> {
+        Arc::new(self)
+    }
+}
+
+// 1 - c
+pub struct InvertTexture {
+    tex: Arc<dyn TextureTrait + Send + Sync>,
+}
+
+impl InvertTexture {
+    pub fn new(tex: Arc<dyn TextureTrait + Send + Sync>) -> Self {
+        Self { tex }
+    }
+}
+
+impl TextureTrait for InvertTexture {
+    fn value(&self, u: f64, v: f64, p: Vec3) -> Vec3 {
+        self.sample(&TexCoord::new(u, v, p))
+    }
+
+    fn sample(&self, tc: &TexCoord) -> Vec3 {
+        Vec3::ones() - self.tex.sample(tc)
+    }
+
+    fn instancing(self) -> Arc<dyn TextureTrait + Send + Sync> {
+        Arc::new(self)
+    }
+}
+
+// turns the hue by hue_shift turns and scales saturation and value
+pub struct HsvTexture {
+    tex: Arc<dyn TextureTrait + Send + Sync>,
+    hue_shift: f64,
+    saturation: f64,
+    value: f64,
+}
+
+impl HsvTexture {
+    pub fn new(tex: Arc<dyn TextureTrait + Send + Sync>, hue_shift: f64, saturation: f64, value: f64) -> Self {
+        Self {
+            tex,
+            hue_shift,
+            saturation,
+            value,
+        }
+    }
+}
+
+impl TextureTrait for HsvTexture {
+    fn value(&self, u: f64, v: f64, p: Vec3) -> Vec3 {
+        self.sample(&TexCoord::new(u, v, p))
+    }
+
+    fn sample(&self, tc: &TexCoord) -> Vec3 {
+        let hsv = rgb_to_hsv(self.tex.sample(tc));
+        hsv_to_rgb(Vec3::new(hsv.x + self.hue_shift, (hsv.y * self.saturation).clamp(0.0, 1.0), hsv.z * self.value))
+    }
+
+    fn instancing(self) -> Arc<dyn TextureTrait + Send + Sync> {
+        Arc::new(self)
+    }
+}
+
+// the luminance of tex through a color ramp
+pub struct ColorRampTexture {
+    tex: Arc<dyn TextureTrait + Send + Sync>,
+    ramp: ColorRamp,
+}
+
+impl ColorRampTexture {
+    pub fn new(tex: Arc<dyn TextureTrait + Send + Sync>, ramp: ColorRamp) -> Self {
+        Self { tex, ramp }
+    }
+}
+
+impl TextureTrait for ColorRampTexture {
+    fn value(&self, u: f64, v: f64, p: Vec3) -> Vec3 {
+        self.sample(&TexCoord::new(u, v, p))
+    }
+
+    fn sample(&self, tc: &TexCoord) -> Vec3 {
+        self.ramp.eval(luminance(self.tex.sample(tc)))
+    }
+
+    fn instancing(self) -> Arc<dyn TextureTrait + Send + Sync> {
+        Arc::new(self)
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Channel {
+    Red,
+    Green,
+    Blue,
+    Luminance,
+}
+
+// one channel of a color as a gray scalar
+pub struct ChannelTexture {
+    tex: Arc<dyn TextureTrait + Send + Sync>,
+    channel: Channel,
+}
+
+impl ChannelTexture {
+    pub fn new(tex: Arc<dyn TextureTrait + Send + Sync>, channel: Channel) -> Self {
+        Self { tex, channel }
+    }
+}
+
+impl TextureTrait for ChannelTexture {
+    fn value(&self, u: f64, v: f64, p: Vec3) -> Vec3 {
+        self.sample(&TexCoord::new(u, v, p))
+    }
+
+    fn sample(&self, tc: &TexCoord) -> Vec3 {
+        let c = self.tex.sample(tc);
+        let scalar = match self.channel {
+            Channel::Red => c.x,
+            Channel::Green => c.y,
+            Channel::Blue => c.z,
+            Channel::Luminance => luminance(c),
+        };
+        Vec3::ones() * scalar
+    }
+
+    fn instancing(self) -> Arc<dyn TextureTrait + Send + Sync> {
+        Arc::new(self)
+    }
+}
+
+// a color from three scalars
+pub struct CombineTexture {
+    r: Arc<dyn TextureTrait + Send + Sync>,
+    g: Arc<dyn TextureTrait + Send + Sync>,
+    b: Arc<dyn TextureTrait + Send + Sync>,
+}
+
+impl CombineTexture {
+    pub fn new(r: Arc<dyn TextureTrait + Send + Sync>, g: Arc<dyn TextureTrait + Send + Sync>, b: Arc<dyn TextureTrait + Send + Sync>) -> Self {
+        Self { r, g, b }
+    }
+}
+
+impl TextureTrait for CombineTexture {
+    fn value(&self, u: f64, v: f64, p: Vec3) -> Vec3 {
+        self.sample(&TexCoord::new(u, v, p))
+    }
+
+    fn sample(&self, tc: &TexCoord) -> Vec3 {
+        Vec3::new(luminance(self.r.sample(tc)), luminance(self.g.sample(tc)), luminance(self.b.sample(tc)))
+    }
+
+    fn instancing(self) -> Arc<dyn TextureTrait + Send + Sync> {
+        Arc::new(self)
+    }
+}
+
+// the named textures of a texture graph file, one texture per line, each built from the ones above:
+//
+//   # comments run to the end of the line
+//   rings = wood center=0,0,0 axis=0,1,0 rings=6 seed=3
+//   spots = worley scale=4 feature=f2-f1
+//   dark  = hsv input=rings value=0.5
+//   out   = mix a=rings b=dark factor=spots
+//
+// arguments are key=value without spaces, a key the node does not take is an error; where a texture
+// is expected, a number or an r,g,b color gives a constant one. The nodes and their arguments (defaults in brackets):
+//   color value                       image path [color_space=srgb filter=trilinear wrap=clamp]
+//   checker scale even odd            marble [scale=1 axis=0,0,1 turbulence=10 depth=7 seed]
+//   fbm [scale=1 kind=fbm octaves=7 lacunarity=2 gain=0.5 time seed]
+//   worley [scale=1 feature=f1 seed]  wood center axis rings [turbulence=0.3 noise_scale=1.5 seed]
+//   gradient from to                  radial center radius
+//   mix a b factor                    add / subtract / multiply / min / max a b
+//   invert input                      hsv input [hue=0 saturation=1 value=1]
+//   ramp input stop=position:r,g,b... channel input which=r|g|b|luminance
+//   combine r g b
+// image paths are relative to the graph file; images are loaded through textures
+pub struct TextureGraph {
+    textures: HashMap<String, Arc<dyn TextureTrait + Send + Sync>>,
+}
+
+impl TextureGraph {
+    pub fn new_from_file(path: &Path, textures: &TextureManager) -> TextureGraph {
+        let text = fs::read_to_string(path).expect("File not found");
+        Self::parse(&text, path.parent().unwrap_or(Path::new("")), textures)
+    }
+
+    // panics on the first line that cannot be read, naming it
+    pub fn parse(text: &str, base_dir: &Path, textures: &TextureManager) -> TextureGraph {
+        let mut graph = TextureGraph {
+            textures: HashMap::new(),
+        };
+        for (number, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or("").trim();
+            if line.is_empty() {
+                continue;
+            }
+            let node = Node::parse(number + 1, line);
+            let texture = graph.build(&node, base_dir, textures);
+            node.check_unused();
+            graph.textures.insert(node.name.to_string(), texture);
+        }
+        graph
+    }
+
+    pub fn get(&self, name: &str) -> Option<Arc<dyn TextureTrait + Send + Sync>> {
+        self.textures.get(name).cloned()
+    }
+
+    fn build(&self, node: &Node, base_dir: &Path, textures: &TextureManager) -> Arc<dyn TextureTrait + Send + Sync> {
+        let input = |key: &str| self.input(node, key);
+        match node.kind {
+            "color" => SolidColor::new(node.vector("value", None)).instancing(),
+            "image" => {
+                let path = base_dir.join(node.required("path"));
+                let color_space = match node.get("color_space").unwrap_or("srgb") {
+                    "srgb" => ColorSpace::Srgb,
+                    "linear" => ColorSpace::Linear,
+                    "raw" => ColorSpace::Raw,
+                    other => node.fail(&format!("unknown color space {}", other)),
+                };
+                let filter = match node.get("filter").unwrap_or("trilinear") {
+                    "nearest" => TextureFilter::Nearest,
+                    "bilinear" => TextureFilter::Bilinear,
+                    "trilinear" => TextureFilter::Trilinear,
+                    "ewa" => TextureFilter::Ewa,
+                    other => node.fail(&format!("unknown filter {}", other)),
+                };
+                let wrap = match node.get("wrap").unwrap_or("clamp") {
+                    "repeat" => WrapMode::Repeat,
+                    "mirror" => WrapMode::Mirror,
+                    "clamp" => WrapMode::Clamp,
+                    "border" => WrapMode::Border(node.vector("border", Some(Vec3::zero()))),
+                    other => node.fail(&format!("unknown wrap mode {}", other)),
+                };
+                let Ok(mut texture) = textures.load(&path, color_space) else {
+                    // reports it and falls back
+                    return textures.get(&path, color_space);
+                };
+                texture.filter = filter;
+                texture.wrap = wrap;
+                texture.instancing()
+            }
+            "checker" => CheckerTexture::new(node.number("scale", None), input("even"), input("odd")).instancing(),
+            "marble" => {
+                let mut marble = NoiseTexture::new(node.number("scale", Some(1.0)))
+                    .axis(node.vector("axis", Some(Vec3::new(0.0, 0.0, 1.0))))
+                    .turbulence(node.number("turbulence", Some(10.0)), node.number("depth", Some(7.0)) as usize);
+                if node.get("seed").is_some() {
+                    marble = marble.seed(node.number("seed", None) as u64);
+                }
+                marble.instancing()
+            }
+            "fbm" => {
+                let kind = match node.get("kind").unwrap_or("fbm") {
+                    "fbm" => NoiseKind::Fbm,
+                    "turbulence" => NoiseKind::Turbulence,
+                    "ridged" => NoiseKind::Ridged,
+                    other => node.fail(&format!("unknown noise kind {}", other)),
+                };
+                let defaults = Fractal::default();
+                let fractal = Fractal::new(
+                    node.number("octaves", Some(defaults.octaves as f64)) as usize,
+                    node.number("lacunarity", Some(defaults.lacunarity)),
+                    node.number("gain", Some(defaults.gain)),
+                );
+                let mut fbm = FbmTexture::new(node.number("scale", Some(1.0))).kind(kind).fractal(fractal);
+                if node.get("time").is_some() {
+                    fbm = fbm.time(node.number("time", None));
+                }
+                if node.get("seed").is_some() {
+                    fbm = fbm.seed(node.number("seed", None) as u64);
+                }
+                fbm.instancing()
+            }
+            "worley" => {
+                let feature = match node.get("feature").unwrap_or("f1") {
+                    "f1" => WorleyFeature::F1,
+                    "f2" => WorleyFeature::F2,
+                    "f2-f1" => WorleyFeature::F2MinusF1,
+                    other => node.fail(&format!("unknown worley feature {}", other)),
+                };
+                let mut worley = WorleyTexture::new(node.number("scale", Some(1.0))).feature(feature);
+                if node.get("seed").is_some() {
+                    worley = worley.seed(node.number("seed", None) as u64);
+                }
+                worley.instancing()
+            }
+            "wood" => {
+                let mut wood = WoodTexture::new(node.vector("center", None), node.vector("axis", None), node.number("rings", None))
+                    .turbulence(node.number("turbulence", Some(0.3)), node.number("noise_scale", Some(1.5)));
+                if node.get("seed").is_some() {
+                    wood = wood.seed(node.number("seed", None) as u64);
+                }
+                wood.instancing()
+            }
+            "gradient" => GradientTexture::new_linear(node.vector("from", None), node.vector("to", None), ColorRamp::grayscale()).instancing(),
+            "radial" => GradientTexture::new_radial(node.vector("center", None), node.number("radius", None), ColorRamp::grayscale()).instancing(),
+            "mix" => MixTexture::new_with_mask(input("a"), input("b"), input("factor")).instancing(),
+            "add" => MathTexture::new(MathOp::Add, input("a"), input("b")).instancing(),
+            "subtract" => MathTexture::new(MathOp::Subtract, input("a"), input("b")).instancing(),
+            "multiply" => MathTexture::new(MathOp::Multiply, input("a"), input("b")).instancing(),
+            "min" => MathTexture::new(MathOp::Min, input("a"), input("b")).instancing(),
+            "max" => MathTexture::new(MathOp::Max, input("a"), input("b")).instancing(),
+            "invert" => InvertTexture::new(input("input")).instancing(),
+            "hsv" => HsvTexture::new(
+                input("input"),
+                node.number("hue", Some(0.0)),
+                node.number("saturation", Some(1.0)),
+                node.number("value", Some(1.0)),
+            )
+            .instancing(),
+            "ramp" => {
+                let stops: Vec<(f64, Vec3)> = node
+                    .all("stop")
+                    .map(|stop| {
+                        let (position, color) = stop.split_once(':').unwrap_or_else(|| node.fail("a ramp stop is position:r,g,b"));
+                        let position = position.parse().unwrap_or_else(|_| node.fail(&format!("invalid number {}", position)));
+                        (position, node.parse_vector(color))
+                    })
+                    .collect();
+                if stops.is_empty() {
+                    node.fail("a ramp needs a stop");
+                }
+                ColorRampTexture::new(input("input"), ColorRamp::new(stops)).instancing()
+            }
+            "channel" => {
+                let channel = match node.required("which") {
+                    "r" => Channel::Red,
+                    "g" => Channel::Green,
+                    "b" => Channel::Blue,
+                    "luminance" => Channel::Luminance,
+                    other => node.fail(&format!("unknown channel {}", other)),
+                };
+                ChannelTexture::new(input("input"), channel).instancing()
+            }
+            "combine" => CombineTexture::new(input("r"), input("g"), input("b")).instancing(),
+            other => node.fail(&format!("unknown node {}", other)),
+        }
+    }
+
+    // a texture defined above, or a constant
+    fn input(&self, node: &Node, key: &str) -> Arc<dyn TextureTrait + Send + Sync> {
+        let value = node.required(key);
+        match self.textures.get(value) {
+            Some(texture) => texture.clone(),
+            None => SolidColor::new(node.parse_vector(value)).instancing(),
+        }
+    }
+}
+
+// one line of a texture graph: name = kind key=value ...
+struct Node<'a> {
+    line: usize,
+    name: &'a str,
+    kind: &'a str,
+    args: Vec<(&'a str, &'a str)>,
+    // which args were read, see check_unused
+    used: RefCell<Vec<bool>>,
+}
+
+impl<'a> Node<'a> {
+    fn parse(line: usize, text: &'a str) -> Node<'a> {
+        let fail = |message: &str| -> ! { panic!("texture graph line {}: {}", line, message) };
+        let (name, rest) = text.split_once('=').unwrap_or_else(|| fail("expected name = node"));
+        let name = name.trim();
+        if name.is_empty() || name.contains(char::is_whitespace) {
+            fail("a texture name is one word");
+        }
+        let mut tokens = rest.split_whitespace();
+        let kind = tokens.next().unwrap_or_else(|| fail("missing node"));
+        let args: Vec<(&str, &str)> = tokens
+            .map(|token| token.split_once('=').unwrap_or_else(|| fail(&format!("expected key=value, found {}", token))))
+            .collect();
+        let used = RefCell::new(vec![false; args.len()]);
+        Node { line, name, kind, args, used }
+    }
+
+    fn fail(&self, message: &str) -> ! {
+        panic!("texture graph line {}: {}", self.line, message)
+    }
+
+    fn get(&self, key: &str) -> Option<&'a str> {
+        self.all(key).next()
+    }
+
+    // every value of a key that may repeat
+    fn all<'b>(&'b self, key: &'b str) -> impl Iterator<Item = &'a str> + 'b {
+        self.args.iter().enumerate().filter(move |(_, arg)| arg.0 == key).map(move |(i, arg)| {
+            self.used.borrow_mut()[i] = true;
+            arg.1
+        })
+    }
+
+    // once the node is built, a key it did not read is most likely misspelled
+    fn check_unused(&self) {
+        let used = self.used.borrow();
+        if let Some((key, _)) = self.args.iter().zip(used.iter()).find(|(_, &used)| !used).map(|(arg, _)| arg) {
+            self.fail(&format!("{} does not take {}", self.kind, key));
+        }
+    }
+
+    fn required(&self, key: &str) -> &'a str {
+        self.get(key).unwrap_or_else(|| self.fail(&format!("{} needs {}", self.kind, key)))
+    }
+
+    fn number(&self, key: &str, default: Option<f64>) -> f64 {
+        match (self.get(key), default) {
+            (Some(value), _) => value.parse().unwrap_or_else(|_| self.fail(&format!("invalid number {}", value))),
+            (None, Some(default)) => default,
+            (None, None) => self.fail(&format!("{} needs {}", self.kind, key)),
+        }
+    }
+
+    fn vector(&self, key: &str, default: Option<Vec3>) -> Vec3 {
+        match (self.get(key), default) {
+            (Some(value), _) => self.parse_vector(value),
+            (None, Some(default)) => default,
+            (None, None) => self.fail(&format!("{} needs {}", self.kind, key)),
+        }
+    }
+
+    // "x,y,z", or a single number for all three
+    fn parse_vector(&self, value: &str) -> Vec3 {
+        let numbers: Vec<f64> = value
+            .split(',')
+            .map(|n| n.parse().unwrap_or_else(|_| self.fail(&format!("unknown texture or invalid value {}", value))))
+            .collect();
+        match numbers[..] {
+            [n] => Vec3::ones() * n,
+            [x, y, z] => Vec3::new(x, y, z),
+            _ => self.fail(&format!("expected one or three numbers, found {}", value)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(text: &str) -> TextureGraph {
+        TextureGraph::parse(text, Path::new(""), &TextureManager::default())
+    }
+
+    fn assert_color(graph: &TextureGraph, name: &str, expected: Vec3) {
+        let color = graph.get(name).expect("texture not defined").value(0.3, 0.7, Vec3::new(0.1, 0.2, 0.3));
+        assert!((color - expected).length() < 1e-9, "{}: {:?} instead of {:?}", name, color, expected);
+    }
+
+    #[test]
+    fn constants_and_references() {
+        let graph = parse(
+            "base = color value=0.2,0.4,0.6
+             gray = invert input=0.25
+             solid = invert input=1,0,0.5
+             mixed = mix a=base b=1 factor=0.5",
+        );
+        assert_color(&graph, "gray", Vec3::ones() * 0.75);
+        assert_color(&graph, "solid", Vec3::new(0.0, 1.0, 0.5));
+        assert_color(&graph, "mixed", Vec3::new(0.6, 0.7, 0.8));
+    }
+
+    #[test]
+    fn a_texture_named_like_a_number_wins_over_the_constant() {
+        let graph = parse(
+            "1 = color value=0,0.5,0
+             x = invert input=1",
+        );
+        assert_color(&graph, "x", Vec3::new(1.0, 0.5, 1.0));
+    }
+
+    #[test]
+    fn repeated_stops_make_one_ramp() {
+        let graph = parse("r = ramp input=0.25 stop=1:0,0,1 stop=0:1,0,0 stop=0.5:0,1,0");
+        assert_color(&graph, "r", Vec3::new(0.5, 0.5, 0.0));
+    }
+
+    #[test]
+    fn comments_and_blank_lines_are_skipped() {
+        let graph = parse(
+            "# a comment
+
+             a = color value=0.5 # after a node",
+        );
+        assert_color(&graph, "a", Vec3::ones() * 0.5);
+        assert!(graph.get("#").is_none());
+    }
+
+    #[test]
+    #[should_panic(expected = "texture graph line 4: unknown node bogus")]
+    fn unknown_node_names_its_line() {
+        parse(
+            "# header
+
+             a = color value=1
+             b = bogus x=1",
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "texture graph line 2: color does not take colour")]
+    fn unknown_key_names_its_line() {
+        parse(
+            "a = color value=1
+             b = color value=1 colour=0.5",
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "texture graph line 1: checker needs odd")]
+    fn missing_key_names_its_line() {
+        parse("a = checker scale=2 even=1");
+    }
+
+    #[test]
+    #[should_panic(expected = "texture graph line 1: unknown texture or invalid value b")]
+    fn texture_used_before_it_is_defined() {
+        parse(
+            "a = invert input=b
+             b = color value=1",
+        );
+    }
+}
@@ -0,0 +1,407 @@
+use crate::color::*;
+use crate::texture::*;
+use crate::vec3::*;
+
+use image::{DynamicImage, ImageBuffer, ImageResult, Rgb, Rgb32FImage, RgbImage};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+
+// side of the square tiles the mipmaps are decoded in
+pub const TILE_SIZE: usize = 64;
+pub const DEFAULT_TEXTURE_BUDGET: usize = 256 * 1024 * 1024;
+// what a texture whose file cannot be read shows
+pub const FALLBACK_COLOR: Vec3 = Vec3 { x: 1.0, y: 0.0, z: 1.0 };
+
+// loads every image file once and shares it between the textures made from it; images are only
+// decoded when first sampled, and their mipmaps are expanded to linear float tiles on demand.
+// memory_budget bounds the decoded images and the tiles together: the least recently used ones
+// are dropped, and an image that was dropped is read from its file again when a tile needs it,
+// so the budget should hold at least the largest image
+// usage: let textures = TextureManager::new(64 << 20); let earth = textures.get(&path, ColorSpace::Srgb);
+pub struct TextureManager {
+    cache: Arc<TileCache>,
+    images: Mutex<HashMap<(PathBuf, ColorSpace), Arc<ManagedImage>>>,
+}
+
+impl TextureManager {
+    pub fn new(memory_budget: usize) -> TextureManager {
+        TextureManager {
+            cache: Arc::new(TileCache::new(memory_budget)),
+            images: Mutex::new(HashMap::new()),
+        }
+    }
+
+    // fails if the file is missing or is not an image, only its header is read here
+    pub fn load(&self, path: &Path, color_space: ColorSpace) -> ImageResult<ManagedTexture> {
+        let (width, height) = image::image_dimensions(path)?;
+        let key = (path.canonicalize().unwrap_or_else(|_| path.to_path_buf()), color_space);
+        let image = self
+            .images
+            .lock()
+            .unwrap()
+            .entry(key)
+            .or_insert_with(|| {
+                let source = SourceFile::new(path, color_space);
+                Arc::new(ManagedImage {
+                    levels: tiled_levels(width as usize, height as usize, source, &self.cache),
+                })
+            })
+            .clone();
+        Ok(ManagedTexture {
+            image,
+            filter: TextureFilter::Trilinear,
+            wrap: WrapMode::Clamp,
+        })
+    }
+
+    // like load, but a file that cannot be read is reported and shows FALLBACK_COLOR
+    pub fn get(&self, path: &Path, color_space: ColorSpace) -> Arc<dyn TextureTrait + Send + Sync> {
+        match self.load(path, color_space) {
+            Ok(texture) => texture.instancing(),
+            Err(err) => {
+                eprintln!("texture {}: {}, using the fallback color", path.display(), err);
+                SolidColor::new(FALLBACK_COLOR).instancing()
+            }
+        }
+    }
+
+    // bytes of decoded images and tiles held right now
+    pub fn resident_bytes(&self) -> usize {
+        self.cache.used.load(Ordering::Relaxed)
+    }
+}
+
+impl Default for TextureManager {
+    fn default() -> Self {
+        Self::new(DEFAULT_TEXTURE_BUDGET)
+    }
+}
+
+// a texture of a TextureManager, the filter and wrap mode are its own
+pub struct ManagedTexture {
+    image: Arc<ManagedImage>,
+    pub filter: TextureFilter,
+    pub wrap: WrapMode,
+}
+
+impl ManagedTexture {
+    pub fn set_wrap(&mut self, wrap: WrapMode) {
+        self.wrap = wrap;
+    }
+}
+
+impl TextureTrait for ManagedTexture {
+    fn value(&self, u: f64, v: f64, p: Vec3) -> Vec3 {
+        self.sample(&TexCoord::new(u, v, p))
+    }
+
+    fn sample(&self, tc: &TexCoord) -> Vec3 {
+        sample_mipmap(&self.image.levels, self.filter, self.wrap, tc)
+    }
+
+    fn instancing(self) -> Arc<dyn TextureTrait + Send + Sync> {
+        Arc::new(self)
+    }
+}
+
+// the mipmap of one file, every level is decoded a tile at a time
+struct ManagedImage {
+    levels: Vec<Arc<TiledLevel>>,
+}
+
+// an image file and its decoded pixels while they are resident
+struct SourceFile {
+    path: PathBuf,
+    color_space: ColorSpace,
+    slot: Arc<Slot<SourceImage>>,
+    // the file is only reported once when it cannot be decoded
+    failed: AtomicBool,
+}
+
+impl SourceFile {
+    fn new(path: &Path, color_space: ColorSpace) -> SourceFile {
+        SourceFile {
+            path: path.to_path_buf(),
+            color_space,
+            slot: Arc::new(Slot::default()),
+            failed: AtomicBool::new(false),
+        }
+    }
+
+    // decodes the file if it is not resident, None if it cannot be read (anymore)
+    fn image(&self, cache: &TileCache) -> Option<Arc<SourceImage>> {
+        if let Some(image) = self.slot.value.read().unwrap().as_ref() {
+            cache.touch(&self.slot);
+            return Some(image.clone());
+        }
+        match open_image(&self.path) {
+            Ok(img) => {
+                let source = SourceImage::new(img, self.color_space);
+                let bytes = source.bytes();
+                Some(cache.insert(&self.slot, source, bytes))
+            }
+            Err(err) => {
+                if !self.failed.swap(true, Ordering::Relaxed) {
+                    eprintln!("texture {}: {}, using the fallback color", self.path.display(), err);
+                }
+                None
+            }
+        }
+    }
+}
+
+// the full resolution image in the precision of its file, decoded to linear a tile at a time
+enum SourceImage {
+    // with the decoded value of every byte
+    Rgb8(RgbImage, Box<[f32; 256]>),
+    Rgb16(ImageBuffer<Rgb<u16>, Vec<u16>>, ColorSpace),
+    // always linear
+    Rgb32F(Rgb32FImage),
+}
+
+impl SourceImage {
+    fn new(img: DynamicImage, color_space: ColorSpace) -> SourceImage {
+        match img {
+            DynamicImage::ImageRgb32F(_) | DynamicImage::ImageRgba32F(_) => SourceImage::Rgb32F(img.to_rgb32f()),
+            DynamicImage::ImageLuma16(_) | DynamicImage::ImageLumaA16(_) | DynamicImage::ImageRgb16(_) | DynamicImage::ImageRgba16(_) => {
+                SourceImage::Rgb16(img.to_rgb16(), color_space)
+            }
+            _ => {
+                let mut table = Box::new([0.0; 256]);
+                for (i, value) in table.iter_mut().enumerate() {
+                    *value = color_space.decode(i as f32 / 255.0);
+                }
+                SourceImage::Rgb8(img.to_rgb8(), table)
+            }
+        }
+    }
+
+    fn bytes(&self) -> usize {
+        match self {
+            SourceImage::Rgb8(img, table) => std::mem::size_of_val(img.as_raw().as_slice()) + std::mem::size_of_val(table.as_ref()),
+            SourceImage::Rgb16(img, _) => std::mem::size_of_val(img.as_raw().as_slice()),
+            SourceImage::Rgb32F(img) => std::mem::size_of_val(img.as_raw().as_slice()),
+        }
+    }
+
+    fn texel(&self, x: usize, y: usize) -> [f32; 3] {
+        let (x, y) = (x as u32, y as u32);
+        match self {
+            SourceImage::Rgb8(img, table) => img.get_pixel(x, y).0.map(|c| table[c as usize]),
+            SourceImage::Rgb16(img, color_space) => img.get_pixel(x, y).0.map(|c| color_space.decode(c as f32 / 65535.0)),
+            SourceImage::Rgb32F(img) => img.get_pixel(x, y).0,
+        }
+    }
+}
+
+fn tiled_levels(mut width: usize, mut height: usize, source: SourceFile, cache: &Arc<TileCache>) -> Vec<Arc<TiledLevel>> {
+    if width == 0 || height == 0 {
+        return Vec::new();
+    }
+    let mut levels = vec![Arc::new(TiledLevel::new(width, height, LevelSource::Image(source), cache.clone()))];
+    while width > 1 || height > 1 {
+        (width, height) = (width.div_ceil(2), height.div_ceil(2));
+        let parent = levels[levels.len() - 1].clone();
+        levels.push(Arc::new(TiledLevel::new(width, height, LevelSource::Parent(parent), cache.clone())));
+    }
+    levels
+}
+
+enum LevelSource {
+    // level 0 decodes its tiles from the image
+    Image(SourceFile),
+    // the others average the level above
+    Parent(Arc<TiledLevel>),
+}
+
+// something the cache may drop, a tile or a decoded image
+struct Slot<T> {
+    value: RwLock<Option<Arc<T>>>,
+    bytes: AtomicUsize,
+    last_used: AtomicU64,
+}
+
+impl<T> Default for Slot<T> {
+    fn default() -> Self {
+        Slot {
+            value: RwLock::new(None),
+            bytes: AtomicUsize::new(0),
+            last_used: AtomicU64::new(0),
+        }
+    }
+}
+
+type TileSlot = Slot<Vec<[f32; 3]>>;
+
+// the slots of any type, as the cache sees them
+trait Resident: Send + Sync {
+    fn last_used(&self) -> u64;
+    // drops the value, returns the bytes freed
+    fn evict(&self) -> usize;
+}
+
+impl<T: Send + Sync> Resident for Slot<T> {
+    fn last_used(&self) -> u64 {
+        self.last_used.load(Ordering::Relaxed)
+    }
+
+    fn evict(&self) -> usize {
+        match self.value.write().unwrap().take() {
+            Some(_) => self.bytes.load(Ordering::Relaxed),
+            None => 0,
+        }
+    }
+}
+
+struct TiledLevel {
+    width: usize,
+    height: usize,
+    tiles_x: usize,
+    slots: Vec<Arc<TileSlot>>,
+    source: LevelSource,
+    cache: Arc<TileCache>,
+}
+
+impl TiledLevel {
+    fn new(width: usize, height: usize, source: LevelSource, cache: Arc<TileCache>) -> TiledLevel {
+        let tiles_x = width.div_ceil(TILE_SIZE);
+        let tiles_y = height.div_ceil(TILE_SIZE);
+        TiledLevel {
+            width,
+            height,
+            tiles_x,
+            slots: (0..tiles_x * tiles_y).map(|_| Arc::new(TileSlot::default())).collect(),
+            source,
+            cache,
+        }
+    }
+
+    // runs lookup on the tile, decoding it first if it is not resident
+    fn with_tile<R>(&self, tx: usize, ty: usize, lookup: impl FnOnce(&[[f32; 3]]) -> R) -> R {
+        let slot = &self.slots[ty * self.tiles_x + tx];
+        if let Some(texels) = slot.value.read().unwrap().as_ref() {
+            self.cache.touch(slot);
+            return lookup(texels);
+        }
+        let texels = self.decode_tile(tx, ty);
+        let bytes = std::mem::size_of_val(texels.as_slice());
+        lookup(&self.cache.insert(slot, texels, bytes))
+    }
+
+    fn decode_tile(&self, tx: usize, ty: usize) -> Vec<[f32; 3]> {
+        let (x0, y0) = (tx * TILE_SIZE, ty * TILE_SIZE);
+        let (x1, y1) = ((x0 + TILE_SIZE).min(self.width), (y0 + TILE_SIZE).min(self.height));
+        let mut texels = Vec::with_capacity((x1 - x0) * (y1 - y0));
+        match &self.source {
+            LevelSource::Image(file) => {
+                let Some(image) = file.image(&self.cache) else {
+                    let fallback = [FALLBACK_COLOR.x as f32, FALLBACK_COLOR.y as f32, FALLBACK_COLOR.z as f32];
+                    return vec![fallback; texels.capacity()];
+                };
+                for y in y0..y1 {
+                    texels.extend((x0..x1).map(|x| image.texel(x, y)));
+                }
+            }
+            LevelSource::Parent(parent) => {
+                for y in y0..y1 {
+                    texels.extend((x0..x1).map(|x| {
+                        let average = parent.downsampled_texel(x, y);
+                        [average.x as f32, average.y as f32, average.z as f32]
+                    }));
+                }
+            }
+        }
+        texels
+    }
+}
+
+impl MipLevelTrait for TiledLevel {
+    fn size(&self) -> (usize, usize) {
+        (self.width, self.height)
+    }
+
+    fn fetch(&self, x: usize, y: usize) -> Vec3 {
+        let (tx, ty) = (x / TILE_SIZE, y / TILE_SIZE);
+        let tile_width = TILE_SIZE.min(self.width - tx * TILE_SIZE);
+        let [r, g, b] = self.with_tile(tx, ty, |texels| texels[(y % TILE_SIZE) * tile_width + x % TILE_SIZE]);
+        Vec3::new(r as f64, g as f64, b as f64)
+    }
+
+    // one tile lookup when the four texels share a tile, which is nearly always
+    fn fetch_quad(&self, x: [usize; 2], y: [usize; 2]) -> [Vec3; 4] {
+        let (tx, ty) = (x[0] / TILE_SIZE, y[0] / TILE_SIZE);
+        if x[1] / TILE_SIZE != tx || y[1] / TILE_SIZE != ty {
+            return [self.fetch(x[0], y[0]), self.fetch(x[1], y[0]), self.fetch(x[0], y[1]), self.fetch(x[1], y[1])];
+        }
+        let tile_width = TILE_SIZE.min(self.width - tx * TILE_SIZE);
+        self.with_tile(tx, ty, |texels| {
+            [(x[0], y[0]), (x[1], y[0]), (x[0], y[1]), (x[1], y[1])].map(|(x, y)| {
+                let [r, g, b] = texels[(y % TILE_SIZE) * tile_width + x % TILE_SIZE];
+                Vec3::new(r as f64, g as f64, b as f64)
+            })
+        })
+    }
+}
+
+// the decoded images and tiles of every texture of a manager
+struct TileCache {
+    budget: usize,
+    used: AtomicUsize,
+    // advances with every insertion, slots remember when they were last read
+    clock: AtomicU64,
+    resident: Mutex<Vec<Arc<dyn Resident>>>,
+}
+
+impl TileCache {
+    fn new(budget: usize) -> TileCache {
+        TileCache {
+            budget,
+            used: AtomicUsize::new(0),
+            clock: AtomicU64::new(0),
+            resident: Mutex::new(Vec::new()),
+        }
+    }
+
+    fn touch<T>(&self, slot: &Slot<T>) {
+        slot.last_used.store(self.clock.load(Ordering::Relaxed), Ordering::Relaxed);
+    }
+
+    fn insert<T: Send + Sync + 'static>(&self, slot: &Arc<Slot<T>>, value: T, bytes: usize) -> Arc<T> {
+        let value = Arc::new(value);
+        {
+            let mut resident_value = slot.value.write().unwrap();
+            // another thread decoded it meanwhile
+            if let Some(existing) = resident_value.as_ref() {
+                return existing.clone();
+            }
+            *resident_value = Some(value.clone());
+        }
+        slot.bytes.store(bytes, Ordering::Relaxed);
+        slot.last_used.store(self.clock.fetch_add(1, Ordering::Relaxed) + 1, Ordering::Relaxed);
+
+        let mut resident = self.resident.lock().unwrap();
+        resident.push(slot.clone());
+        if self.used.fetch_add(bytes, Ordering::Relaxed) + bytes > self.budget {
+            self.evict(&mut resident);
+        }
+        value
+    }
+
+    // drops the least recently used slots until a quarter of the budget is free again,
+    // lookups still holding one of them keep it alive until they are done
+    fn evict(&self, resident: &mut Vec<Arc<dyn Resident>>) {
+        resident.sort_by_key(|slot| slot.last_used());
+        let target = self.budget / 4 * 3;
+        let mut evicted = 0;
+        for slot in resident.iter() {
+            if self.used.load(Ordering::Relaxed) <= target {
+                break;
+            }
+            self.used.fetch_sub(slot.evict(), Ordering::Relaxed);
+            evicted += 1;
+        }
+        resident.drain(..evicted);
+    }
+}
@@ -0,0 +1,111 @@
+use crate::aabb::*;
+use crate::disk::*;
+use crate::hitable::*;
+use crate::interval::*;
+use crate::material::*;
+use crate::onb::*;
+use crate::ray::*;
+use crate::utils::*;
+use crate::vec3::*;
+
+use std::f64::consts::PI;
+use std::sync::Arc;
+
+// torus around axis: the tube of radius minor_radius follows a circle of radius major_radius
+// u is the angle around the axis, v the angle around the tube
+pub struct Torus {
+    pub center: Vec3,
+    pub axis: Vec3,
+    pub major_radius: f64,
+    pub minor_radius: f64,
+    pub material: Arc<dyn MaterialTrait + Send + Sync>,
+    onb: Onb,
+    bbox: Aabb,
+}
+
+impl Torus {
+    pub fn new(center: Vec3, axis: Vec3, major_radius: f64, minor_radius: f64, material: Arc<dyn MaterialTrait + Send + Sync>) -> Torus {
+        let onb = Onb::new(axis);
+        // the circle of the tube centers, grown by the tube radius on every side
+        let ring = disk_bbox(center, onb.w, major_radius);
+        let bbox = Aabb::new(
+            ring.x.expand(2.0 * minor_radius),
+            ring.y.expand(2.0 * minor_radius),
+            ring.z.expand(2.0 * minor_radius),
+        );
+        Torus {
+            center,
+            axis: onb.w,
+            major_radius,
+            minor_radius,
+            material,
+            onb,
+            bbox,
+        }
+    }
+}
+
+impl Hittable for Torus {
+    fn hit(&self, r: &Ray, ray_t: Interval) -> Option<HitRecord<'_>> {
+        let direction_length = r.direction().length();
+        let d = self.onb.to_local(r.direction()) / direction_length;
+        let o = self.onb.to_local(r.origin() - self.center);
+        // start from the point of the ray closest to the center, so that the quartic stays well conditioned
+        let shift = -(o * d);
+        let p = o + d * shift;
+
+        // (|p + s d|^2 + R^2 - r^2)^2 = 4 R^2 ((p + s d).x^2 + (p + s d).y^2), d normalized
+        let major2 = self.major_radius * self.major_radius;
+        let n = p * d;
+        let sum = p.squared_length() + major2 - self.minor_radius * self.minor_radius;
+        let coefficients = [
+            sum * sum - 4.0 * major2 * (p.x * p.x + p.y * p.y),
+            4.0 * n * sum - 8.0 * major2 * (p.x * d.x + p.y * d.y),
+            4.0 * n * n + 2.0 * sum - 4.0 * major2 * (d.x * d.x + d.y * d.y),
+            4.0 * n,
+        ];
+        let (roots, count) = solve_quartic(coefficients);
+        let t = roots[..count]
+            .iter()
+            .map(|s| (s + shift) / direction_length)
+            .find(|&t| ray_t.surrounds(t))?;
+
+        let local = o + d * (t * direction_length);
+        let rho = (local.x * local.x + local.y * local.y).sqrt();
+        // the nearest point on the circle of tube centers
+        let tube_center = if rho > 0.0 {
+            Vec3::new(local.x, local.y, 0.0) * (self.major_radius / rho)
+        } else {
+            Vec3::new(self.major_radius, 0.0, 0.0)
+        };
+        let local_normal = unit_vec(local - tube_center);
+
+        let normal = self.onb.local_vec(local_normal);
+        let mut rec = HitRecord::new(
+            t,
+            r.at(t),
+            normal,
+            self.material.as_ref(),
+            true,
+            azimuth_uv(local.x, local.y),
+            (local.z.atan2(rho - self.major_radius) + PI) / (2.0 * PI),
+        );
+        // u turns around the axis, v around the tube
+        rec.dpdu = self.onb.local(-local.y, local.x, 0.0) * (2.0 * PI);
+        rec.dpdv = if rho > 0.0 {
+            self.onb.local(-local.z * local.x / rho, -local.z * local.y / rho, rho - self.major_radius) * (2.0 * PI)
+        } else {
+            Vec3::zero()
+        };
+        rec.set_face_normal(*r, normal);
+        Some(rec)
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.bbox
+    }
+
+    fn instancing(self) -> Arc<dyn Hittable + Send + Sync> {
+        Arc::new(self)
+    }
+}
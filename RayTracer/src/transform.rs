@@ -0,0 +1,228 @@
+use crate::aabb::*;
+use crate::utils::*;
+use crate::vec3::*;
+
+use std::ops::Mul;
+
+// affine transform, keeps the matrix and its inverse
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Transform {
+    pub m: [[f64; 4]; 4],
+    pub inv: [[f64; 4]; 4],
+}
+
+const IDENTITY: [[f64; 4]; 4] = [
+    [1.0, 0.0, 0.0, 0.0],
+    [0.0, 1.0, 0.0, 0.0],
+    [0.0, 0.0, 1.0, 0.0],
+    [0.0, 0.0, 0.0, 1.0],
+];
+
+fn mat_mul(a: &[[f64; 4]; 4], b: &[[f64; 4]; 4]) -> [[f64; 4]; 4] {
+    let mut r = [[0.0; 4]; 4];
+    for (i, row) in r.iter_mut().enumerate() {
+        for (j, value) in row.iter_mut().enumerate() {
+            *value = (0..4).map(|k| a[i][k] * b[k][j]).sum();
+        }
+    }
+    r
+}
+
+impl Transform {
+    pub fn new(m: [[f64; 4]; 4], inv: [[f64; 4]; 4]) -> Self {
+        Self { m, inv }
+    }
+
+    pub fn identity() -> Self {
+        Self::new(IDENTITY, IDENTITY)
+    }
+
+    pub fn translate(offset: Vec3) -> Self {
+        let mut m = IDENTITY;
+        let mut inv = IDENTITY;
+        m[0][3] = offset.x;
+        m[1][3] = offset.y;
+        m[2][3] = offset.z;
+        inv[0][3] = -offset.x;
+        inv[1][3] = -offset.y;
+        inv[2][3] = -offset.z;
+        Self::new(m, inv)
+    }
+
+    pub fn scale(factor: Vec3) -> Self {
+        let mut m = IDENTITY;
+        let mut inv = IDENTITY;
+        m[0][0] = factor.x;
+        m[1][1] = factor.y;
+        m[2][2] = factor.z;
+        inv[0][0] = 1.0 / factor.x;
+        inv[1][1] = 1.0 / factor.y;
+        inv[2][2] = 1.0 / factor.z;
+        Self::new(m, inv)
+    }
+
+    // rotation of angle degrees around the unit axis a and b span, e.g. (1, 2) for the x axis
+    fn rotate_plane(a: usize, b: usize, angle: f64) -> Self {
+        let (sin_theta, cos_theta) = angle.to_radians().sin_cos();
+        let mut m = IDENTITY;
+        m[a][a] = cos_theta;
+        m[a][b] = -sin_theta;
+        m[b][a] = sin_theta;
+        m[b][b] = cos_theta;
+        // rotations are orthogonal, the inverse is the transpose
+        let mut inv = m;
+        inv[a][b] = sin_theta;
+        inv[b][a] = -sin_theta;
+        Self::new(m, inv)
+    }
+
+    pub fn rotate_x(angle: f64) -> Self {
+        Self::rotate_plane(1, 2, angle)
+    }
+
+    // same orientation as RotateY
+    pub fn rotate_y(angle: f64) -> Self {
+        Self::rotate_plane(2, 0, angle)
+    }
+
+    pub fn rotate_z(angle: f64) -> Self {
+        Self::rotate_plane(0, 1, angle)
+    }
+
+    // rotation of angle degrees around axis (any length), counterclockwise looking down the axis
+    pub fn rotate(axis: Vec3, angle: f64) -> Self {
+        let a = unit_vec(axis);
+        let (sin_theta, cos_theta) = angle.to_radians().sin_cos();
+        let k = 1.0 - cos_theta;
+        let mut m = IDENTITY;
+        m[0][0] = cos_theta + a.x * a.x * k;
+        m[0][1] = a.x * a.y * k - a.z * sin_theta;
+        m[0][2] = a.x * a.z * k + a.y * sin_theta;
+        m[1][0] = a.y * a.x * k + a.z * sin_theta;
+        m[1][1] = cos_theta + a.y * a.y * k;
+        m[1][2] = a.y * a.z * k - a.x * sin_theta;
+        m[2][0] = a.z * a.x * k - a.y * sin_theta;
+        m[2][1] = a.z * a.y * k + a.x * sin_theta;
+        m[2][2] = cos_theta + a.z * a.z * k;
+        // orthogonal again, the inverse is the transpose
+        let mut inv = IDENTITY;
+        for (i, row) in inv.iter_mut().enumerate().take(3) {
+            for (j, value) in row.iter_mut().enumerate().take(3) {
+                *value = m[j][i];
+            }
+        }
+        Self::new(m, inv)
+    }
+
+    pub fn inverse(&self) -> Self {
+        Self::new(self.inv, self.m)
+    }
+
+    pub fn apply_point(&self, p: Vec3) -> Vec3 {
+        Self::mul_point(&self.m, p)
+    }
+
+    pub fn apply_vector(&self, v: Vec3) -> Vec3 {
+        Self::mul_vector(&self.m, v)
+    }
+
+    // normals transform with the inverse transpose
+    pub fn apply_normal(&self, n: Vec3) -> Vec3 {
+        let inv = &self.inv;
+        Vec3::new(
+            inv[0][0] * n.x + inv[1][0] * n.y + inv[2][0] * n.z,
+            inv[0][1] * n.x + inv[1][1] * n.y + inv[2][1] * n.z,
+            inv[0][2] * n.x + inv[1][2] * n.y + inv[2][2] * n.z,
+        )
+    }
+
+    pub fn apply_inverse_point(&self, p: Vec3) -> Vec3 {
+        Self::mul_point(&self.inv, p)
+    }
+
+    pub fn apply_inverse_vector(&self, v: Vec3) -> Vec3 {
+        Self::mul_vector(&self.inv, v)
+    }
+
+    // bounds of the eight transformed corners
+    pub fn apply_bbox(&self, bbox: &Aabb) -> Aabb {
+        let mut min = Vec3::new(f64::INFINITY, f64::INFINITY, f64::INFINITY);
+        let mut max = Vec3::new(f64::NEG_INFINITY, f64::NEG_INFINITY, f64::NEG_INFINITY);
+        for i in 0..2 {
+            for j in 0..2 {
+                for k in 0..2 {
+                    let corner = Vec3::new(
+                        if i == 1 { bbox.x.tmax } else { bbox.x.tmin },
+                        if j == 1 { bbox.y.tmax } else { bbox.y.tmin },
+                        if k == 1 { bbox.z.tmax } else { bbox.z.tmin },
+                    );
+                    let p = self.apply_point(corner);
+                    min = Vec3::merge_min(&min, &p);
+                    max = Vec3::merge_max(&max, &p);
+                }
+            }
+        }
+        Aabb::new_from_point(min, max)
+    }
+
+    fn mul_point(m: &[[f64; 4]; 4], p: Vec3) -> Vec3 {
+        Vec3::new(
+            m[0][0] * p.x + m[0][1] * p.y + m[0][2] * p.z + m[0][3],
+            m[1][0] * p.x + m[1][1] * p.y + m[1][2] * p.z + m[1][3],
+            m[2][0] * p.x + m[2][1] * p.y + m[2][2] * p.z + m[2][3],
+        )
+    }
+
+    fn mul_vector(m: &[[f64; 4]; 4], v: Vec3) -> Vec3 {
+        Vec3::new(
+            m[0][0] * v.x + m[0][1] * v.y + m[0][2] * v.z,
+            m[1][0] * v.x + m[1][1] * v.y + m[1][2] * v.z,
+            m[2][0] * v.x + m[2][1] * v.y + m[2][2] * v.z,
+        )
+    }
+}
+
+impl Mul for Transform {
+    // a * b applies b first, then a
+    type Output = Transform;
+
+    fn mul(self, other: Transform) -> Transform {
+        Transform::new(mat_mul(&self.m, &other.m), mat_mul(&other.inv, &self.inv))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_same(a: &Transform, b: &Transform) {
+        for i in 0..4 {
+            for j in 0..4 {
+                assert!((a.m[i][j] - b.m[i][j]).abs() < 1e-12, "{:?} instead of {:?}", a.m, b.m);
+                assert!((a.inv[i][j] - b.inv[i][j]).abs() < 1e-12, "{:?} instead of {:?}", a.inv, b.inv);
+            }
+        }
+    }
+
+    #[test]
+    fn rotate_matches_the_axis_rotations() {
+        for angle in [0.0, 30.0, 90.0, 200.0, -45.0] {
+            assert_same(&Transform::rotate(Vec3::new(2.0, 0.0, 0.0), angle), &Transform::rotate_x(angle));
+            assert_same(&Transform::rotate(Vec3::new(0.0, 1.0, 0.0), angle), &Transform::rotate_y(angle));
+            assert_same(&Transform::rotate(Vec3::new(0.0, 0.0, 0.5), angle), &Transform::rotate_z(angle));
+        }
+    }
+
+    #[test]
+    fn rotate_keeps_the_axis_and_inverts() {
+        let axis = Vec3::new(1.0, 1.0, 1.0);
+        let rotation = Transform::rotate(axis, 120.0);
+        // a third of a turn around the diagonal cycles the coordinate axes
+        let p = rotation.apply_point(Vec3::new(1.0, 0.0, 0.0));
+        assert!((p - Vec3::new(0.0, 1.0, 0.0)).length() < 1e-12, "{:?}", p);
+        let q = rotation.apply_point(axis);
+        assert!((q - axis).length() < 1e-12, "{:?}", q);
+        let back = rotation.apply_inverse_point(p);
+        assert!((back - Vec3::new(1.0, 0.0, 0.0)).length() < 1e-12, "{:?}", back);
+    }
+}
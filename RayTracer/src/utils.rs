@@ -68,12 +68,18 @@ pub fn refract(v: Vec3, n: Vec3, ratio: f64) -> Vec3 {
         //全反射
         reflect(v, n)
     } else {
-        let perp = (v + n * cos_theta) * ratio;
-        let para = Vec3::zero() - n * f64::sqrt(fabs(1.0 - perp.squared_length()));
-        perp + para
+        snell_refract(v, n, ratio)
     }
 }
 
+// the refracted direction alone, without the random choice of refract
+pub fn snell_refract(v: Vec3, n: Vec3, ratio: f64) -> Vec3 {
+    let cos_theta = (Vec3::zero() - v) * n;
+    let perp = (v + n * cos_theta) * ratio;
+    let para = Vec3::zero() - n * f64::sqrt(fabs(1.0 - perp.squared_length()));
+    perp + para
+}
+
 //反射模块，简单，v为入射光线，n为法线
 pub fn reflect(v: Vec3, n: Vec3) -> Vec3 {
     //v,n为单位向量
@@ -213,4 +219,266 @@ pub fn color(x: f64, y: f64, z: f64) -> [u8; 3] {
         (255.0 * cut(y)) as u8,
         (255.0 * cut(z)) as u8,
     ]
-}
\ No newline at end of file
+}
+
+// 实系数多项式求根，返回 (根, 根的个数)，根按从小到大排列
+// a t^2 + b t + c = 0
+pub fn solve_quadratic(a: f64, b: f64, c: f64) -> ([f64; 2], usize) {
+    if fabs(a) < 1e-12 {
+        if fabs(b) < 1e-12 {
+            return ([0.0; 2], 0);
+        }
+        return ([-c / b, 0.0], 1);
+    }
+    let delta = b * b - 4.0 * a * c;
+    if delta < 0.0 {
+        return ([0.0; 2], 0);
+    }
+    // avoids the cancellation of -b + sqrt(delta)
+    let q = -0.5 * (b + b.signum() * delta.sqrt());
+    let (t0, t1) = if q == 0.0 { (0.0, 0.0) } else { (q / a, c / q) };
+    ([fmin(t0, t1), fmax(t0, t1)], 2)
+}
+
+// a t^3 + b t^2 + c t + d = 0, Cardano for one real root, the trigonometric form for three
+// when a is small next to the other coefficients only the largest root keeps its digits, so that
+// one is polished on the original cubic and the others come from the quadratic left once it is divided out
+pub fn solve_cubic(a: f64, b: f64, c: f64, d: f64) -> ([f64; 3], usize) {
+    if fabs(a) < 1e-12 {
+        let (roots, count) = solve_quadratic(b, c, d);
+        return ([roots[0], roots[1], 0.0], count);
+    }
+    let (roots, count) = solve_normalized_cubic(b / a, c / a, d / a);
+    let f = |t: f64| ((a * t + b) * t + c) * t + d;
+    let mut r = roots[..count].iter().copied().fold(0.0, |m: f64, t| if t.abs() > m.abs() { t } else { m });
+    for _ in 0..4 {
+        let next = r - f(r) / ((3.0 * a * r + 2.0 * b) * r + c);
+        if !next.is_finite() || fabs(f(next)) >= fabs(f(r)) {
+            break;
+        }
+        r = next;
+    }
+
+    // (t - r) (e2 t^2 + e1 t + e0), from the top for a small root and from the bottom for a large one
+    let (e2, e1, e0) = if fabs(r) <= 1.0 {
+        let e1 = b + r * a;
+        (a, e1, c + r * e1)
+    } else {
+        let e0 = -d / r;
+        let e1 = (e0 - c) / r;
+        ((e1 - b) / r, e1, e0)
+    };
+    let (rest, rest_count) = solve_quadratic(1.0, e1 / e2, e0 / e2);
+    let mut roots = [r, rest[0], rest[1]];
+    roots[..1 + rest_count].sort_by(|x, y| x.total_cmp(y));
+    (roots, 1 + rest_count)
+}
+
+// t^3 + b t^2 + c t + d = 0
+fn solve_normalized_cubic(b: f64, c: f64, d: f64) -> ([f64; 3], usize) {
+    // t = x - b / 3 gives x^3 + p x + q = 0
+    let p = (3.0 * c - b * b) / 3.0;
+    let q = (2.0 * b * b * b - 9.0 * b * c + 27.0 * d) / 27.0;
+    let shift = -b / 3.0;
+    let delta = q * q / 4.0 + p * p * p / 27.0;
+
+    if delta > 0.0 {
+        let sqrt_delta = delta.sqrt();
+        let x = (-q / 2.0 + sqrt_delta).cbrt() + (-q / 2.0 - sqrt_delta).cbrt();
+        ([x + shift, 0.0, 0.0], 1)
+    } else if p == 0.0 {
+        ([shift, 0.0, 0.0], 1)
+    } else {
+        let r = 2.0 * (-p / 3.0).sqrt();
+        let phi = ((3.0 * q / (2.0 * p)) * (-3.0 / p).sqrt()).clamp(-1.0, 1.0).acos() / 3.0;
+        let mut roots = [0.0; 3];
+        for (k, root) in roots.iter_mut().enumerate() {
+            *root = r * (phi - 2.0 * std::f64::consts::PI * k as f64 / 3.0).cos() + shift;
+        }
+        roots.sort_by(|x, y| x.total_cmp(y));
+        (roots, 3)
+    }
+}
+
+// t^4 + c[3] t^3 + c[2] t^2 + c[1] t + c[0] = 0
+// the critical points (roots of the derivative) split the real line into monotonic pieces,
+// each sign change is then refined by Newton steps kept inside the bracket
+pub fn solve_quartic(c: [f64; 4]) -> ([f64; 4], usize) {
+    let f = |t: f64| (((t + c[3]) * t + c[2]) * t + c[1]) * t + c[0];
+    let df = |t: f64| ((4.0 * t + 3.0 * c[3]) * t + 2.0 * c[2]) * t + c[1];
+
+    // Cauchy bound on the roots
+    let bound = 1.0 + c.iter().fold(0.0, |m: f64, x| m.max(x.abs()));
+    let (critical, critical_count) = solve_cubic(4.0, 3.0 * c[3], 2.0 * c[2], c[1]);
+    let mut ends = [0.0; 5];
+    ends[0] = -bound;
+    let mut end_count = 1;
+    for &t in &critical[..critical_count] {
+        if t > ends[end_count - 1] && t < bound {
+            ends[end_count] = t;
+            end_count += 1;
+        }
+    }
+    ends[end_count] = bound;
+    end_count += 1;
+
+    // a double root (a ray tangent to the surface) does not change sign, it is a critical point
+    // where f is lost in the rounding error of its evaluation
+    let mut values = [0.0; 5];
+    for (k, value) in values[..end_count].iter_mut().enumerate() {
+        let t = fabs(ends[k]);
+        *value = f(ends[k]);
+        let error = 16.0 * f64::EPSILON * ((((t + fabs(c[3])) * t + fabs(c[2])) * t + fabs(c[1])) * t + fabs(c[0]));
+        if k > 0 && k < end_count - 1 && fabs(*value) <= error {
+            *value = 0.0;
+        }
+    }
+
+    let mut roots = [0.0; 4];
+    let mut count = 0;
+    for k in 0..end_count - 1 {
+        let (mut lo, mut hi) = (ends[k], ends[k + 1]);
+        let (f_lo, f_hi) = (values[k], values[k + 1]);
+        if f_lo == 0.0 {
+            for _ in 0..2 {
+                if count < 4 {
+                    roots[count] = lo;
+                    count += 1;
+                }
+            }
+            continue;
+        }
+        // a zero at hi is taken by the next piece
+        if f_hi == 0.0 || f_lo * f_hi > 0.0 {
+            continue;
+        }
+        let increasing = f_lo < 0.0;
+        let mut t = 0.5 * (lo + hi);
+        for _ in 0..100 {
+            let value = f(t);
+            if value == 0.0 {
+                break;
+            }
+            if (value < 0.0) == increasing {
+                lo = t;
+            } else {
+                hi = t;
+            }
+            let slope = df(t);
+            let newton = t - value / slope;
+            // bisect whenever Newton leaves the bracket
+            let next = if slope != 0.0 && newton > lo && newton < hi { newton } else { 0.5 * (lo + hi) };
+            let converged = fabs(next - t) < 1e-12 * (1.0 + t.abs()) || hi - lo < 1e-12 * (1.0 + t.abs());
+            t = next;
+            if converged {
+                break;
+            }
+        }
+        if count < 4 {
+            roots[count] = t;
+            count += 1;
+        }
+    }
+    (roots, count)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_roots(found: &[f64], expected: &[f64], tolerance: f64) {
+        assert_eq!(found.len(), expected.len(), "{:?} instead of {:?}", found, expected);
+        for (x, y) in found.iter().zip(expected) {
+            assert!(fabs(x - y) <= tolerance * (1.0 + y.abs()), "{:?} instead of {:?}", found, expected);
+        }
+    }
+
+    #[test]
+    fn quadratic_known_roots() {
+        let (roots, count) = solve_quadratic(2.0, -8.0, 6.0);
+        assert_roots(&roots[..count], &[1.0, 3.0], 1e-15);
+        let (roots, count) = solve_quadratic(1.0, 0.0, -4.0);
+        assert_roots(&roots[..count], &[-2.0, 2.0], 1e-15);
+        let (_, count) = solve_quadratic(1.0, 0.0, 1.0);
+        assert_eq!(count, 0);
+    }
+
+    #[test]
+    fn quadratic_double_root() {
+        let (roots, count) = solve_quadratic(1.0, -4.0, 4.0);
+        assert_roots(&roots[..count], &[2.0, 2.0], 1e-15);
+        let (roots, count) = solve_quadratic(3.0, 0.0, 0.0);
+        assert_roots(&roots[..count], &[0.0, 0.0], 0.0);
+    }
+
+    #[test]
+    fn quadratic_nearly_linear() {
+        // the small root keeps its digits, -b + sqrt(delta) would cancel
+        let (roots, count) = solve_quadratic(1e-10, 1.0, -1.0);
+        assert_roots(&roots[..count], &[-1e10 - 1.0, 1.0 - 1e-10], 1e-15);
+        let (roots, count) = solve_quadratic(1e-13, 2.0, -1.0);
+        assert_roots(&roots[..count], &[0.5], 1e-15);
+    }
+
+    #[test]
+    fn cubic_known_roots() {
+        let (roots, count) = solve_cubic(1.0, -6.0, 11.0, -6.0);
+        assert_roots(&roots[..count], &[1.0, 2.0, 3.0], 1e-14);
+        // (t - 1) (t^2 + 1)
+        let (roots, count) = solve_cubic(2.0, -2.0, 2.0, -2.0);
+        assert_roots(&roots[..count], &[1.0], 1e-14);
+    }
+
+    #[test]
+    fn cubic_multiple_roots() {
+        // (t - 1)^2 (t - 2)
+        let (roots, count) = solve_cubic(1.0, -4.0, 5.0, -2.0);
+        assert_roots(&roots[..count], &[1.0, 1.0, 2.0], 1e-7);
+        let (roots, count) = solve_cubic(1.0, -3.0, 3.0, -1.0);
+        assert_roots(&roots[..count], &[1.0, 1.0, 1.0], 1e-5);
+    }
+
+    #[test]
+    fn cubic_nearly_quadratic() {
+        // 1e-9 (t + 1e9) (t - 1) (t - 2): the two small roots stay close to those of t^2 - 3t + 2
+        let (roots, count) = solve_cubic(1e-9, 1.0 - 3e-9, -3.0 + 2e-9, 2.0);
+        assert_roots(&roots[..count], &[-1e9, 1.0, 2.0], 1e-12);
+        let (roots, count) = solve_cubic(1e-11, 1.0, -3.0, 2.0);
+        assert_eq!(count, 3);
+        assert_roots(&roots[1..count], &[1.0, 2.0], 1e-9);
+    }
+
+    // coefficients of (t - r0) (t - r1) (t - r2) (t - r3) for solve_quartic
+    fn quartic(r: [f64; 4]) -> [f64; 4] {
+        let mut c = [1.0, 0.0, 0.0, 0.0, 0.0];
+        for (n, root) in r.iter().enumerate() {
+            for k in (1..=n + 1).rev() {
+                c[k] = c[k - 1] - root * c[k];
+            }
+            c[0] *= -root;
+        }
+        [c[0], c[1], c[2], c[3]]
+    }
+
+    #[test]
+    fn quartic_known_roots() {
+        let (roots, count) = solve_quartic(quartic([1.0, 2.0, 3.0, 4.0]));
+        assert_roots(&roots[..count], &[1.0, 2.0, 3.0, 4.0], 1e-12);
+        let (roots, count) = solve_quartic(quartic([-5.0, -0.5, 0.25, 10.0]));
+        assert_roots(&roots[..count], &[-5.0, -0.5, 0.25, 10.0], 1e-12);
+        // (t^2 + 1) (t^2 + 4)
+        let (_, count) = solve_quartic([4.0, 0.0, 5.0, 0.0]);
+        assert_eq!(count, 0);
+    }
+
+    #[test]
+    fn quartic_double_roots() {
+        let (roots, count) = solve_quartic(quartic([-2.0, 1.0, 1.0, 3.0]));
+        assert_roots(&roots[..count], &[-2.0, 1.0, 1.0, 3.0], 1e-7);
+        let (roots, count) = solve_quartic(quartic([1.0, 1.0, 2.0, 2.0]));
+        assert_roots(&roots[..count], &[1.0, 1.0, 2.0, 2.0], 1e-7);
+        // (t - 0.5)^2 (t^2 + 1), the tangent ray of a torus, with no sign change to find
+        let (roots, count) = solve_quartic([0.25, -1.0, 1.25, -1.0]);
+        assert_roots(&roots[..count], &[0.5, 0.5], 1e-7);
+    }
+}